@@ -0,0 +1,723 @@
+// Append only file persistence.
+// https://redis.io/docs/latest/operate/oss_and_stack/management/persistence/
+
+use std::{
+    fmt,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use anyhow::Context;
+use bytes::BytesMut;
+
+use crate::{
+    client::Client,
+    commands, connection,
+    db::Db,
+    message::{self, Message},
+};
+
+/// When the appended commands are flushed to the disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum AppendFsync {
+    Always,
+    EverySec,
+    No,
+}
+
+impl AppendFsync {
+    pub(crate) fn parse(value: &str) -> anyhow::Result<Self> {
+        match value.to_lowercase().as_str() {
+            "always" => Ok(Self::Always),
+            "everysec" => Ok(Self::EverySec),
+            "no" => Ok(Self::No),
+            _ => anyhow::bail!(
+                "ERR CONFIG SET failed (possibly related to argument 'appendfsync') - argument(s) must be one of the following: always, everysec, no"
+            ),
+        }
+    }
+}
+
+impl fmt::Display for AppendFsync {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppendFsync::Always => write!(f, "always"),
+            AppendFsync::EverySec => write!(f, "everysec"),
+            AppendFsync::No => write!(f, "no"),
+        }
+    }
+}
+
+/// A file of the multi part AOF.
+#[derive(Debug, Clone)]
+struct AofFile {
+    name: String,
+    seq: u64,
+}
+
+/// Lists the files making up the AOF: a base, either an RDB or an AOF, followed by the
+/// incremental files holding the commands appended since the base was written.
+#[derive(Debug, Clone, Default)]
+struct Manifest {
+    base: Option<AofFile>,
+    incrs: Vec<AofFile>,
+}
+
+impl Manifest {
+    /// Parses lines like `file appendonly.aof.1.base.rdb seq 1 type b`.
+    fn parse(content: &str) -> anyhow::Result<Self> {
+        let mut manifest = Manifest::default();
+
+        for line in content.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let field = |name: &str| {
+                fields
+                    .chunks(2)
+                    .find(|pair| pair[0] == name)
+                    .and_then(|pair| pair.get(1).copied())
+            };
+            let (Some(name), Some(seq), Some(kind)) = (field("file"), field("seq"), field("type"))
+            else {
+                anyhow::bail!("Invalid AOF manifest line: {}", line);
+            };
+            let file = AofFile {
+                name: name.to_string(),
+                seq: seq.parse().context("Invalid AOF manifest seq")?,
+            };
+
+            match kind {
+                "b" => manifest.base = Some(file),
+                "i" => manifest.incrs.push(file),
+                // History files are leftovers of a rewrite, they are not loaded.
+                "h" => {}
+                _ => anyhow::bail!("Invalid AOF manifest file type: {}", kind),
+            }
+        }
+
+        Ok(manifest)
+    }
+
+    fn serialize(&self) -> String {
+        let mut content = String::new();
+        if let Some(base) = &self.base {
+            content.push_str(&format!("file {} seq {} type b\n", base.name, base.seq));
+        }
+        for incr in &self.incrs {
+            content.push_str(&format!("file {} seq {} type i\n", incr.name, incr.seq));
+        }
+
+        content
+    }
+
+    fn files(&self) -> impl Iterator<Item = &AofFile> {
+        self.base.iter().chain(&self.incrs)
+    }
+
+    fn next_base_seq(&self) -> u64 {
+        self.base.as_ref().map_or(1, |base| base.seq + 1)
+    }
+
+    fn next_incr_seq(&self) -> u64 {
+        self.incrs.iter().map(|incr| incr.seq).max().unwrap_or(0) + 1
+    }
+}
+
+/// Identifies rewrites across AOFs, so the one finishing after the AOF was disabled and enabled
+/// again doesn't replace the files of the new AOF.
+static NEXT_REWRITE_ID: AtomicU64 = AtomicU64::new(1);
+
+/// The files a running rewrite produces: a new base, and the incremental file commands are
+/// appended to meanwhile.
+#[derive(Debug)]
+struct Rewrite {
+    id: u64,
+    base: AofFile,
+    incr: AofFile,
+}
+
+/// Where a rewrite writes the base, which becomes the base of the AOF when it finishes.
+#[derive(Debug)]
+pub(crate) struct RewriteTarget {
+    pub(crate) id: u64,
+    /// A temporary file only this rewrite writes to.
+    pub(crate) path: PathBuf,
+}
+
+/// The open multi part append only file, in the `appenddirname` directory.
+#[derive(Debug)]
+pub(crate) struct Aof {
+    dir: PathBuf,
+    filename: String,
+    manifest: Manifest,
+    /// The incremental file commands are appended to.
+    incr: File,
+    /// Files of a previous AOF, deleted once the first rewrite replaces them.
+    stale: Vec<AofFile>,
+    /// Whether commands were written since the last fsync.
+    unsynced: bool,
+    rewrite: Option<Rewrite>,
+    pub(crate) last_rewrite_ok: bool,
+    next_base_seq: u64,
+    next_incr_seq: u64,
+    /// Size of the files, used with the size after the last rewrite to trigger the next one.
+    pub(crate) current_size: u64,
+    pub(crate) base_size: u64,
+}
+
+impl Aof {
+    /// Opens the files of a loaded manifest, creating an incremental file if it has none.
+    fn open(dir: PathBuf, filename: String, mut manifest: Manifest) -> anyhow::Result<Self> {
+        let created = manifest.incrs.is_empty();
+        if created {
+            let seq = manifest.next_incr_seq();
+            manifest.incrs.push(AofFile {
+                name: format!("{}.{}.incr.aof", filename, seq),
+                seq,
+            });
+        }
+        let incr_name = &manifest
+            .incrs
+            .last()
+            .expect("incrs should not be empty")
+            .name;
+        let incr = open_append(&dir.join(incr_name))?;
+
+        let mut aof = Self {
+            next_base_seq: manifest.next_base_seq(),
+            next_incr_seq: manifest.next_incr_seq(),
+            dir,
+            filename,
+            manifest,
+            incr,
+            stale: Vec::new(),
+            unsynced: false,
+            rewrite: None,
+            last_rewrite_ok: true,
+            current_size: 0,
+            base_size: 0,
+        };
+        if created {
+            aof.write_manifest()?;
+        }
+        aof.current_size = aof.files_size();
+        aof.base_size = aof.current_size;
+
+        Ok(aof)
+    }
+
+    /// Creates an empty AOF, a rewrite has to write the dataset as its base. The files of a
+    /// previous AOF in the directory are replaced once it is done.
+    fn create(dir: PathBuf, filename: String) -> anyhow::Result<Self> {
+        fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+
+        let previous = read_manifest(&dir, &filename)?;
+
+        let seq = previous.next_incr_seq();
+        let incr = AofFile {
+            name: format!("{}.{}.incr.aof", filename, seq),
+            seq,
+        };
+        let file = open_append(&dir.join(&incr.name))?;
+
+        Ok(Self {
+            next_base_seq: previous.next_base_seq(),
+            next_incr_seq: seq + 1,
+            stale: previous.files().cloned().collect(),
+            manifest: Manifest {
+                base: None,
+                incrs: vec![incr],
+            },
+            dir,
+            filename,
+            incr: file,
+            unsynced: false,
+            rewrite: None,
+            last_rewrite_ok: true,
+            current_size: 0,
+            base_size: 0,
+        })
+    }
+
+    /// Appends the commands propagated by an execution to the incremental file, syncing it
+    /// according to `appendfsync`.
+    pub(crate) fn append(&mut self, messages: &[Message], fsync: AppendFsync) -> io::Result<()> {
+        let mut buf = Vec::new();
+        for message in messages {
+            message.serialize(&mut buf);
+        }
+
+        self.incr.write_all(&buf)?;
+        self.current_size += buf.len() as u64;
+        match fsync {
+            AppendFsync::Always => self.incr.sync_data()?,
+            AppendFsync::EverySec => self.unsynced = true,
+            AppendFsync::No => {}
+        }
+
+        Ok(())
+    }
+
+    /// Returns a handle to fsync when commands were written since the last call, so the fsync
+    /// runs without holding the lock.
+    pub(crate) fn take_unsynced(&mut self) -> Option<File> {
+        if !std::mem::take(&mut self.unsynced) {
+            return None;
+        }
+
+        match self.incr.try_clone() {
+            Ok(file) => Some(file),
+            Err(err) => {
+                eprintln!("AOF FSYNC ERROR: {}", err);
+                None
+            }
+        }
+    }
+
+    pub(crate) fn is_rewriting(&self) -> bool {
+        self.rewrite.is_some()
+    }
+
+    /// Returns whether the AOF grew by `percentage` since the last rewrite and is bigger than
+    /// `min_size`.
+    pub(crate) fn needs_rewrite(&self, percentage: u64, min_size: u64) -> bool {
+        if percentage == 0 || self.is_rewriting() || self.current_size < min_size {
+            return false;
+        }
+
+        self.current_size * 100 / self.base_size.max(1) >= 100 + percentage
+    }
+
+    /// Switches appends to a new incremental file, returning where the new base has to be
+    /// written. The base and the new incremental file hold every write, so the other files are
+    /// dropped once the base is written.
+    pub(crate) fn start_rewrite(&mut self) -> anyhow::Result<RewriteTarget> {
+        anyhow::ensure!(
+            !self.is_rewriting(),
+            "ERR Background append only file rewriting already in progress"
+        );
+
+        let incr = AofFile {
+            name: format!("{}.{}.incr.aof", self.filename, self.next_incr_seq),
+            seq: self.next_incr_seq,
+        };
+        let file = open_append(&self.dir.join(&incr.name))?;
+        if let Err(err) = self.incr.sync_data() {
+            eprintln!("AOF FSYNC ERROR: {}", err);
+        }
+        self.incr = file;
+        self.next_incr_seq += 1;
+
+        // Until the base is written, the previous files are still needed to load the AOF. A
+        // new AOF has nothing to load until its first base exists.
+        self.manifest.incrs.push(incr.clone());
+        if self.manifest.base.is_some() {
+            self.write_manifest()?;
+        }
+
+        let base = AofFile {
+            name: format!("{}.{}.base.rdb", self.filename, self.next_base_seq),
+            seq: self.next_base_seq,
+        };
+        self.next_base_seq += 1;
+        let target = RewriteTarget::new(&self.dir);
+        self.rewrite = Some(Rewrite {
+            id: target.id,
+            base,
+            incr,
+        });
+
+        Ok(target)
+    }
+
+    /// Makes the new base and incremental file the whole AOF once the base is written. Returns
+    /// false when the rewrite isn't the one of this AOF, its base is then discarded.
+    pub(crate) fn finish_rewrite(
+        &mut self,
+        target: &RewriteTarget,
+        result: anyhow::Result<()>,
+    ) -> bool {
+        if self.rewrite.as_ref().map(|rewrite| rewrite.id) != Some(target.id) {
+            return false;
+        }
+        let Some(rewrite) = self.rewrite.take() else {
+            return false;
+        };
+
+        let base_path = self.dir.join(&rewrite.base.name);
+        let result = result
+            .and_then(|()| {
+                fs::rename(&target.path, &base_path)
+                    .with_context(|| format!("Failed to rename to {}", base_path.display()))
+            })
+            .and_then(|()| self.replace_files(rewrite));
+        match result {
+            Ok(()) => {
+                self.last_rewrite_ok = true;
+                println!("Background AOF rewrite finished successfully");
+            }
+            Err(err) => {
+                self.last_rewrite_ok = false;
+                eprintln!("AOF REWRITE ERROR: {:?}", err);
+                let _ = fs::remove_file(&target.path);
+            }
+        }
+
+        true
+    }
+
+    fn replace_files(&mut self, rewrite: Rewrite) -> anyhow::Result<()> {
+        let new_incr = rewrite.incr.name.clone();
+        let previous = std::mem::replace(
+            &mut self.manifest,
+            Manifest {
+                base: Some(rewrite.base),
+                incrs: vec![rewrite.incr],
+            },
+        );
+        self.write_manifest()?;
+
+        let stale = std::mem::take(&mut self.stale);
+        for file in previous.files().chain(&stale) {
+            if file.name != new_incr {
+                let _ = fs::remove_file(self.dir.join(&file.name));
+            }
+        }
+
+        self.current_size = self.files_size();
+        self.base_size = self.current_size;
+
+        Ok(())
+    }
+
+    fn files_size(&self) -> u64 {
+        self.manifest
+            .files()
+            .filter_map(|file| fs::metadata(self.dir.join(&file.name)).ok())
+            .map(|metadata| metadata.len())
+            .sum()
+    }
+
+    fn write_manifest(&self) -> anyhow::Result<()> {
+        write_manifest(&self.dir, &self.filename, &self.manifest)
+    }
+}
+
+impl Drop for Aof {
+    fn drop(&mut self) {
+        let _ = self.incr.sync_data();
+    }
+}
+
+impl RewriteTarget {
+    pub(crate) fn new(dir: &Path) -> Self {
+        let id = NEXT_REWRITE_ID.fetch_add(1, Ordering::Relaxed);
+
+        Self {
+            id,
+            path: dir.join(format!("temp-rewriteaof-{}.rdb", id)),
+        }
+    }
+}
+
+/// Reads the manifest of the AOF in `dir`, which is empty when there is none.
+fn read_manifest(dir: &Path, filename: &str) -> anyhow::Result<Manifest> {
+    match fs::read_to_string(dir.join(format!("{}.manifest", filename))) {
+        Ok(content) => Manifest::parse(&content),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Manifest::default()),
+        Err(err) => Err(err).context("Failed to read AOF manifest"),
+    }
+}
+
+fn write_manifest(dir: &Path, filename: &str, manifest: &Manifest) -> anyhow::Result<()> {
+    let path = dir.join(format!("{}.manifest", filename));
+    let temp_path = dir.join(format!("temp-{}.manifest", filename));
+    fs::write(&temp_path, manifest.serialize())
+        .and_then(|()| fs::rename(&temp_path, &path))
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Makes the base written by a rewrite while the AOF is disabled the whole AOF in `dir`, like
+/// Redis. No writes are appended, so the manifest has no incremental file.
+pub(crate) fn replace_with_base(
+    dir: &Path,
+    filename: &str,
+    target: &RewriteTarget,
+) -> anyhow::Result<()> {
+    let previous = read_manifest(dir, filename)?;
+    let seq = previous.next_base_seq();
+    let base = AofFile {
+        name: format!("{}.{}.base.rdb", filename, seq),
+        seq,
+    };
+    let base_path = dir.join(&base.name);
+    fs::rename(&target.path, &base_path)
+        .with_context(|| format!("Failed to rename to {}", base_path.display()))?;
+
+    write_manifest(
+        dir,
+        filename,
+        &Manifest {
+            base: Some(base),
+            incrs: Vec::new(),
+        },
+    )?;
+    for file in previous.files() {
+        let _ = fs::remove_file(dir.join(&file.name));
+    }
+
+    Ok(())
+}
+
+fn open_append(path: &Path) -> anyhow::Result<File> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Failed to open {}", path.display()))
+}
+
+/// Loads the AOF through the normal command path, then opens it for appending. An AOF written
+/// before multi part AOFs is upgraded by moving it in the directory as the base.
+pub(crate) fn load(db: &Db) -> anyhow::Result<()> {
+    let (dir, filename, legacy_path) = {
+        let config = db.config();
+        (
+            config.aof_dir(),
+            config.appendfilename.clone(),
+            config.aof_path(),
+        )
+    };
+    let manifest_path = dir.join(format!("{}.manifest", filename));
+
+    let manifest = match fs::read_to_string(&manifest_path) {
+        Ok(content) => Manifest::parse(&content)
+            .with_context(|| format!("Failed to load {}", manifest_path.display()))?,
+        Err(err) if err.kind() == io::ErrorKind::NotFound && legacy_path.exists() => {
+            println!("Upgrading {} to a multi part AOF", legacy_path.display());
+            fs::create_dir_all(&dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+            fs::rename(&legacy_path, dir.join(&filename))
+                .with_context(|| format!("Failed to move {}", legacy_path.display()))?;
+
+            Manifest {
+                base: Some(AofFile {
+                    name: filename.clone(),
+                    seq: 1,
+                }),
+                incrs: Vec::new(),
+            }
+        }
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            return db.enable_aof(&db.keyspace());
+        }
+        Err(err) => {
+            return Err(err).with_context(|| format!("Failed to read {}", manifest_path.display()))
+        }
+    };
+
+    let files: Vec<&AofFile> = manifest.files().collect();
+    for (i, file) in files.iter().enumerate() {
+        load_file(db, &dir.join(&file.name), i == files.len() - 1)?;
+    }
+
+    *db.aof() = Some(Aof::open(dir, filename, manifest)?);
+
+    Ok(())
+}
+
+/// Creates an AOF to which writes are appended from now on, a rewrite writes the dataset.
+pub(crate) fn create(db: &Db) -> anyhow::Result<Aof> {
+    let config = db.config();
+    Aof::create(config.aof_dir(), config.appendfilename.clone())
+}
+
+/// Loads a base or incremental file. Only the last file may be truncated, the others were
+/// complete when the following file was started.
+fn load_file(db: &Db, path: &Path, is_last: bool) -> anyhow::Result<()> {
+    let bytes = fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+
+    if bytes.starts_with(b"REDIS") {
+        let loaded = db
+            .load_rdb_bytes(&bytes)
+            .with_context(|| format!("Failed to load {}", path.display()))?;
+        println!("Loaded {} keys from {}", loaded, path.display());
+        return Ok(());
+    }
+
+    let (commands, valid_len) = replay(db, &bytes)?;
+    if valid_len < bytes.len() {
+        anyhow::ensure!(
+            is_last && db.config().aof_load_truncated,
+            "Unexpected end of file reading the append only file {}, set aof-load-truncated to yes to load it anyway",
+            path.display()
+        );
+
+        eprintln!(
+            "AOF {} was truncated, discarding the last {} bytes",
+            path.display(),
+            bytes.len() - valid_len
+        );
+        OpenOptions::new()
+            .write(true)
+            .open(path)
+            .and_then(|file| file.set_len(valid_len as u64))
+            .with_context(|| format!("Failed to truncate {}", path.display()))?;
+    }
+    println!("Loaded {} commands from {}", commands, path.display());
+
+    Ok(())
+}
+
+/// Executes the commands of the file, returning how many ran and the length of the file up to
+/// the last complete command. A transaction missing its `EXEC` is not applied.
+fn replay(db: &Db, bytes: &[u8]) -> anyhow::Result<(usize, usize)> {
+    // The commands are neither propagated nor counted as changes to save.
+    db.keyspace().set_loading(true);
+    let result = replay_commands(db, bytes);
+    db.keyspace().set_loading(false);
+
+    result
+}
+
+fn replay_commands(db: &Db, bytes: &[u8]) -> anyhow::Result<(usize, usize)> {
+    let mut client = Client::new_aof_loader(db);
+    let mut buf = BytesMut::from(bytes);
+    let mut commands = 0;
+    let mut valid_len = 0;
+    let mut valid_before_multi = 0;
+
+    while let Some(frame_len) = message::frame_len(&buf).context("Bad file format reading AOF")? {
+        let frame = buf.split_to(frame_len);
+        let args = commands::parse_message(&frame).context("Bad file format reading AOF")?;
+        if client.transaction.is_none() {
+            valid_before_multi = valid_len;
+        }
+
+        let reply = connection::process_command(db, &mut client, &args);
+        if let Message::SimpleError(err) = reply {
+            eprintln!("AOF COMMAND ERROR: {}", err.data);
+        }
+        commands += 1;
+        valid_len += frame_len;
+    }
+
+    if client.transaction.is_some() {
+        eprintln!("Reverting incomplete MULTI/EXEC transaction in AOF");
+        return Ok((commands, valid_before_multi));
+    }
+
+    Ok((commands, valid_len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names<'a>(files: impl Iterator<Item = &'a AofFile>) -> Vec<(&'a str, u64)> {
+        files.map(|file| (file.name.as_str(), file.seq)).collect()
+    }
+
+    #[test]
+    fn parse_manifest() {
+        let manifest = Manifest::parse(
+            "file appendonly.aof.2.base.rdb seq 2 type b\n\
+             # comment\n\
+             \n\
+             file appendonly.aof.1.incr.aof seq 1 type h\n\
+             file appendonly.aof.3.incr.aof seq 3 type i\n\
+             file appendonly.aof.4.incr.aof type i seq 4 startoffset 10\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            names(manifest.files()),
+            [
+                ("appendonly.aof.2.base.rdb", 2),
+                ("appendonly.aof.3.incr.aof", 3),
+                ("appendonly.aof.4.incr.aof", 4),
+            ]
+        );
+        assert_eq!(manifest.next_base_seq(), 3);
+        assert_eq!(manifest.next_incr_seq(), 5);
+    }
+
+    #[test]
+    fn serialize_manifest() {
+        let content = "file appendonly.aof.1.base.aof seq 1 type b\n\
+                       file appendonly.aof.1.incr.aof seq 1 type i\n";
+        assert_eq!(Manifest::parse(content).unwrap().serialize(), content);
+    }
+
+    #[test]
+    fn empty_manifest() {
+        let manifest = Manifest::parse("").unwrap();
+        assert!(manifest.base.is_none());
+        assert_eq!(manifest.next_base_seq(), 1);
+        assert_eq!(manifest.next_incr_seq(), 1);
+    }
+
+    #[test]
+    fn invalid_manifest() {
+        for content in [
+            "file appendonly.aof.1.base.rdb seq 1",
+            "file appendonly.aof.1.base.rdb seq one type b",
+            "file appendonly.aof.1.base.rdb seq 1 type x",
+        ] {
+            assert!(Manifest::parse(content).is_err(), "{}", content);
+        }
+    }
+
+    #[test]
+    fn stale_rewrite_is_discarded() {
+        let dir = std::env::temp_dir().join(format!("aof-rewrite-{}", std::process::id()));
+        let filename = String::from("appendonly.aof");
+
+        let mut stale = Aof::create(dir.clone(), filename.clone()).unwrap();
+        let stale_target = stale.start_rewrite().unwrap();
+        drop(stale);
+        let mut aof = Aof::create(dir.clone(), filename).unwrap();
+        let target = aof.start_rewrite().unwrap();
+        assert_ne!(stale_target.path, target.path);
+
+        fs::write(&stale_target.path, b"stale").unwrap();
+        assert!(!aof.finish_rewrite(&stale_target, Ok(())));
+        assert!(aof.is_rewriting());
+
+        fs::write(&target.path, b"base").unwrap();
+        assert!(aof.finish_rewrite(&target, Ok(())));
+        assert!(aof.last_rewrite_ok);
+        let base = aof.manifest.base.as_ref().unwrap();
+        assert_eq!(fs::read(dir.join(&base.name)).unwrap(), b"base");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rewrite_while_disabled_replaces_the_aof_with_a_base() {
+        let dir = std::env::temp_dir().join(format!("aof-offline-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        for base in [b"first", b"other"] {
+            let target = RewriteTarget::new(&dir);
+            fs::write(&target.path, base).unwrap();
+            replace_with_base(&dir, "appendonly.aof", &target).unwrap();
+        }
+
+        let manifest = read_manifest(&dir, "appendonly.aof").unwrap();
+        assert_eq!(names(manifest.files()), [("appendonly.aof.2.base.rdb", 2)]);
+        assert!(!dir.join("appendonly.aof.1.base.rdb").exists());
+        assert_eq!(
+            fs::read(dir.join("appendonly.aof.2.base.rdb")).unwrap(),
+            b"other"
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,231 @@
+use std::{
+    collections::HashSet,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use tokio::sync::{mpsc, watch};
+
+use crate::{
+    db::{Db, Entry},
+    message::Message,
+    pubsub::PubSub,
+    replication::{ReplicaOutput, WaitAcks},
+    transaction::{Transaction, WatchedKeys},
+};
+
+static NEXT_CLIENT_ID: AtomicU64 = AtomicU64::new(1);
+
+/// What a replica needs to synchronize after `PSYNC`, followed by the writes applied after it
+/// ran.
+pub(crate) enum Resync {
+    /// The keyspace when `PSYNC` ran.
+    Full {
+        snapshot: Vec<(String, Entry)>,
+        feed: Arc<ReplicaOutput>,
+    },
+    /// The bytes of the replication stream the replica missed, taken from the backlog.
+    Partial {
+        missing: Vec<u8>,
+        feed: Arc<ReplicaOutput>,
+    },
+}
+
+/// Per-connection state.
+pub(crate) struct Client {
+    pub(crate) id: u64,
+    /// The address of the peer, unknown for connections created internally like when loading
+    /// the AOF.
+    pub(crate) addr: Option<SocketAddr>,
+    db: Db,
+    pub(crate) transaction: Option<Transaction>,
+    pub(crate) watched_keys: WatchedKeys,
+    /// Set by `PSYNC`, the connection then synchronizes the replica and streams it the writes.
+    pub(crate) resync: Option<Resync>,
+    /// Set by `WAIT`, the connection replies once enough replicas acknowledged the writes.
+    pub(crate) wait: Option<WaitAcks>,
+    /// The replication offset after the last write of the connection.
+    pub(crate) write_offset: usize,
+    channels: HashSet<Vec<u8>>,
+    patterns: HashSet<Vec<u8>>,
+    shard_channels: HashSet<Vec<u8>>,
+    sender: mpsc::UnboundedSender<Message>,
+    /// Messages written to the connection outside of command replies, like published messages.
+    pub(crate) pushed: mpsc::UnboundedReceiver<Message>,
+    /// Set by `QUIT`, the connection is closed once the reply is written.
+    pub(crate) close_after_reply: bool,
+    /// The RESP version chosen with `HELLO`.
+    pub(crate) protocol: u8,
+    /// Set by `CLIENT CACHING` for the next command.
+    pub(crate) caching: Option<bool>,
+    /// The port a replica listens on, announced with `REPLCONF listening-port`.
+    pub(crate) listening_port: Option<u16>,
+    /// Set by `REPLCONF GETACK`, the only command a replica answers on the master link.
+    pub(crate) master_force_reply: bool,
+    /// The frame received from the master being processed, forwarded as is to the replicas of
+    /// this server.
+    pub(crate) master_frame: Option<Vec<u8>>,
+    /// Set on the connection with the master, signaled when the role of the server changes.
+    master_link: Option<watch::Receiver<()>>,
+    /// Set on the client replaying the AOF.
+    aof_loader: bool,
+}
+
+impl Client {
+    pub(crate) fn new(db: &Db) -> Self {
+        let (sender, pushed) = mpsc::unbounded_channel();
+        let id = NEXT_CLIENT_ID.fetch_add(1, Ordering::SeqCst);
+        db.tracking().register(id, sender.clone());
+
+        Self {
+            id,
+            addr: None,
+            db: db.clone(),
+            transaction: None,
+            watched_keys: WatchedKeys::new(db.clone()),
+            resync: None,
+            wait: None,
+            write_offset: 0,
+            channels: HashSet::new(),
+            patterns: HashSet::new(),
+            shard_channels: HashSet::new(),
+            sender,
+            pushed,
+            close_after_reply: false,
+            protocol: 2,
+            caching: None,
+            listening_port: None,
+            master_force_reply: false,
+            master_frame: None,
+            master_link: None,
+            aof_loader: false,
+        }
+    }
+
+    /// The connection a replica keeps with its master, which is never replied to.
+    pub(crate) fn new_master_link(db: &Db) -> Self {
+        let mut client = Self::new(db);
+        client.master_link = Some(db.replication().subscribe_role_changes());
+
+        client
+    }
+
+    /// The client replaying the commands of the AOF while loading it.
+    pub(crate) fn new_aof_loader(db: &Db) -> Self {
+        let mut client = Self::new(db);
+        client.aof_loader = true;
+
+        client
+    }
+
+    /// Whether the writes of the connection were already accepted: the ones sent by the master
+    /// or replayed from the AOF.
+    pub(crate) fn replays_writes(&self) -> bool {
+        self.is_master_link() || self.aof_loader
+    }
+
+    pub(crate) fn is_master_link(&self) -> bool {
+        self.master_link.is_some()
+    }
+
+    /// Whether this is a link with a master this server no longer follows.
+    pub(crate) fn is_stale_master_link(&self) -> bool {
+        self.master_link
+            .as_ref()
+            .is_some_and(|role_changes| role_changes.has_changed().unwrap_or(true))
+    }
+
+    pub(crate) fn subscribe(&mut self, pubsub: &mut PubSub, channel: Vec<u8>) {
+        if self.channels.insert(channel.clone()) {
+            pubsub.subscribe(channel, self.id, self.sender.clone());
+        }
+    }
+
+    pub(crate) fn unsubscribe(&mut self, pubsub: &mut PubSub, channel: &[u8]) {
+        if self.channels.remove(channel) {
+            pubsub.unsubscribe(channel, self.id);
+        }
+    }
+
+    pub(crate) fn psubscribe(&mut self, pubsub: &mut PubSub, pattern: Vec<u8>) {
+        if self.patterns.insert(pattern.clone()) {
+            pubsub.psubscribe(pattern, self.id, self.sender.clone());
+        }
+    }
+
+    pub(crate) fn punsubscribe(&mut self, pubsub: &mut PubSub, pattern: &[u8]) {
+        if self.patterns.remove(pattern) {
+            pubsub.punsubscribe(pattern, self.id);
+        }
+    }
+
+    pub(crate) fn ssubscribe(&mut self, pubsub: &mut PubSub, channel: Vec<u8>) {
+        if self.shard_channels.insert(channel.clone()) {
+            pubsub.ssubscribe(channel, self.id, self.sender.clone());
+        }
+    }
+
+    pub(crate) fn sunsubscribe(&mut self, pubsub: &mut PubSub, channel: &[u8]) {
+        if self.shard_channels.remove(channel) {
+            pubsub.sunsubscribe(channel, self.id);
+        }
+    }
+
+    pub(crate) fn channels(&self) -> Vec<Vec<u8>> {
+        self.channels.iter().cloned().collect()
+    }
+
+    pub(crate) fn patterns(&self) -> Vec<Vec<u8>> {
+        self.patterns.iter().cloned().collect()
+    }
+
+    pub(crate) fn shard_channels(&self) -> Vec<Vec<u8>> {
+        self.shard_channels.iter().cloned().collect()
+    }
+
+    /// Returns the number of channels and patterns subscribed, shard channels are counted apart.
+    pub(crate) fn subscription_count(&self) -> usize {
+        self.channels.len() + self.patterns.len()
+    }
+
+    pub(crate) fn shard_subscription_count(&self) -> usize {
+        self.shard_channels.len()
+    }
+
+    /// Returns whether the connection has subscriptions.
+    pub(crate) fn is_subscriber(&self) -> bool {
+        self.subscription_count() + self.shard_subscription_count() > 0
+    }
+
+    /// Returns whether the connection is restricted to subscription commands, which is only the
+    /// case for RESP2 as it can't tell replies from published messages.
+    pub(crate) fn in_subscriber_mode(&self) -> bool {
+        self.protocol == 2 && self.is_subscriber()
+    }
+
+    pub(crate) fn unsubscribe_all(&mut self, pubsub: &mut PubSub) {
+        for channel in self.channels() {
+            self.unsubscribe(pubsub, &channel);
+        }
+        for pattern in self.patterns() {
+            self.punsubscribe(pubsub, &pattern);
+        }
+        for channel in self.shard_channels() {
+            self.sunsubscribe(pubsub, &channel);
+        }
+    }
+}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        self.db.tracking().unregister(self.id);
+
+        if self.is_subscriber() {
+            let db = self.db.clone();
+            self.unsubscribe_all(&mut db.pubsub());
+        }
+    }
+}
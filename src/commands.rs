@@ -1,64 +1,1167 @@
-use std::{fmt, io::Cursor};
+use std::{
+    fmt,
+    io::Cursor,
+    time::{SystemTime, UNIX_EPOCH},
+};
 
 use anyhow::{Context, Ok};
-use async_trait::async_trait;
-use tokio::{
-    io::{BufWriter, WriteHalf},
-    net::TcpStream,
-};
 
 use crate::{
-    db::Db,
+    client::Client,
+    db::{Db, Keyspace},
     message::{Array, BulkString, Message},
 };
 
+pub(crate) mod bgrewriteaof;
+pub(crate) mod bgsave;
+pub(crate) mod client;
+pub(crate) mod config;
+pub(crate) mod del;
+pub(crate) mod discard;
 pub(crate) mod echo;
+pub(crate) mod exec;
+pub(crate) mod expire;
+pub(crate) mod flushall;
+pub(crate) mod geoadd;
+pub(crate) mod geodist;
+pub(crate) mod geohash;
+pub(crate) mod geopos;
+pub(crate) mod geosearch;
+pub(crate) mod geosearchstore;
 pub(crate) mod get;
+pub(crate) mod hello;
 pub(crate) mod info;
+pub(crate) mod lastsave;
+pub(crate) mod multi;
+pub(crate) mod pexpire;
+pub(crate) mod pexpireat;
+pub(crate) mod pfadd;
+pub(crate) mod pfcount;
+pub(crate) mod pfmerge;
 pub(crate) mod ping;
+pub(crate) mod psubscribe;
 pub(crate) mod psync;
+pub(crate) mod publish;
+pub(crate) mod pubsub;
+pub(crate) mod punsubscribe;
+pub(crate) mod quit;
 pub(crate) mod replconf;
+pub(crate) mod replicaof;
+pub(crate) mod reset;
+pub(crate) mod save;
 pub(crate) mod set;
+pub(crate) mod spublish;
+pub(crate) mod ssubscribe;
+pub(crate) mod subscribe;
+pub(crate) mod sunsubscribe;
+pub(crate) mod unsubscribe;
+pub(crate) mod unwatch;
+pub(crate) mod wait;
+pub(crate) mod watch;
 
 pub(crate) type CommandArgs<'a> = &'a [BulkString];
 
-#[async_trait]
-pub(crate) trait Command: Send + fmt::Display {
+pub(crate) trait Command: Send + Sync + fmt::Display {
     fn new(args: CommandArgs) -> anyhow::Result<Self>
     where
         Self: Sized;
 
     fn to_message(&self) -> Message;
 
-    async fn handle(
-        &self,
-        writer: &mut BufWriter<WriteHalf<TcpStream>>,
-        db: &Db,
-    ) -> anyhow::Result<()>;
+    /// Whether the command modifies the dataset. Read only replicas reject it from their clients.
+    fn is_write(&self) -> bool {
+        false
+    }
+
+    /// The command replicas and the AOF apply to reproduce this one when it ran at `now`, in unix
+    /// time milliseconds. Commands relative to the time are rewritten to absolute ones.
+    fn replicated(&self, _now: u128) -> Message {
+        self.to_message()
+    }
+
+    /// Runs the command while the keyspace is locked, returning the reply. Errors are replied to
+    /// the client as error messages.
+    fn execute(&self, context: &mut ExecutionContext) -> anyhow::Result<Message>;
+}
+
+/// Everything a command can act on while it runs.
+pub(crate) struct ExecutionContext<'a> {
+    pub(crate) db: &'a Db,
+    pub(crate) keyspace: &'a mut Keyspace,
+    pub(crate) client: &'a mut Client,
+    /// Unix time in milliseconds the execution started at, the time of all its commands.
+    pub(crate) now: u128,
+    propagated: Vec<Message>,
+}
+
+impl<'a> ExecutionContext<'a> {
+    pub(crate) fn new(db: &'a Db, keyspace: &'a mut Keyspace, client: &'a mut Client) -> Self {
+        Self {
+            db,
+            keyspace,
+            client,
+            now: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .expect("SystemTime before UNIX EPOCH!")
+                .as_millis(),
+            propagated: Vec::new(),
+        }
+    }
+
+    /// Declares a command replicas have to apply to reproduce the effects of the execution.
+    pub(crate) fn propagate(&mut self, message: Message) {
+        // Replaying the AOF applies writes that were already propagated.
+        if self.keyspace.is_loading() {
+            return;
+        }
+
+        self.propagate_expired();
+        self.propagated.push(message);
+    }
+
+    /// Declares the command that ran, as replicas apply it.
+    pub(crate) fn propagate_command(&mut self, command: &dyn Command) {
+        self.propagate(command.replicated(self.now));
+    }
+
+    /// Propagates the keys that expired so far as `DEL`s, so replicas delete them in the same
+    /// order.
+    fn propagate_expired(&mut self) {
+        for key in self.keyspace.take_expired() {
+            self.propagated
+                .push(del::DelCommand::new_command(vec![key]).to_message());
+        }
+    }
+
+    pub(crate) fn propagated_len(&self) -> usize {
+        self.propagated.len()
+    }
+
+    /// Wraps the commands propagated since `start` in a `MULTI ... EXEC` block.
+    pub(crate) fn wrap_propagated_in_transaction(&mut self, start: usize) {
+        if self.propagated.len() > start {
+            self.propagated
+                .insert(start, Message::array(vec![Message::bulk_string("MULTI")]));
+            self.propagated
+                .push(Message::array(vec![Message::bulk_string("EXEC")]));
+        }
+    }
+}
+
+/// Executes a command atomically and propagates its effects to the replicas.
+pub(crate) fn execute(db: &Db, client: &mut Client, command: &dyn Command) -> Message {
+    let mut keyspace = db.keyspace();
+    // `CLIENT CACHING` only applies to the next command.
+    let caching = client.caching.take();
+    let mut context = ExecutionContext::new(db, &mut keyspace, client);
+
+    let message = command
+        .execute(&mut context)
+        .unwrap_or_else(|err| Message::error(&err));
+    context.propagate_expired();
+    let wrote = !context.propagated.is_empty();
+    db.propagate(context.propagated);
+    if let Some(frame) = client.master_frame.take() {
+        db.feed_replication(&frame);
+    }
+    if wrote {
+        client.write_offset = db.replication_offset();
+    }
+    db.notify_keyspace_events(keyspace.take_events());
+    db.update_tracking(&mut keyspace, Some(client.id), caching);
+
+    message
 }
 
-pub(crate) fn parse_command(buf: &mut [u8]) -> anyhow::Result<Box<dyn Command>> {
+pub(crate) fn parse_message(buf: &[u8]) -> anyhow::Result<Vec<BulkString>> {
     let message: Array = Message::deserialize(&mut Cursor::new(buf))
         .context("Failed to parse message")?
         .try_into()?;
 
-    let args: Vec<BulkString> = message
+    let args = message
         .elements
         .iter()
-        .map(|value| value.try_into().unwrap())
-        .collect();
+        .map(|value| value.try_into())
+        .collect::<anyhow::Result<Vec<BulkString>>>()?;
+    anyhow::ensure!(!args.is_empty(), "Message should have command");
+
+    Ok(args)
+}
 
-    let command = args.first().expect("Message should have command");
+pub(crate) fn command_name(args: &[BulkString]) -> String {
+    args.first()
+        .map(|command| command.to_string().to_lowercase())
+        .unwrap_or_default()
+}
+
+pub(crate) fn parse_command(args: &[BulkString]) -> anyhow::Result<Box<dyn Command>> {
     let command_args = &args[1..];
 
-    match command.data.to_lowercase().as_str() {
+    match command_name(args).as_str() {
         "ping" => Ok(Box::new(ping::PingCommand::new(command_args)?)),
         "echo" => Ok(Box::new(echo::EchoCommand::new(command_args)?)),
         "set" => Ok(Box::new(set::SetCommand::new(command_args)?)),
         "get" => Ok(Box::new(get::GetCommand::new(command_args)?)),
+        "expire" => Ok(Box::new(expire::ExpireCommand::new(command_args)?)),
+        "pexpire" => Ok(Box::new(pexpire::PExpireCommand::new(command_args)?)),
+        "pexpireat" => Ok(Box::new(pexpireat::PExpireAtCommand::new(command_args)?)),
+        "del" => Ok(Box::new(del::DelCommand::new(command_args)?)),
+        "config" => Ok(Box::new(config::ConfigCommand::new(command_args)?)),
         "info" => Ok(Box::new(info::InfoCommand::new(command_args)?)),
         "replconf" => Ok(Box::new(replconf::ReplConfCommand::new(command_args)?)),
+        "replicaof" => Ok(Box::new(replicaof::ReplicaOfCommand::new(command_args)?)),
+        "slaveof" => Ok(Box::new(replicaof::ReplicaOfCommand::parse(
+            "slaveof",
+            command_args,
+        )?)),
         "psync" => Ok(Box::new(psync::PSyncCommand::new(command_args)?)),
-        command => anyhow::bail!("Command not implemented: {}", command),
+        "wait" => Ok(Box::new(wait::WaitCommand::new(command_args)?)),
+        "pfadd" => Ok(Box::new(pfadd::PfAddCommand::new(command_args)?)),
+        "pfcount" => Ok(Box::new(pfcount::PfCountCommand::new(command_args)?)),
+        "pfmerge" => Ok(Box::new(pfmerge::PfMergeCommand::new(command_args)?)),
+        "flushall" => Ok(Box::new(flushall::FlushAllCommand::new(command_args)?)),
+        "multi" => Ok(Box::new(multi::MultiCommand::new(command_args)?)),
+        "exec" => Ok(Box::new(exec::ExecCommand::new(command_args)?)),
+        "discard" => Ok(Box::new(discard::DiscardCommand::new(command_args)?)),
+        "watch" => Ok(Box::new(watch::WatchCommand::new(command_args)?)),
+        "unwatch" => Ok(Box::new(unwatch::UnwatchCommand::new(command_args)?)),
+        "subscribe" => Ok(Box::new(subscribe::SubscribeCommand::new(command_args)?)),
+        "unsubscribe" => Ok(Box::new(unsubscribe::UnsubscribeCommand::new(
+            command_args,
+        )?)),
+        "psubscribe" => Ok(Box::new(psubscribe::PSubscribeCommand::new(command_args)?)),
+        "punsubscribe" => Ok(Box::new(punsubscribe::PUnsubscribeCommand::new(
+            command_args,
+        )?)),
+        "ssubscribe" => Ok(Box::new(ssubscribe::SSubscribeCommand::new(command_args)?)),
+        "sunsubscribe" => Ok(Box::new(sunsubscribe::SUnsubscribeCommand::new(
+            command_args,
+        )?)),
+        "spublish" => Ok(Box::new(spublish::SPublishCommand::new(command_args)?)),
+        "publish" => Ok(Box::new(publish::PublishCommand::new(command_args)?)),
+        "pubsub" => Ok(Box::new(pubsub::PubSubCommand::new(command_args)?)),
+        "quit" => Ok(Box::new(quit::QuitCommand::new(command_args)?)),
+        "client" => Ok(Box::new(client::ClientCommand::new(command_args)?)),
+        "hello" => Ok(Box::new(hello::HelloCommand::new(command_args)?)),
+        "save" => Ok(Box::new(save::SaveCommand::new(command_args)?)),
+        "bgrewriteaof" => Ok(Box::new(bgrewriteaof::BgRewriteAofCommand::new(
+            command_args,
+        )?)),
+        "bgsave" => Ok(Box::new(bgsave::BgSaveCommand::new(command_args)?)),
+        "lastsave" => Ok(Box::new(lastsave::LastSaveCommand::new(command_args)?)),
+        "reset" => Ok(Box::new(reset::ResetCommand::new(command_args)?)),
+        "geoadd" => Ok(Box::new(geoadd::GeoAddCommand::new(command_args)?)),
+        "geodist" => Ok(Box::new(geodist::GeoDistCommand::new(command_args)?)),
+        "geopos" => Ok(Box::new(geopos::GeoPosCommand::new(command_args)?)),
+        "geohash" => Ok(Box::new(geohash::GeoHashCommand::new(command_args)?)),
+        "geosearch" => Ok(Box::new(geosearch::GeoSearchCommand::new(command_args)?)),
+        "geosearchstore" => Ok(Box::new(geosearchstore::GeoSearchStoreCommand::new(
+            command_args,
+        )?)),
+        command => anyhow::bail!(
+            "ERR unknown command '{}', with args beginning with: {}",
+            command,
+            command_args
+                .iter()
+                .map(|arg| format!("'{}' ", arg))
+                .collect::<String>()
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        client::Resync,
+        config::Config,
+        db::{Entry, Value},
+        replication::{self, Role},
+    };
+
+    fn now() -> u128 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("SystemTime before UNIX EPOCH!")
+            .as_millis()
+    }
+
+    fn bulk_strings(args: &[&str]) -> Vec<Message> {
+        args.iter().map(|arg| Message::bulk_string(*arg)).collect()
+    }
+
+    fn command(args: &[&str]) -> Message {
+        Message::array(bulk_strings(args))
+    }
+
+    fn args(line: &str) -> Vec<BulkString> {
+        line.split_whitespace()
+            .map(|arg| BulkString {
+                data: arg.as_bytes().to_vec(),
+            })
+            .collect()
+    }
+
+    fn parse(line: &str) -> Box<dyn Command> {
+        let Some(command) = parse_command(&args(line)).ok() else {
+            panic!("Failed to parse {}", line);
+        };
+
+        command
+    }
+
+    /// Runs a command line, returning its reply and the commands it propagates.
+    fn run(db: &Db, line: &str) -> (Message, Vec<Message>) {
+        run_as(db, &mut Client::new(db), line)
+    }
+
+    fn run_as(db: &Db, client: &mut Client, line: &str) -> (Message, Vec<Message>) {
+        let command = parse(line);
+
+        let mut keyspace = db.keyspace();
+        let mut context = ExecutionContext::new(db, &mut keyspace, client);
+        let reply = command.execute(&mut context).unwrap();
+        context.propagate_expired();
+
+        (reply, context.propagated)
+    }
+
+    /// Sends a command line on the connection of `client`, like one read from its socket.
+    fn send(db: &Db, client: &mut Client, line: &str) -> Message {
+        crate::connection::process_command(db, client, &args(line))
+    }
+
+    fn queued() -> Message {
+        Message::simple_string(String::from("QUEUED"))
+    }
+
+    fn error(message: &str) -> Message {
+        Message::simple_error(message.to_string())
+    }
+
+    fn load_expired(db: &Db, key: &str) {
+        db.keyspace().load(
+            key.to_string(),
+            Entry::with_expire_at(Value::String(b"value".to_vec()), 1),
+        );
+    }
+
+    #[test]
+    fn set_with_relative_expiration_propagates_pxat() {
+        let db = Db::new(None, Config::default());
+
+        let start = now();
+        let (_, propagated) = run(&db, "SET key value PX 1000");
+        let end = now();
+
+        let [Message::Array(Array { elements })] = propagated.as_slice() else {
+            panic!("Expected a single command, got {:?}", propagated);
+        };
+        assert_eq!(
+            elements[..4],
+            bulk_strings(&["SET", "key", "value", "PXAT"])
+        );
+        let Message::BulkString(BulkString { data }) = &elements[4] else {
+            panic!("Expected a deadline, got {:?}", elements[4]);
+        };
+        let expire_at: u128 = std::str::from_utf8(data).unwrap().parse().unwrap();
+        assert!((start + 1000..=end + 1000).contains(&expire_at));
+        assert_eq!(elements.len(), 5);
+    }
+
+    #[test]
+    fn set_rejects_invalid_expire_times() {
+        for line in [
+            "SET key value PX 0",
+            "SET key value PX -1",
+            "SET key value PX 9223372036854775807",
+            "SET key value PXAT 0",
+            "SET key value PX 340282366920938463463374607431768211455",
+        ] {
+            let Err(error) = parse_command(&args(line)) else {
+                panic!("{} should fail", line);
+            };
+            let error = error.to_string();
+            assert!(
+                error == "ERR invalid expire time in 'set' command"
+                    || error == "ERR value is not an integer or out of range",
+                "{}: {}",
+                line,
+                error
+            );
+        }
+    }
+
+    #[test]
+    fn wrong_number_of_arguments() {
+        for line in [
+            "PFADD",
+            "PFCOUNT",
+            "PFMERGE",
+            "GEOADD g 1 1",
+            "GEODIST g a",
+            "GEOHASH",
+            "GEOPOS",
+            "GEOSEARCH g FROMLONLAT 1 1",
+            "GEOSEARCHSTORE d g FROMLONLAT 1 1",
+            "MULTI x",
+            "EXEC x",
+            "DISCARD x",
+            "UNWATCH x",
+            "WATCH",
+            "PING a b",
+            "ECHO",
+            "ECHO a b",
+            "GET",
+            "GET a b",
+            "SET key",
+            "QUIT x",
+            "RESET x",
+            "PSYNC ?",
+            "REPLCONF",
+            "REPLCONF ACK",
+            "REPLCONF capa eof capa",
+            "REPLICAOF no",
+            "SLAVEOF no",
+        ] {
+            let Err(error) = parse_command(&args(line)) else {
+                panic!("{} should fail", line);
+            };
+            let name = command_name(&args(line));
+            assert_eq!(
+                error.to_string(),
+                format!("ERR wrong number of arguments for '{}' command", name)
+            );
+        }
+    }
+
+    #[test]
+    fn info_ignores_unknown_sections() {
+        let db = Db::new(None, Config::default());
+
+        assert_eq!(run(&db, "INFO unknown").0, Message::bulk_string(""));
+        let Message::BulkString(BulkString { data }) = run(&db, "INFO Replication unknown").0
+        else {
+            panic!("Expected a bulk string");
+        };
+        let info = String::from_utf8(data).unwrap();
+        assert!(info.contains("role:master"), "{}", info);
+    }
+
+    #[test]
+    fn binary_keys_are_rejected() {
+        for key in [b"\xff".as_slice(), b"\xfe"] {
+            let args = [b"SET".as_slice(), key, b"value"].map(|data| BulkString {
+                data: data.to_vec(),
+            });
+            let Err(error) = parse_command(&args) else {
+                panic!("{:?} should be rejected", key);
+            };
+            assert_eq!(error.to_string(), "ERR keys must be valid UTF-8");
+        }
+    }
+
+    #[test]
+    fn set_rejects_invalid_options() {
+        for line in [
+            "SET key value PX",
+            "SET key value PX 10 PXAT 10",
+            "SET key value UNKNOWN",
+        ] {
+            let Err(error) = parse_command(&args(line)) else {
+                panic!("{} should fail", line);
+            };
+            assert_eq!(error.to_string(), "ERR syntax error", "{}", line);
+        }
+    }
+
+    #[test]
+    fn keyspace_survives_a_panicking_command() {
+        let db = Db::new(None, Config::default());
+        let panicking = db.clone();
+        std::thread::spawn(move || {
+            let _keyspace = panicking.keyspace();
+            panic!("command panicked");
+        })
+        .join()
+        .unwrap_err();
+
+        run(&db, "SET key value");
+        assert_eq!(run(&db, "GET key").0, Message::bulk_string("value"));
+    }
+
+    #[test]
+    fn set_without_expiration_propagates_as_is() {
+        let db = Db::new(None, Config::default());
+
+        let (_, propagated) = run(&db, "SET key value");
+        assert_eq!(propagated, [command(&["SET", "key", "value"])]);
+    }
+
+    /// Returns the deadline of a propagated `PEXPIREAT key <deadline>`.
+    fn pexpireat_deadline(propagated: &[Message]) -> u128 {
+        let [Message::Array(Array { elements })] = propagated else {
+            panic!("Expected a single command, got {:?}", propagated);
+        };
+        assert_eq!(elements[..2], bulk_strings(&["PEXPIREAT", "key"]));
+        let Message::BulkString(BulkString { data }) = &elements[2] else {
+            panic!("Expected a deadline, got {:?}", elements[2]);
+        };
+
+        std::str::from_utf8(data).unwrap().parse().unwrap()
+    }
+
+    #[test]
+    fn relative_expirations_propagate_pexpireat() {
+        let db = Db::new(None, Config::default());
+        run(&db, "SET key value");
+
+        let start = now();
+        let (reply, propagated) = run(&db, "EXPIRE key 10");
+        assert_eq!(reply, Message::Integer(1));
+        assert!((start + 10_000..=now() + 10_000).contains(&pexpireat_deadline(&propagated)));
+
+        let start = now();
+        let (reply, propagated) = run(&db, "PEXPIRE key 500 LT");
+        assert_eq!(reply, Message::Integer(1));
+        assert!((start + 500..=now() + 500).contains(&pexpireat_deadline(&propagated)));
+
+        let (reply, propagated) = run(&db, "PEXPIRE key 1000 LT");
+        assert_eq!(reply, Message::Integer(0));
+        assert!(propagated.is_empty());
+
+        let (reply, propagated) = run(&db, "EXPIRE key 0");
+        assert_eq!(reply, Message::Integer(1));
+        assert_eq!(propagated, [command(&["DEL", "key"])]);
+        assert_eq!(run(&db, "GET key").0, Message::NullBulkString);
+    }
+
+    #[test]
+    fn write_commands_are_declared() {
+        for line in [
+            "SET key value",
+            "DEL key",
+            "EXPIRE key 1",
+            "PEXPIRE key 1",
+            "PEXPIREAT key 1",
+            "PFADD key a",
+            "PFMERGE key",
+            "FLUSHALL",
+            "GEOADD key 1 1 a",
+            "GEOSEARCHSTORE key source FROMLONLAT 1 1 BYRADIUS 1 km",
+        ] {
+            assert!(parse(line).is_write(), "{}", line);
+        }
+        for line in ["GET key", "PFCOUNT key", "PUBLISH channel message", "MULTI"] {
+            assert!(!parse(line).is_write(), "{}", line);
+        }
+    }
+
+    #[test]
+    fn expired_key_propagates_del() {
+        let db = Db::new(None, Config::default());
+        load_expired(&db, "key");
+
+        let (reply, propagated) = run(&db, "GET key");
+        assert_eq!(reply, Message::NullBulkString);
+        assert_eq!(propagated, [command(&["DEL", "key"])]);
+        assert_eq!(db.keyspace().entries().count(), 0);
+    }
+
+    #[test]
+    fn expired_key_propagates_del_before_the_write() {
+        let db = Db::new(None, Config::default());
+        load_expired(&db, "key");
+
+        let (_, propagated) = run(&db, "SET key new");
+        assert_eq!(
+            propagated,
+            [command(&["DEL", "key"]), command(&["SET", "key", "new"])]
+        );
+    }
+
+    #[test]
+    fn replica_keeps_expired_keys() {
+        let db = Db::new(Some(String::from("127.0.0.1 6379")), Config::default());
+        load_expired(&db, "key");
+
+        let (reply, propagated) = run(&db, "GET key");
+        assert_eq!(reply, Message::NullBulkString);
+        assert!(propagated.is_empty());
+        assert_eq!(db.keyspace().entries().count(), 1);
+    }
+
+    #[test]
+    fn pfcount_caches_without_writing() {
+        let db = Db::new(None, Config::default());
+        run(&db, "PFADD hll foo bar zap");
+        let dirty = db.keyspace().dirty();
+
+        let (reply, propagated) = run(&db, "PFCOUNT hll");
+        assert_eq!(reply, Message::Integer(3));
+        assert!(propagated.is_empty());
+        assert_eq!(db.keyspace().dirty(), dirty);
+
+        let mut keyspace = db.keyspace();
+        let value = keyspace.get("hll").unwrap().value.as_string().unwrap();
+        assert_eq!(value[8..16], 3_u64.to_le_bytes());
+    }
+
+    #[test]
+    fn replconf_ack_has_no_reply() {
+        let db = Db::new(None, Config::default());
+
+        let (reply, _) = run(&db, "REPLCONF ACK 42");
+        let mut bytes = Vec::new();
+        reply.serialize(&mut bytes);
+        assert!(bytes.is_empty());
+    }
+
+    // The replies of the GEOSEARCH examples of Redis.
+    #[test]
+    fn geoadd_rejects_nx_with_xx() {
+        let Err(error) = parse_command(&args("GEOADD g NX XX 1 1 m")) else {
+            panic!("GEOADD with NX and XX should fail");
+        };
+        assert_eq!(
+            error.to_string(),
+            "ERR XX and NX options at the same time are not compatible"
+        );
+    }
+
+    #[test]
+    fn geosearch() {
+        let db = Db::new(None, Config::default());
+        run(
+            &db,
+            "GEOADD Sicily 13.361389 38.115556 Palermo 15.087269 37.502669 Catania",
+        );
+        run(
+            &db,
+            "GEOADD Sicily 12.758489 38.788135 edge1 17.241510 38.788135 edge2",
+        );
+
+        let (reply, _) = run(&db, "GEOSEARCH Sicily FROMLONLAT 15 37 BYRADIUS 200 km ASC");
+        assert_eq!(reply, command(&["Catania", "Palermo"]));
+
+        let (reply, _) = run(
+            &db,
+            "GEOSEARCH Sicily FROMLONLAT 15 37 BYBOX 400 400 km ASC WITHDIST",
+        );
+        let results = [
+            ("Catania", "56.4413"),
+            ("Palermo", "190.4424"),
+            ("edge2", "279.7403"),
+            ("edge1", "279.7405"),
+        ];
+        assert_eq!(
+            reply,
+            Message::array(
+                results
+                    .iter()
+                    .map(|(member, distance)| command(&[member, distance]))
+                    .collect()
+            )
+        );
+    }
+
+    #[test]
+    fn loading_propagates_nothing() {
+        let db = Db::new(None, Config::default());
+        load_expired(&db, "key");
+        db.keyspace().set_loading(true);
+
+        let (_, propagated) = run(&db, "SET key value PX 1000");
+        assert!(propagated.is_empty());
+        assert_eq!(db.keyspace().dirty(), 0);
+    }
+
+    #[test]
+    fn exec_runs_the_queued_commands() {
+        let db = Db::new(None, Config::default());
+        let mut client = Client::new(&db);
+
+        assert_eq!(send(&db, &mut client, "MULTI"), Message::ok_message());
+        assert_eq!(send(&db, &mut client, "SET key value"), queued());
+        assert_eq!(send(&db, &mut client, "GET key"), queued());
+        assert!(db.keyspace().get("key").is_none());
+
+        let (reply, propagated) = run_as(&db, &mut client, "EXEC");
+        assert_eq!(
+            reply,
+            Message::array(vec![Message::ok_message(), Message::bulk_string("value")])
+        );
+        assert_eq!(
+            propagated,
+            vec![
+                command(&["MULTI"]),
+                command(&["SET", "key", "value"]),
+                command(&["EXEC"])
+            ]
+        );
+        assert_eq!(
+            send(&db, &mut client, "EXEC"),
+            error("ERR EXEC without MULTI")
+        );
+    }
+
+    #[test]
+    fn exec_aborts_after_a_rejected_command() {
+        let db = Db::new(None, Config::default());
+        let mut client = Client::new(&db);
+
+        send(&db, &mut client, "MULTI");
+        assert_eq!(send(&db, &mut client, "SET key value"), queued());
+        assert_eq!(
+            send(&db, &mut client, "SET key"),
+            error("ERR wrong number of arguments for 'set' command")
+        );
+        assert_eq!(
+            send(&db, &mut client, "EXEC"),
+            error("EXECABORT Transaction discarded because of previous errors.")
+        );
+        assert!(db.keyspace().get("key").is_none());
+        assert!(client.transaction.is_none());
+    }
+
+    #[test]
+    fn exec_reports_failing_commands_in_place() {
+        let db = Db::new(None, Config::default());
+        let mut client = Client::new(&db);
+        send(&db, &mut client, "GEOADD key 1 1 member");
+
+        send(&db, &mut client, "MULTI");
+        send(&db, &mut client, "GET key");
+        send(&db, &mut client, "SET other value");
+        assert_eq!(
+            send(&db, &mut client, "EXEC"),
+            Message::array(vec![
+                error("WRONGTYPE Operation against a key holding the wrong kind of value"),
+                Message::ok_message()
+            ])
+        );
+    }
+
+    #[test]
+    fn discard_drops_the_queued_commands() {
+        let db = Db::new(None, Config::default());
+        let mut client = Client::new(&db);
+
+        send(&db, &mut client, "MULTI");
+        send(&db, &mut client, "SET key value");
+        assert_eq!(send(&db, &mut client, "DISCARD"), Message::ok_message());
+        assert!(db.keyspace().get("key").is_none());
+        assert_eq!(
+            send(&db, &mut client, "DISCARD"),
+            error("ERR DISCARD without MULTI")
+        );
+    }
+
+    #[test]
+    fn exec_fails_when_a_watched_key_is_modified() {
+        let db = Db::new(None, Config::default());
+        let mut client = Client::new(&db);
+        let mut other = Client::new(&db);
+
+        assert_eq!(send(&db, &mut client, "WATCH key"), Message::ok_message());
+        send(&db, &mut other, "SET key other");
+        send(&db, &mut client, "MULTI");
+        send(&db, &mut client, "SET key value");
+        assert_eq!(send(&db, &mut client, "EXEC"), Message::NullArray);
+        assert_eq!(run(&db, "GET key").0, Message::bulk_string("other"));
+
+        // `EXEC` releases the watched keys.
+        send(&db, &mut other, "SET key again");
+        send(&db, &mut client, "MULTI");
+        send(&db, &mut client, "SET key value");
+        assert_eq!(
+            send(&db, &mut client, "EXEC"),
+            Message::array(vec![Message::ok_message()])
+        );
+    }
+
+    #[test]
+    fn exec_fails_when_a_watched_key_expires() {
+        let db = Db::new(None, Config::default());
+        let mut client = Client::new(&db);
+        db.keyspace().load(
+            String::from("key"),
+            Entry::with_expire_at(Value::String(b"value".to_vec()), now() + 50),
+        );
+
+        send(&db, &mut client, "WATCH key");
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        send(&db, &mut client, "MULTI");
+        assert_eq!(send(&db, &mut client, "EXEC"), Message::NullArray);
+    }
+
+    #[test]
+    fn unwatch_forgets_the_watched_keys() {
+        let db = Db::new(None, Config::default());
+        let mut client = Client::new(&db);
+        let mut other = Client::new(&db);
+
+        send(&db, &mut client, "WATCH key");
+        assert_eq!(send(&db, &mut client, "UNWATCH"), Message::ok_message());
+        send(&db, &mut other, "SET key other");
+        send(&db, &mut client, "MULTI");
+        assert_eq!(
+            send(&db, &mut client, "WATCH key"),
+            error("ERR WATCH inside MULTI is not allowed")
+        );
+        assert_eq!(send(&db, &mut client, "EXEC"), Message::array(Vec::new()));
+    }
+
+    /// Takes the messages pushed to the connection of `client`.
+    fn pushed(client: &mut Client) -> Vec<Message> {
+        std::iter::from_fn(|| client.pushed.try_recv().ok()).collect()
+    }
+
+    fn published(channel: &str, message: &str) -> Message {
+        Message::Push(bulk_strings(&["message", channel, message]))
+    }
+
+    #[test]
+    fn keyspace_notifications_are_published_when_enabled() {
+        let db = Db::new(None, Config::default());
+        let mut subscriber = Client::new(&db);
+        send(
+            &db,
+            &mut subscriber,
+            "SUBSCRIBE __keyspace@0__:key __keyevent@0__:set __keyevent@0__:del",
+        );
+        pushed(&mut subscriber);
+
+        send(&db, &mut Client::new(&db), "SET key value");
+        assert!(pushed(&mut subscriber).is_empty());
+
+        run(&db, "CONFIG SET notify-keyspace-events KE$");
+        send(&db, &mut Client::new(&db), "SET key value");
+        assert_eq!(
+            pushed(&mut subscriber),
+            vec![
+                published("__keyspace@0__:key", "set"),
+                published("__keyevent@0__:set", "key")
+            ]
+        );
+
+        // Generic events are not enabled.
+        send(&db, &mut Client::new(&db), "DEL key");
+        assert!(pushed(&mut subscriber).is_empty());
+    }
+
+    #[test]
+    fn expired_keys_are_notified() {
+        let db = Db::new(None, Config::default());
+        run(&db, "CONFIG SET notify-keyspace-events Kx");
+        let mut subscriber = Client::new(&db);
+        send(&db, &mut subscriber, "SUBSCRIBE __keyspace@0__:key");
+        pushed(&mut subscriber);
+
+        load_expired(&db, "key");
+        send(&db, &mut Client::new(&db), "GET key");
+        assert_eq!(
+            pushed(&mut subscriber),
+            vec![published("__keyspace@0__:key", "expired")]
+        );
+    }
+
+    #[test]
+    fn notify_keyspace_events_rejects_unknown_classes() {
+        let db = Db::new(None, Config::default());
+
+        assert_eq!(
+            send(
+                &db,
+                &mut Client::new(&db),
+                "CONFIG SET notify-keyspace-events KEy"
+            ),
+            error("ERR Invalid event class character 'y'. Use 'Ag$lshzxeKEtmn'.")
+        );
+    }
+
+    fn invalidated(keys: &[&str]) -> Message {
+        Message::Push(vec![
+            Message::bulk_string("invalidate"),
+            Message::array(bulk_strings(keys)),
+        ])
+    }
+
+    #[test]
+    fn tracking_invalidates_the_keys_read() {
+        let db = Db::new(None, Config::default());
+        let mut client = Client::new(&db);
+        let mut other = Client::new(&db);
+
+        assert_eq!(
+            send(&db, &mut client, "CLIENT TRACKING ON"),
+            Message::ok_message()
+        );
+        send(&db, &mut client, "GET key");
+        send(&db, &mut other, "SET other value");
+        assert!(pushed(&mut client).is_empty());
+
+        send(&db, &mut other, "SET key value");
+        assert_eq!(pushed(&mut client), vec![invalidated(&["key"])]);
+        // The key is tracked again once it is read again.
+        send(&db, &mut other, "SET key value");
+        assert!(pushed(&mut client).is_empty());
+
+        send(&db, &mut client, "GET key");
+        send(&db, &mut client, "DEL key");
+        assert_eq!(pushed(&mut client), vec![invalidated(&["key"])]);
+    }
+
+    #[test]
+    fn tracking_noloop_skips_own_writes() {
+        let db = Db::new(None, Config::default());
+        let mut client = Client::new(&db);
+
+        send(&db, &mut client, "CLIENT TRACKING ON NOLOOP");
+        send(&db, &mut client, "GET key");
+        send(&db, &mut client, "SET key value");
+        assert!(pushed(&mut client).is_empty());
+
+        send(&db, &mut client, "GET key");
+        send(&db, &mut Client::new(&db), "SET key value");
+        assert_eq!(pushed(&mut client), vec![invalidated(&["key"])]);
+    }
+
+    #[test]
+    fn tracking_bcast_invalidates_the_prefixes() {
+        let db = Db::new(None, Config::default());
+        let mut client = Client::new(&db);
+        let mut other = Client::new(&db);
+
+        send(&db, &mut client, "CLIENT TRACKING ON BCAST PREFIX user:");
+        send(&db, &mut other, "SET user:1 value");
+        send(&db, &mut other, "SET session:1 value");
+        assert_eq!(pushed(&mut client), vec![invalidated(&["user:1"])]);
+    }
+
+    #[test]
+    fn tracking_optin_tracks_after_caching_yes() {
+        let db = Db::new(None, Config::default());
+        let mut client = Client::new(&db);
+        let mut other = Client::new(&db);
+
+        send(&db, &mut client, "CLIENT TRACKING ON OPTIN");
+        send(&db, &mut client, "GET key");
+        send(&db, &mut other, "SET key value");
+        assert!(pushed(&mut client).is_empty());
+
+        assert_eq!(
+            send(&db, &mut client, "CLIENT CACHING YES"),
+            Message::ok_message()
+        );
+        send(&db, &mut client, "GET key");
+        send(&db, &mut other, "SET key value");
+        assert_eq!(pushed(&mut client), vec![invalidated(&["key"])]);
+    }
+
+    #[tokio::test]
+    async fn wait_blocks_until_the_replicas_acknowledge_the_writes() {
+        let db = Db::new(None, Config::default());
+        let mut client = Client::new(&db);
+        let replica = Client::new(&db);
+        db.replicas()
+            .register(replica.id, String::from("127.0.0.1"), 6380);
+
+        // Nothing was written yet.
+        assert_eq!(send(&db, &mut client, "WAIT 1 0"), Message::Integer(1));
+        assert!(client.wait.is_none());
+
+        send(&db, &mut client, "SET key value");
+        let offset = client.write_offset;
+        assert_eq!(offset, db.replication_offset());
+        assert_eq!(send(&db, &mut client, "WAIT 1 50"), Message::Integer(0));
+        let wait = client.wait.take().expect("WAIT should block");
+        assert_eq!(replication::wait_for_acks(&db, wait).await, 0);
+
+        send(&db, &mut client, "WAIT 1 0");
+        let wait = client.wait.take().expect("WAIT should block");
+        assert_eq!(wait.timeout, None);
+        let acks = db.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            acks.replicas().ack(replica.id, offset);
+        });
+        assert_eq!(replication::wait_for_acks(&db, wait).await, 1);
+        assert_eq!(send(&db, &mut client, "WAIT 1 0"), Message::Integer(1));
+    }
+
+    #[test]
+    fn wait_fails_on_replicas() {
+        let db = Db::new(None, Config::default());
+        run(&db, "REPLICAOF localhost 6390");
+
+        assert_eq!(
+            send(&db, &mut Client::new(&db), "WAIT 1 0"),
+            error("ERR WAIT cannot be used with replica instances. Please also note that writes to replicas are just local and are not propagated.")
+        );
+    }
+
+    #[test]
+    fn psync_continues_from_the_backlog() {
+        let db = Db::new(None, Config::default());
+        let id = db.replication().id.clone();
+        let mut replica = Client::new(&db);
+
+        assert_eq!(
+            send(&db, &mut replica, "PSYNC ? -1"),
+            Message::simple_string(format!("FULLRESYNC {} 0", id))
+        );
+        assert!(matches!(replica.resync, Some(Resync::Full { .. })));
+
+        send(&db, &mut Client::new(&db), "SET key value");
+        let mut set = Vec::new();
+        command(&["SET", "key", "value"]).serialize(&mut set);
+
+        // The offset of the next byte the replica needs.
+        let mut replica = Client::new(&db);
+        assert_eq!(
+            send(&db, &mut replica, &format!("PSYNC {} 1", id)),
+            Message::simple_string(format!("CONTINUE {}", id))
+        );
+        let Some(Resync::Partial { missing, .. }) = &replica.resync else {
+            panic!("PSYNC should continue");
+        };
+        assert_eq!(*missing, set);
+
+        for line in [
+            format!("PSYNC {} {}", id, set.len() + 2),
+            format!("PSYNC {} 1", "b".repeat(40)),
+        ] {
+            let mut replica = Client::new(&db);
+            assert_eq!(
+                send(&db, &mut replica, &line),
+                Message::simple_string(format!("FULLRESYNC {} {}", id, set.len()))
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn replicaof_follows_a_new_master() {
+        let db = Db::new(None, Config::default());
+        let id = db.replication().id.clone();
+        let output = db.replication().subscribe();
+        let mut role_changes = db.replication().subscribe_role_changes();
+
+        assert_eq!(
+            run(&db, "REPLICAOF localhost 6390").0,
+            Message::ok_message()
+        );
+        assert_eq!(
+            db.replication().role,
+            Role::Replica {
+                master_address: String::from("localhost:6390")
+            }
+        );
+        assert!(role_changes.has_changed().unwrap());
+        // The replicas of this server follow the stream of the new master.
+        assert_eq!(
+            output.next().await.unwrap_err().to_string(),
+            "Replication stream changed"
+        );
+        // The stream is kept so the new master can continue from it.
+        assert!(db.replication().has_backlog());
+        assert_eq!(db.replication().id, id);
+
+        role_changes.borrow_and_update();
+        assert_eq!(
+            run(&db, "SLAVEOF localhost 6390").0,
+            Message::simple_string(String::from("OK Already connected to specified master"))
+        );
+        assert!(!role_changes.has_changed().unwrap());
+    }
+
+    #[test]
+    fn replicaof_no_one_promotes_to_master() {
+        let db = Db::new(None, Config::default());
+        assert_eq!(run(&db, "REPLICAOF NO ONE").0, Message::ok_message());
+        let id = db.replication().id.clone();
+
+        run(&db, "REPLICAOF localhost 6390");
+        db.replication().feed(b"stream", 100);
+        assert_eq!(run(&db, "REPLICAOF no one").0, Message::ok_message());
+
+        let replication = db.replication();
+        assert!(replication.is_master());
+        assert_ne!(replication.id, id);
+        // Replicas of the previous master can continue from this server.
+        assert_eq!(replication.previous_id, id);
+        assert_eq!(replication.previous_offset, Some(6));
+    }
+
+    #[test]
+    fn replicaof_rejects_invalid_ports() {
+        for line in ["REPLICAOF localhost port", "REPLICAOF localhost 65536"] {
+            let Err(error) = parse_command(&args(line)) else {
+                panic!("{} should fail", line);
+            };
+            assert_eq!(error.to_string(), "ERR Invalid master port");
+        }
+    }
+
+    #[test]
+    fn replicas_reject_writes_from_their_clients() {
+        let db = Db::new(None, Config::default());
+        run(&db, "REPLICAOF localhost 6390");
+        let mut client = Client::new(&db);
+        let readonly = error("READONLY You can't write against a read only replica.");
+
+        assert_eq!(send(&db, &mut client, "SET key value"), readonly);
+        assert_eq!(send(&db, &mut client, "PEXPIRE key 100"), readonly);
+        assert_eq!(send(&db, &mut client, "GET key"), Message::NullBulkString);
+
+        send(&db, &mut client, "MULTI");
+        assert_eq!(send(&db, &mut client, "GEOADD key 1 1 member"), readonly);
+        assert_eq!(
+            send(&db, &mut client, "EXEC"),
+            error("EXECABORT Transaction discarded because of previous errors.")
+        );
+
+        // The writes of the master are applied.
+        let mut master = Client::new_master_link(&db);
+        send(&db, &mut master, "SET key master");
+        assert_eq!(
+            send(&db, &mut client, "GET key"),
+            Message::bulk_string("master")
+        );
+
+        send(&db, &mut client, "CONFIG SET replica-read-only no");
+        assert_eq!(
+            send(&db, &mut client, "SET key value"),
+            Message::ok_message()
+        );
+    }
+
+    #[test]
+    fn writes_need_enough_good_replicas() {
+        let db = Db::new(None, Config::default());
+        let mut client = Client::new(&db);
+
+        send(&db, &mut client, "CONFIG SET min-replicas-to-write 1");
+        assert_eq!(
+            send(&db, &mut client, "SET key value"),
+            error("NOREPLICAS Not enough good replicas to write.")
+        );
+        assert_eq!(send(&db, &mut client, "GET key"), Message::NullBulkString);
+
+        let replica = Client::new(&db);
+        db.replicas()
+            .register(replica.id, String::from("127.0.0.1"), 6380);
+        assert_eq!(
+            send(&db, &mut client, "SET key value"),
+            Message::ok_message()
+        );
+
+        send(&db, &mut client, "CONFIG SET min-replicas-to-write 2");
+        assert_eq!(
+            send(&db, &mut client, "DEL key"),
+            error("NOREPLICAS Not enough good replicas to write.")
+        );
+        send(&db, &mut client, "CONFIG SET min-replicas-to-write 0");
+        assert_eq!(send(&db, &mut client, "DEL key"), Message::Integer(1));
     }
 }
@@ -0,0 +1,38 @@
+use std::fmt;
+
+use crate::message::Message;
+
+use super::{Command, CommandArgs, ExecutionContext};
+
+/// Rewrites the AOF in the background, replacing its files with a snapshot of the dataset.
+#[derive(Debug)]
+pub(crate) struct BgRewriteAofCommand;
+
+impl fmt::Display for BgRewriteAofCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "BGREWRITEAOF")
+    }
+}
+
+impl Command for BgRewriteAofCommand {
+    fn new(args: CommandArgs) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            args.is_empty(),
+            "ERR wrong number of arguments for 'bgrewriteaof' command"
+        );
+
+        Ok(Self)
+    }
+
+    fn to_message(&self) -> Message {
+        Message::array(vec![Message::bulk_string("BGREWRITEAOF")])
+    }
+
+    fn execute(&self, context: &mut ExecutionContext) -> anyhow::Result<Message> {
+        context.db.rewrite_aof(context.keyspace)?;
+
+        Ok(Message::simple_string(String::from(
+            "Background append only file rewriting started",
+        )))
+    }
+}
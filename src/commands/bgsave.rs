@@ -0,0 +1,38 @@
+use std::fmt;
+
+use crate::message::Message;
+
+use super::{Command, CommandArgs, ExecutionContext};
+
+/// Writes the dataset to the RDB file in the background.
+#[derive(Debug)]
+pub(crate) struct BgSaveCommand;
+
+impl fmt::Display for BgSaveCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "BGSAVE")
+    }
+}
+
+impl Command for BgSaveCommand {
+    fn new(args: CommandArgs) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            args.is_empty(),
+            "ERR wrong number of arguments for 'bgsave' command"
+        );
+
+        Ok(Self)
+    }
+
+    fn to_message(&self) -> Message {
+        Message::array(vec![Message::bulk_string("BGSAVE")])
+    }
+
+    fn execute(&self, context: &mut ExecutionContext) -> anyhow::Result<Message> {
+        context.db.bgsave(context.keyspace)?;
+
+        Ok(Message::simple_string(String::from(
+            "Background saving started",
+        )))
+    }
+}
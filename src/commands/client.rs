@@ -0,0 +1,192 @@
+use std::fmt;
+
+use anyhow::Context;
+
+use crate::{message::Message, tracking::TrackingOptions};
+
+use super::{Command, CommandArgs, ExecutionContext};
+
+#[derive(Debug)]
+enum Subcommand {
+    Id,
+    Tracking(Option<TrackingOptions>),
+    Caching(bool),
+    GetRedir,
+}
+
+#[derive(Debug)]
+pub(crate) struct ClientCommand {
+    subcommand: Subcommand,
+}
+
+impl fmt::Display for ClientCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "CLIENT {:?}", self.subcommand)
+    }
+}
+
+impl Command for ClientCommand {
+    fn new(args: CommandArgs) -> anyhow::Result<Self> {
+        let subcommand = args
+            .first()
+            .context("ERR wrong number of arguments for 'client' command")?
+            .to_string()
+            .to_lowercase();
+        let subcommand_args = &args[1..];
+
+        let subcommand = match (subcommand.as_str(), subcommand_args) {
+            ("id", []) => Subcommand::Id,
+            ("getredir", []) => Subcommand::GetRedir,
+            ("caching", [mode]) => match mode.to_string().to_lowercase().as_str() {
+                "yes" => Subcommand::Caching(true),
+                "no" => Subcommand::Caching(false),
+                _ => anyhow::bail!("ERR syntax error"),
+            },
+            ("tracking", [mode, options @ ..]) => match mode.to_string().to_lowercase().as_str() {
+                "on" => Subcommand::Tracking(Some(parse_tracking_options(options)?)),
+                "off" => Subcommand::Tracking(None),
+                _ => anyhow::bail!("ERR syntax error"),
+            },
+            ("id" | "getredir" | "caching" | "tracking", _) => anyhow::bail!(
+                "ERR wrong number of arguments for 'client|{}' command",
+                subcommand
+            ),
+            (subcommand, _) => {
+                anyhow::bail!("ERR unknown subcommand '{}'. Try CLIENT HELP.", subcommand)
+            }
+        };
+
+        Ok(Self { subcommand })
+    }
+
+    fn to_message(&self) -> Message {
+        let mut elements = vec![Message::bulk_string("CLIENT")];
+
+        match &self.subcommand {
+            Subcommand::Id => elements.push(Message::bulk_string("ID")),
+            Subcommand::GetRedir => elements.push(Message::bulk_string("GETREDIR")),
+            Subcommand::Caching(yes) => {
+                elements.push(Message::bulk_string("CACHING"));
+                elements.push(Message::bulk_string(if *yes { "YES" } else { "NO" }));
+            }
+            Subcommand::Tracking(None) => {
+                elements.push(Message::bulk_string("TRACKING"));
+                elements.push(Message::bulk_string("OFF"));
+            }
+            Subcommand::Tracking(Some(options)) => {
+                elements.push(Message::bulk_string("TRACKING"));
+                elements.push(Message::bulk_string("ON"));
+                if let Some(redirect) = options.redirect {
+                    elements.push(Message::bulk_string("REDIRECT"));
+                    elements.push(Message::bulk_string(redirect.to_string()));
+                }
+                for prefix in &options.prefixes {
+                    elements.push(Message::bulk_string("PREFIX"));
+                    elements.push(Message::bulk_string(prefix.clone()));
+                }
+                for (enabled, option) in [
+                    (options.bcast, "BCAST"),
+                    (options.optin, "OPTIN"),
+                    (options.optout, "OPTOUT"),
+                    (options.noloop, "NOLOOP"),
+                ] {
+                    if enabled {
+                        elements.push(Message::bulk_string(option));
+                    }
+                }
+            }
+        }
+
+        Message::array(elements)
+    }
+
+    fn execute(&self, context: &mut ExecutionContext) -> anyhow::Result<Message> {
+        let client_id = context.client.id;
+
+        match &self.subcommand {
+            Subcommand::Id => Ok(Message::Integer(client_id as i64)),
+            Subcommand::GetRedir => {
+                let tracking = context.db.tracking();
+                let redirect = match tracking.options(client_id) {
+                    Some(options) => options.redirect.map_or(0, |redirect| redirect as i64),
+                    None => -1,
+                };
+
+                Ok(Message::Integer(redirect))
+            }
+            Subcommand::Caching(yes) => {
+                let tracking = context.db.tracking();
+                let options = tracking
+                    .options(client_id)
+                    .filter(|options| options.optin || options.optout)
+                    .context("ERR CLIENT CACHING can be called only when the client is in tracking mode with OPTIN or OPTOUT mode enabled")?;
+                anyhow::ensure!(
+                    !*yes || options.optin,
+                    "ERR CLIENT CACHING YES is only valid when tracking is enabled in OPTIN mode."
+                );
+                anyhow::ensure!(
+                    *yes || options.optout,
+                    "ERR CLIENT CACHING NO is only valid when tracking is enabled in OPTOUT mode."
+                );
+
+                context.client.caching = Some(*yes);
+
+                Ok(Message::ok_message())
+            }
+            Subcommand::Tracking(Some(options)) => {
+                context.db.tracking().enable(client_id, options.clone())?;
+
+                Ok(Message::ok_message())
+            }
+            Subcommand::Tracking(None) => {
+                context.db.tracking().disable(client_id);
+
+                Ok(Message::ok_message())
+            }
+        }
+    }
+}
+
+fn parse_tracking_options(args: CommandArgs) -> anyhow::Result<TrackingOptions> {
+    let mut options = TrackingOptions::default();
+
+    let mut args = args.iter();
+    while let Some(option) = args.next() {
+        match option.to_string().to_lowercase().as_str() {
+            "redirect" => {
+                let redirect = args.next().context("ERR syntax error")?;
+                options.redirect = Some(
+                    redirect
+                        .to_string()
+                        .parse()
+                        .ok()
+                        .context("ERR value is not an integer or out of range")?,
+                );
+            }
+            "prefix" => {
+                let prefix = args.next().context("ERR syntax error")?;
+                options.prefixes.push(prefix.data.clone());
+            }
+            "bcast" => options.bcast = true,
+            "optin" => options.optin = true,
+            "optout" => options.optout = true,
+            "noloop" => options.noloop = true,
+            _ => anyhow::bail!("ERR syntax error"),
+        }
+    }
+
+    anyhow::ensure!(
+        options.bcast || options.prefixes.is_empty(),
+        "ERR PREFIX option requires BCAST mode to be enabled"
+    );
+    anyhow::ensure!(
+        !(options.optin && options.optout),
+        "ERR You can't use both OPTIN and OPTOUT"
+    );
+    anyhow::ensure!(
+        !options.bcast || !(options.optin || options.optout),
+        "ERR OPTIN and OPTOUT are not compatible with BCAST"
+    );
+
+    Ok(options)
+}
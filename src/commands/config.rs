@@ -0,0 +1,136 @@
+use std::fmt;
+
+use anyhow::Context;
+
+use crate::{config::Config, message::Message};
+
+use super::{Command, CommandArgs, ExecutionContext};
+
+#[derive(Debug)]
+enum Subcommand {
+    Get(Vec<String>),
+    Set(Vec<(String, String)>),
+}
+
+#[derive(Debug)]
+pub(crate) struct ConfigCommand {
+    subcommand: Subcommand,
+}
+
+impl fmt::Display for ConfigCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "CONFIG {:?}", self.subcommand)
+    }
+}
+
+impl Command for ConfigCommand {
+    fn new(args: CommandArgs) -> anyhow::Result<Self> {
+        let subcommand = args
+            .first()
+            .context("ERR wrong number of arguments for 'config' command")?
+            .to_string()
+            .to_lowercase();
+        let subcommand_args = &args[1..];
+
+        let subcommand = match subcommand.as_str() {
+            "get" => {
+                anyhow::ensure!(
+                    !subcommand_args.is_empty(),
+                    "ERR wrong number of arguments for 'config|get' command"
+                );
+
+                Subcommand::Get(subcommand_args.iter().map(|arg| arg.to_string()).collect())
+            }
+            "set" => {
+                let pairs = subcommand_args.chunks_exact(2);
+                anyhow::ensure!(
+                    !subcommand_args.is_empty() && pairs.remainder().is_empty(),
+                    "ERR wrong number of arguments for 'config|set' command"
+                );
+
+                Subcommand::Set(
+                    pairs
+                        .map(|pair| (pair[0].to_string(), pair[1].to_string()))
+                        .collect(),
+                )
+            }
+            subcommand => {
+                anyhow::bail!("ERR unknown subcommand '{}'. Try CONFIG HELP.", subcommand)
+            }
+        };
+
+        Ok(Self { subcommand })
+    }
+
+    fn to_message(&self) -> Message {
+        let mut elements = vec![Message::bulk_string("CONFIG")];
+
+        match &self.subcommand {
+            Subcommand::Get(patterns) => {
+                elements.push(Message::bulk_string("GET"));
+                elements.extend(
+                    patterns
+                        .iter()
+                        .map(|pattern| Message::bulk_string(pattern.clone())),
+                );
+            }
+            Subcommand::Set(parameters) => {
+                elements.push(Message::bulk_string("SET"));
+                for (name, value) in parameters {
+                    elements.push(Message::bulk_string(name.clone()));
+                    elements.push(Message::bulk_string(value.clone()));
+                }
+            }
+        }
+
+        Message::array(elements)
+    }
+
+    fn execute(&self, context: &mut ExecutionContext) -> anyhow::Result<Message> {
+        let mut config = context.db.config();
+
+        match &self.subcommand {
+            Subcommand::Get(patterns) => {
+                let mut elements = Vec::new();
+                for pattern in patterns {
+                    for (name, value) in config.get(pattern) {
+                        if !elements.contains(&Message::bulk_string(name)) {
+                            elements.push(Message::bulk_string(name));
+                            elements.push(Message::bulk_string(value));
+                        }
+                    }
+                }
+
+                Ok(Message::array(elements))
+            }
+            Subcommand::Set(parameters) => {
+                let appendonly = config.appendonly;
+                for (name, value) in parameters {
+                    anyhow::ensure!(
+                        !Config::is_immutable(name),
+                        "ERR CONFIG SET failed (possibly related to argument '{}') - can't set immutable config",
+                        name.to_lowercase()
+                    );
+                    config.set(name, value)?;
+                }
+
+                // Turning the AOF on writes the dataset as its base, which reads the config.
+                let enable_aof = config.appendonly;
+                if enable_aof == appendonly {
+                    return Ok(Message::ok_message());
+                }
+                drop(config);
+
+                if !enable_aof {
+                    context.db.disable_aof();
+                } else if let Err(err) = context.db.enable_aof(context.keyspace) {
+                    context.db.disable_aof();
+                    context.db.config().appendonly = false;
+                    return Err(err);
+                }
+
+                Ok(Message::ok_message())
+            }
+        }
+    }
+}
@@ -0,0 +1,72 @@
+use std::fmt;
+
+use crate::{message::Message, notify::EventClass};
+
+use super::{Command, CommandArgs, ExecutionContext};
+
+#[derive(Debug)]
+pub(crate) struct DelCommand {
+    keys: Vec<String>,
+}
+
+impl DelCommand {
+    pub(crate) fn new_command(keys: Vec<String>) -> Self {
+        Self { keys }
+    }
+}
+
+impl fmt::Display for DelCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "DEL {}", self.keys.join(" "))
+    }
+}
+
+impl Command for DelCommand {
+    fn new(args: CommandArgs) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            !args.is_empty(),
+            "ERR wrong number of arguments for 'del' command"
+        );
+
+        Ok(Self {
+            keys: args
+                .iter()
+                .map(|key| key.key())
+                .collect::<anyhow::Result<_>>()?,
+        })
+    }
+
+    fn to_message(&self) -> Message {
+        let mut elements = vec![Message::bulk_string("DEL")];
+        elements.extend(
+            self.keys
+                .iter()
+                .map(|key| Message::bulk_string(key.clone())),
+        );
+
+        Message::array(elements)
+    }
+
+    fn is_write(&self) -> bool {
+        true
+    }
+
+    fn execute(&self, context: &mut ExecutionContext) -> anyhow::Result<Message> {
+        let mut removed = 0;
+        for key in &self.keys {
+            // Expired keys are not counted as removed, but replicas still delete the ones their
+            // master deletes.
+            let exists = context.keyspace.get(key).is_some();
+            if context.keyspace.remove(key) && exists {
+                context.keyspace.notify(EventClass::Generic, "del", key);
+                removed += 1;
+            }
+        }
+
+        if removed > 0 {
+            context.propagate_command(self);
+        }
+
+        Ok(Message::Integer(removed))
+    }
+}
@@ -0,0 +1,42 @@
+use std::fmt;
+
+use anyhow::Context;
+
+use crate::message::Message;
+
+use super::{Command, CommandArgs, ExecutionContext};
+
+#[derive(Debug)]
+pub(crate) struct DiscardCommand;
+
+impl fmt::Display for DiscardCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "DISCARD")
+    }
+}
+
+impl Command for DiscardCommand {
+    fn new(args: CommandArgs) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            args.is_empty(),
+            "ERR wrong number of arguments for 'discard' command"
+        );
+
+        Ok(Self)
+    }
+
+    fn to_message(&self) -> Message {
+        Message::array(vec![Message::bulk_string(String::from("DISCARD"))])
+    }
+
+    fn execute(&self, context: &mut ExecutionContext) -> anyhow::Result<Message> {
+        context
+            .client
+            .transaction
+            .take()
+            .context("ERR DISCARD without MULTI")?;
+        context.client.watched_keys.unwatch(context.keyspace);
+
+        Ok(Message::ok_message())
+    }
+}
@@ -1,15 +1,8 @@
 use std::fmt;
 
-use anyhow::Context;
-use async_trait::async_trait;
-use tokio::{
-    io::{BufWriter, WriteHalf},
-    net::TcpStream,
-};
+use crate::message::Message;
 
-use crate::{db::Db, message::Message};
-
-use super::{Command, CommandArgs};
+use super::{Command, CommandArgs, ExecutionContext};
 
 #[derive(Debug)]
 pub(crate) struct EchoCommand {
@@ -22,15 +15,15 @@ impl fmt::Display for EchoCommand {
     }
 }
 
-#[async_trait]
 impl Command for EchoCommand {
     fn new(args: CommandArgs) -> anyhow::Result<Self> {
-        let message = args
-            .first()
-            .expect("ECHO message should have reply message")
-            .to_string();
+        let [message] = args else {
+            anyhow::bail!("ERR wrong number of arguments for 'echo' command");
+        };
 
-        Ok(Self { message })
+        Ok(Self {
+            message: message.to_string(),
+        })
     }
 
     fn to_message(&self) -> Message {
@@ -42,17 +35,7 @@ impl Command for EchoCommand {
         Message::array(elements)
     }
 
-    async fn handle(
-        &self,
-        writer: &mut BufWriter<WriteHalf<TcpStream>>,
-        _: &Db,
-    ) -> anyhow::Result<()> {
-        let message = Message::simple_string(self.message.to_string());
-        message
-            .send(writer)
-            .await
-            .context("Failed to send ECHO reply")?;
-
-        Ok(())
+    fn execute(&self, _: &mut ExecutionContext) -> anyhow::Result<Message> {
+        Ok(Message::simple_string(self.message.to_string()))
     }
 }
@@ -0,0 +1,67 @@
+use std::fmt;
+
+use anyhow::Context;
+
+use crate::message::Message;
+
+use super::{Command, CommandArgs, ExecutionContext};
+
+#[derive(Debug)]
+pub(crate) struct ExecCommand;
+
+impl fmt::Display for ExecCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "EXEC")
+    }
+}
+
+impl Command for ExecCommand {
+    fn new(args: CommandArgs) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            args.is_empty(),
+            "ERR wrong number of arguments for 'exec' command"
+        );
+
+        Ok(Self)
+    }
+
+    fn to_message(&self) -> Message {
+        Message::array(vec![Message::bulk_string(String::from("EXEC"))])
+    }
+
+    fn execute(&self, context: &mut ExecutionContext) -> anyhow::Result<Message> {
+        let transaction = context
+            .client
+            .transaction
+            .take()
+            .context("ERR EXEC without MULTI")?;
+
+        let dirty = context.client.watched_keys.is_dirty(context.keyspace);
+        context.client.watched_keys.unwatch(context.keyspace);
+
+        anyhow::ensure!(
+            !transaction.is_aborted(),
+            "EXECABORT Transaction discarded because of previous errors."
+        );
+        if dirty {
+            return Ok(Message::NullArray);
+        }
+
+        // Replicas apply the whole transaction atomically too.
+        let start = context.propagated_len();
+        let replies = transaction
+            .commands()
+            .iter()
+            .map(|command| {
+                command
+                    .execute(context)
+                    .unwrap_or_else(|err| Message::error(&err))
+            })
+            .collect();
+        context.wrap_propagated_in_transaction(start);
+        // A transaction never blocks, `WAIT` replies right away.
+        context.client.wait = None;
+
+        Ok(Message::array(replies))
+    }
+}
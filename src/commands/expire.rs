@@ -0,0 +1,57 @@
+use std::fmt;
+
+use anyhow::Context;
+
+use crate::message::Message;
+
+use super::{
+    pexpireat::{Conditions, PExpireAtCommand},
+    Command, CommandArgs, ExecutionContext,
+};
+
+/// Sets the number of seconds after which a key expires, replicated as a `PEXPIREAT`.
+#[derive(Debug)]
+pub(crate) struct ExpireCommand {
+    key: String,
+    seconds: i64,
+    conditions: Conditions,
+}
+
+impl fmt::Display for ExpireCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "EXPIRE {} {}", self.key, self.seconds)
+    }
+}
+
+impl Command for ExpireCommand {
+    fn new(args: CommandArgs) -> anyhow::Result<Self> {
+        let (key, seconds, conditions) = PExpireAtCommand::parse_args("expire", args)?;
+
+        Ok(Self {
+            key,
+            seconds,
+            conditions,
+        })
+    }
+
+    fn to_message(&self) -> Message {
+        Message::array(vec![
+            Message::bulk_string("EXPIRE"),
+            Message::bulk_string(self.key.clone()),
+            Message::bulk_string(self.seconds.to_string()),
+        ])
+    }
+
+    fn is_write(&self) -> bool {
+        true
+    }
+
+    fn execute(&self, context: &mut ExecutionContext) -> anyhow::Result<Message> {
+        let expire_at = self
+            .seconds
+            .checked_mul(1000)
+            .and_then(|milliseconds| milliseconds.checked_add(context.now as i64))
+            .context("ERR invalid expire time in 'expire' command")?;
+        PExpireAtCommand::new_command(self.key.clone(), expire_at, self.conditions).execute(context)
+    }
+}
@@ -0,0 +1,43 @@
+use std::fmt;
+
+use crate::message::Message;
+
+use super::{Command, CommandArgs, ExecutionContext};
+
+#[derive(Debug)]
+pub(crate) struct FlushAllCommand;
+
+impl fmt::Display for FlushAllCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "FLUSHALL")
+    }
+}
+
+impl Command for FlushAllCommand {
+    fn new(args: CommandArgs) -> anyhow::Result<Self> {
+        // Keys are always removed synchronously, so ASYNC and SYNC are accepted and ignored.
+        for arg in args {
+            match arg.to_string().to_lowercase().as_str() {
+                "async" | "sync" => {}
+                _ => anyhow::bail!("ERR syntax error"),
+            }
+        }
+
+        Ok(Self)
+    }
+
+    fn to_message(&self) -> Message {
+        Message::array(vec![Message::bulk_string(String::from("FLUSHALL"))])
+    }
+
+    fn is_write(&self) -> bool {
+        true
+    }
+
+    fn execute(&self, context: &mut ExecutionContext) -> anyhow::Result<Message> {
+        context.keyspace.clear();
+        context.propagate_command(self);
+
+        Ok(Message::ok_message())
+    }
+}
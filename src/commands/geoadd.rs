@@ -0,0 +1,151 @@
+use std::fmt;
+
+use crate::{
+    db::{Entry, Value},
+    geo::Coordinates,
+    message::Message,
+    notify::EventClass,
+    sorted_set::SortedSet,
+};
+
+use super::{Command, CommandArgs, ExecutionContext};
+
+#[derive(Debug, PartialEq)]
+enum Condition {
+    OnlyNew,
+    OnlyExisting,
+}
+
+#[derive(Debug)]
+pub(crate) struct GeoAddCommand {
+    key: String,
+    condition: Option<Condition>,
+    changed: bool,
+    items: Vec<(Coordinates, Vec<u8>)>,
+}
+
+impl fmt::Display for GeoAddCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "GEOADD {}", self.key)
+    }
+}
+
+impl GeoAddCommand {
+    fn add(&self, context: &mut ExecutionContext) -> anyhow::Result<i64> {
+        let mut entry = context
+            .keyspace
+            .get(&self.key)
+            .cloned()
+            .unwrap_or_else(|| Entry::new(Value::SortedSet(SortedSet::new()), None));
+        let set = entry.value.as_sorted_set_mut()?;
+
+        let mut added = 0;
+        let mut updated = 0;
+        for (coordinates, member) in &self.items {
+            let score = coordinates.to_score();
+            match set.score(member) {
+                Some(_) if self.condition == Some(Condition::OnlyNew) => continue,
+                None if self.condition == Some(Condition::OnlyExisting) => continue,
+                Some(previous) if previous == score => continue,
+                Some(_) => updated += 1,
+                None => added += 1,
+            }
+            set.insert(member.clone(), score);
+        }
+
+        if added + updated > 0 {
+            context.keyspace.insert(self.key.to_string(), entry);
+            context
+                .keyspace
+                .notify(EventClass::SortedSet, "zadd", &self.key);
+            context.propagate_command(self);
+        }
+
+        Ok(if self.changed { added + updated } else { added })
+    }
+}
+
+impl Command for GeoAddCommand {
+    fn new(args: CommandArgs) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            args.len() >= 4,
+            "ERR wrong number of arguments for 'geoadd' command"
+        );
+        let key = args[0].key()?;
+
+        let (mut nx, mut xx, mut changed) = (false, false, false);
+        let mut index = 1;
+        while let Some(option) = args.get(index) {
+            match option.to_string().to_lowercase().as_str() {
+                "nx" => nx = true,
+                "xx" => xx = true,
+                "ch" => changed = true,
+                _ => break,
+            }
+            index += 1;
+        }
+        let condition = match (nx, xx) {
+            (true, true) => {
+                anyhow::bail!("ERR XX and NX options at the same time are not compatible")
+            }
+            (true, false) => Some(Condition::OnlyNew),
+            (false, true) => Some(Condition::OnlyExisting),
+            (false, false) => None,
+        };
+
+        let items = args[index..].chunks_exact(3);
+        anyhow::ensure!(
+            index < args.len() && items.remainder().is_empty(),
+            "ERR syntax error"
+        );
+        let items = items
+            .map(|item| {
+                Ok((
+                    Coordinates::parse(&item[0], &item[1])?,
+                    item[2].data.clone(),
+                ))
+            })
+            .collect::<anyhow::Result<_>>()?;
+
+        Ok(Self {
+            key,
+            condition,
+            changed,
+            items,
+        })
+    }
+
+    fn to_message(&self) -> Message {
+        let mut elements = vec![
+            Message::bulk_string(String::from("GEOADD")),
+            Message::bulk_string(self.key.to_string()),
+        ];
+
+        match self.condition {
+            Some(Condition::OnlyNew) => elements.push(Message::bulk_string(String::from("NX"))),
+            Some(Condition::OnlyExisting) => {
+                elements.push(Message::bulk_string(String::from("XX")))
+            }
+            None => {}
+        }
+        if self.changed {
+            elements.push(Message::bulk_string(String::from("CH")));
+        }
+
+        for (coordinates, member) in &self.items {
+            elements.push(Message::bulk_string(coordinates.longitude.to_string()));
+            elements.push(Message::bulk_string(coordinates.latitude.to_string()));
+            elements.push(Message::bulk_string(member.clone()));
+        }
+
+        Message::array(elements)
+    }
+
+    fn is_write(&self) -> bool {
+        true
+    }
+
+    fn execute(&self, context: &mut ExecutionContext) -> anyhow::Result<Message> {
+        Ok(Message::Integer(self.add(context)?))
+    }
+}
@@ -0,0 +1,80 @@
+use std::fmt;
+
+use crate::{
+    geo::{self, Coordinates, Unit},
+    message::Message,
+};
+
+use super::{Command, CommandArgs, ExecutionContext};
+
+#[derive(Debug)]
+pub(crate) struct GeoDistCommand {
+    key: String,
+    member_1: Vec<u8>,
+    member_2: Vec<u8>,
+    unit: Unit,
+}
+
+impl fmt::Display for GeoDistCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "GEODIST {}", self.key)
+    }
+}
+
+impl GeoDistCommand {
+    fn distance(&self, context: &mut ExecutionContext) -> anyhow::Result<Option<f64>> {
+        let Some(entry) = context.keyspace.lookup_read(&self.key) else {
+            return Ok(None);
+        };
+        let set = entry.value.as_sorted_set()?;
+
+        let (Some(score_1), Some(score_2)) = (set.score(&self.member_1), set.score(&self.member_2))
+        else {
+            return Ok(None);
+        };
+
+        Ok(Some(
+            Coordinates::from_score(score_1).distance(Coordinates::from_score(score_2)),
+        ))
+    }
+}
+
+impl Command for GeoDistCommand {
+    fn new(args: CommandArgs) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            (3..=4).contains(&args.len()),
+            "ERR wrong number of arguments for 'geodist' command"
+        );
+
+        let unit = match args.get(3) {
+            Some(unit) => Unit::parse(unit)?,
+            None => Unit::Meters,
+        };
+
+        Ok(Self {
+            key: args[0].key()?,
+            member_1: args[1].data.clone(),
+            member_2: args[2].data.clone(),
+            unit,
+        })
+    }
+
+    fn to_message(&self) -> Message {
+        let elements = vec![
+            Message::bulk_string(String::from("GEODIST")),
+            Message::bulk_string(self.key.to_string()),
+            Message::bulk_string(self.member_1.clone()),
+            Message::bulk_string(self.member_2.clone()),
+            Message::bulk_string(self.unit.as_str()),
+        ];
+
+        Message::array(elements)
+    }
+
+    fn execute(&self, context: &mut ExecutionContext) -> anyhow::Result<Message> {
+        Ok(match self.distance(context)? {
+            Some(distance) => Message::bulk_string(geo::format_distance(distance, self.unit)),
+            None => Message::NullBulkString,
+        })
+    }
+}
@@ -0,0 +1,67 @@
+use std::fmt;
+
+use anyhow::Context;
+
+use crate::{geo::Coordinates, message::Message};
+
+use super::{Command, CommandArgs, ExecutionContext};
+
+#[derive(Debug)]
+pub(crate) struct GeoHashCommand {
+    key: String,
+    members: Vec<Vec<u8>>,
+}
+
+impl fmt::Display for GeoHashCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "GEOHASH {}", self.key)
+    }
+}
+
+impl GeoHashCommand {
+    fn hashes(&self, context: &mut ExecutionContext) -> anyhow::Result<Vec<Message>> {
+        let entry = context.keyspace.lookup_read(&self.key);
+        let set = entry
+            .as_ref()
+            .map(|entry| entry.value.as_sorted_set())
+            .transpose()?;
+
+        Ok(self
+            .members
+            .iter()
+            .map(|member| match set.and_then(|set| set.score(member)) {
+                Some(score) => Message::bulk_string(Coordinates::from_score(score).geohash()),
+                None => Message::NullBulkString,
+            })
+            .collect())
+    }
+}
+
+impl Command for GeoHashCommand {
+    fn new(args: CommandArgs) -> anyhow::Result<Self> {
+        let key = args
+            .first()
+            .context("ERR wrong number of arguments for 'geohash' command")?
+            .key()?;
+        let members = args[1..].iter().map(|arg| arg.data.clone()).collect();
+
+        Ok(Self { key, members })
+    }
+
+    fn to_message(&self) -> Message {
+        let mut elements = vec![
+            Message::bulk_string(String::from("GEOHASH")),
+            Message::bulk_string(self.key.to_string()),
+        ];
+
+        for member in &self.members {
+            elements.push(Message::bulk_string(member.clone()));
+        }
+
+        Message::array(elements)
+    }
+
+    fn execute(&self, context: &mut ExecutionContext) -> anyhow::Result<Message> {
+        Ok(Message::array(self.hashes(context)?))
+    }
+}
@@ -0,0 +1,76 @@
+use std::fmt;
+
+use anyhow::Context;
+
+use crate::{
+    geo::{self, Coordinates},
+    message::Message,
+};
+
+use super::{Command, CommandArgs, ExecutionContext};
+
+#[derive(Debug)]
+pub(crate) struct GeoPosCommand {
+    key: String,
+    members: Vec<Vec<u8>>,
+}
+
+impl fmt::Display for GeoPosCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "GEOPOS {}", self.key)
+    }
+}
+
+impl GeoPosCommand {
+    fn positions(&self, context: &mut ExecutionContext) -> anyhow::Result<Vec<Message>> {
+        let entry = context.keyspace.lookup_read(&self.key);
+        let set = entry
+            .as_ref()
+            .map(|entry| entry.value.as_sorted_set())
+            .transpose()?;
+
+        Ok(self
+            .members
+            .iter()
+            .map(|member| match set.and_then(|set| set.score(member)) {
+                Some(score) => {
+                    let coordinates = Coordinates::from_score(score);
+                    Message::array(vec![
+                        Message::bulk_string(geo::format_coordinate(coordinates.longitude)),
+                        Message::bulk_string(geo::format_coordinate(coordinates.latitude)),
+                    ])
+                }
+                None => Message::NullArray,
+            })
+            .collect())
+    }
+}
+
+impl Command for GeoPosCommand {
+    fn new(args: CommandArgs) -> anyhow::Result<Self> {
+        let key = args
+            .first()
+            .context("ERR wrong number of arguments for 'geopos' command")?
+            .key()?;
+        let members = args[1..].iter().map(|arg| arg.data.clone()).collect();
+
+        Ok(Self { key, members })
+    }
+
+    fn to_message(&self) -> Message {
+        let mut elements = vec![
+            Message::bulk_string(String::from("GEOPOS")),
+            Message::bulk_string(self.key.to_string()),
+        ];
+
+        for member in &self.members {
+            elements.push(Message::bulk_string(member.clone()));
+        }
+
+        Message::array(elements)
+    }
+
+    fn execute(&self, context: &mut ExecutionContext) -> anyhow::Result<Message> {
+        Ok(Message::array(self.positions(context)?))
+    }
+}
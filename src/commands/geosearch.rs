@@ -0,0 +1,369 @@
+use std::fmt;
+
+use anyhow::Context;
+
+use crate::{
+    geo::{self, Coordinates, Unit},
+    message::{BulkString, Message},
+    sorted_set::SortedSet,
+};
+
+use super::{Command, CommandArgs, ExecutionContext};
+
+#[derive(Debug)]
+enum Origin {
+    Member(Vec<u8>),
+    Coordinates(Coordinates),
+}
+
+/// Shape dimensions, expressed in the search unit.
+#[derive(Debug)]
+enum Shape {
+    Radius(f64),
+    Box { width: f64, height: f64 },
+}
+
+#[derive(Debug, PartialEq)]
+enum Sort {
+    None,
+    Ascending,
+    Descending,
+}
+
+#[derive(Debug)]
+pub(crate) struct GeoPoint {
+    pub(crate) member: Vec<u8>,
+    pub(crate) score: f64,
+    pub(crate) distance: f64,
+    pub(crate) coordinates: Coordinates,
+}
+
+/// Search arguments shared by GEOSEARCH and GEOSEARCHSTORE.
+#[derive(Debug)]
+pub(crate) struct GeoSearch {
+    origin: Origin,
+    shape: Shape,
+    pub(crate) unit: Unit,
+    sort: Sort,
+    count: Option<(usize, bool)>,
+    with_coordinates: bool,
+    with_distance: bool,
+    with_hash: bool,
+    pub(crate) store_distance: bool,
+}
+
+impl GeoSearch {
+    pub(crate) fn parse(args: &[BulkString], store: bool) -> anyhow::Result<Self> {
+        let mut origin = None;
+        let mut shape = None;
+        let mut unit = Unit::Meters;
+        let mut sort = Sort::None;
+        let mut count = None;
+        let mut with_coordinates = false;
+        let mut with_distance = false;
+        let mut with_hash = false;
+        let mut store_distance = false;
+
+        let mut args = args.iter();
+        while let Some(option) = args.next() {
+            let mut next = || args.next().context("ERR syntax error");
+
+            match option.to_string().to_lowercase().as_str() {
+                "frommember" => {
+                    anyhow::ensure!(
+                        origin.is_none(),
+                        "ERR FROMMEMBER and FROMLONLAT options at the same time are not compatible"
+                    );
+                    origin = Some(Origin::Member(next()?.data.clone()));
+                }
+                "fromlonlat" => {
+                    anyhow::ensure!(
+                        origin.is_none(),
+                        "ERR FROMMEMBER and FROMLONLAT options at the same time are not compatible"
+                    );
+                    let longitude = next()?;
+                    let latitude = next()?;
+                    origin = Some(Origin::Coordinates(Coordinates::parse(
+                        longitude, latitude,
+                    )?));
+                }
+                "byradius" => {
+                    anyhow::ensure!(
+                        shape.is_none(),
+                        "ERR BYRADIUS and BYBOX options at the same time are not compatible"
+                    );
+                    let radius = geo::parse_float(next()?)?;
+                    anyhow::ensure!(radius >= 0.0, "ERR radius cannot be negative");
+                    unit = Unit::parse(next()?)?;
+                    shape = Some(Shape::Radius(radius));
+                }
+                "bybox" => {
+                    anyhow::ensure!(
+                        shape.is_none(),
+                        "ERR BYRADIUS and BYBOX options at the same time are not compatible"
+                    );
+                    let width = geo::parse_float(next()?)?;
+                    let height = geo::parse_float(next()?)?;
+                    anyhow::ensure!(
+                        width >= 0.0 && height >= 0.0,
+                        "ERR height or width cannot be negative"
+                    );
+                    unit = Unit::parse(next()?)?;
+                    shape = Some(Shape::Box { width, height });
+                }
+                "asc" => sort = Sort::Ascending,
+                "desc" => sort = Sort::Descending,
+                "count" => {
+                    let value: i64 = next()?
+                        .to_string()
+                        .parse()
+                        .context("ERR value is not an integer or out of range")?;
+                    anyhow::ensure!(value > 0, "ERR COUNT must be > 0");
+                    count = Some((value as usize, false));
+                }
+                "any" => match &mut count {
+                    Some((_, any)) => *any = true,
+                    None => anyhow::bail!("ERR the ANY argument requires COUNT argument"),
+                },
+                "withcoord" if !store => with_coordinates = true,
+                "withdist" if !store => with_distance = true,
+                "withhash" if !store => with_hash = true,
+                "storedist" if store => store_distance = true,
+                _ => anyhow::bail!("ERR syntax error"),
+            }
+        }
+
+        let origin = origin.context(
+            "ERR exactly one of FROMMEMBER or FROMLONLAT can be specified for GEOSEARCH",
+        )?;
+        let shape = shape
+            .context("ERR exactly one of BYRADIUS and BYBOX can be specified for GEOSEARCH")?;
+
+        // Like Redis, a COUNT without ANY implies the closest results.
+        if sort == Sort::None && matches!(count, Some((_, false))) {
+            sort = Sort::Ascending;
+        }
+
+        Ok(Self {
+            origin,
+            shape,
+            unit,
+            sort,
+            count,
+            with_coordinates,
+            with_distance,
+            with_hash,
+            store_distance,
+        })
+    }
+
+    pub(crate) fn to_args(&self) -> Vec<Message> {
+        let mut elements = Vec::new();
+
+        match &self.origin {
+            Origin::Member(member) => {
+                elements.push(Message::bulk_string(String::from("FROMMEMBER")));
+                elements.push(Message::bulk_string(member.clone()));
+            }
+            Origin::Coordinates(coordinates) => {
+                elements.push(Message::bulk_string(String::from("FROMLONLAT")));
+                elements.push(Message::bulk_string(coordinates.longitude.to_string()));
+                elements.push(Message::bulk_string(coordinates.latitude.to_string()));
+            }
+        }
+
+        match self.shape {
+            Shape::Radius(radius) => {
+                elements.push(Message::bulk_string(String::from("BYRADIUS")));
+                elements.push(Message::bulk_string(radius.to_string()));
+            }
+            Shape::Box { width, height } => {
+                elements.push(Message::bulk_string(String::from("BYBOX")));
+                elements.push(Message::bulk_string(width.to_string()));
+                elements.push(Message::bulk_string(height.to_string()));
+            }
+        }
+        elements.push(Message::bulk_string(self.unit.as_str()));
+
+        match self.sort {
+            Sort::Ascending => elements.push(Message::bulk_string(String::from("ASC"))),
+            Sort::Descending => elements.push(Message::bulk_string(String::from("DESC"))),
+            Sort::None => {}
+        }
+
+        if let Some((count, any)) = self.count {
+            elements.push(Message::bulk_string(String::from("COUNT")));
+            elements.push(Message::bulk_string(count.to_string()));
+            if any {
+                elements.push(Message::bulk_string(String::from("ANY")));
+            }
+        }
+
+        for (enabled, option) in [
+            (self.with_coordinates, "WITHCOORD"),
+            (self.with_distance, "WITHDIST"),
+            (self.with_hash, "WITHHASH"),
+            (self.store_distance, "STOREDIST"),
+        ] {
+            if enabled {
+                elements.push(Message::bulk_string(option));
+            }
+        }
+
+        elements
+    }
+
+    /// Returns the members inside the shape, with distances in meters.
+    pub(crate) fn search(&self, set: &SortedSet) -> anyhow::Result<Vec<GeoPoint>> {
+        let center = match &self.origin {
+            Origin::Member(member) => Coordinates::from_score(
+                set.score(member)
+                    .context("ERR could not decode requested zset member")?,
+            ),
+            Origin::Coordinates(coordinates) => *coordinates,
+        };
+
+        let unit = self.unit.to_meters();
+        let ranges = match self.shape {
+            Shape::Radius(radius) => geo::search_ranges(
+                center,
+                radius * unit,
+                2.0 * radius * unit,
+                2.0 * radius * unit,
+            ),
+            Shape::Box { width, height } => {
+                let (width, height) = (width * unit, height * unit);
+                geo::search_ranges(center, (width / 2.0).hypot(height / 2.0), width, height)
+            }
+        };
+
+        let mut points = Vec::new();
+        'search: for range in ranges {
+            for (member, score) in set.range(range) {
+                let coordinates = Coordinates::from_score(score);
+                let distance = match self.shape {
+                    Shape::Radius(radius) => Some(center.distance(coordinates))
+                        .filter(|distance| *distance <= radius * unit),
+                    Shape::Box { width, height } => {
+                        center.distance_in_box(width * unit, height * unit, coordinates)
+                    }
+                };
+
+                if let Some(distance) = distance {
+                    points.push(GeoPoint {
+                        member: member.to_vec(),
+                        score,
+                        distance,
+                        coordinates,
+                    });
+
+                    if let Some((count, true)) = self.count {
+                        if points.len() == count {
+                            break 'search;
+                        }
+                    }
+                }
+            }
+        }
+
+        match self.sort {
+            Sort::Ascending => points.sort_by(|a, b| a.distance.total_cmp(&b.distance)),
+            Sort::Descending => points.sort_by(|a, b| b.distance.total_cmp(&a.distance)),
+            Sort::None => {}
+        }
+
+        if let Some((count, _)) = self.count {
+            points.truncate(count);
+        }
+
+        Ok(points)
+    }
+
+    fn reply(&self, points: Vec<GeoPoint>) -> Message {
+        let with_fields = self.with_coordinates || self.with_distance || self.with_hash;
+
+        Message::array(
+            points
+                .into_iter()
+                .map(|point| {
+                    if !with_fields {
+                        return Message::bulk_string(point.member);
+                    }
+
+                    let mut fields = vec![Message::bulk_string(point.member)];
+                    if self.with_distance {
+                        fields.push(Message::bulk_string(geo::format_distance(
+                            point.distance,
+                            self.unit,
+                        )));
+                    }
+                    if self.with_hash {
+                        fields.push(Message::Integer(point.score as i64));
+                    }
+                    if self.with_coordinates {
+                        fields.push(Message::array(vec![
+                            Message::bulk_string(geo::format_coordinate(
+                                point.coordinates.longitude,
+                            )),
+                            Message::bulk_string(geo::format_coordinate(
+                                point.coordinates.latitude,
+                            )),
+                        ]));
+                    }
+
+                    Message::array(fields)
+                })
+                .collect(),
+        )
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct GeoSearchCommand {
+    key: String,
+    search: GeoSearch,
+}
+
+impl fmt::Display for GeoSearchCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "GEOSEARCH {}", self.key)
+    }
+}
+
+impl GeoSearchCommand {
+    fn search(&self, context: &mut ExecutionContext) -> anyhow::Result<Message> {
+        let points = match context.keyspace.lookup_read(&self.key) {
+            Some(entry) => self.search.search(entry.value.as_sorted_set()?)?,
+            None => Vec::new(),
+        };
+
+        Ok(self.search.reply(points))
+    }
+}
+
+impl Command for GeoSearchCommand {
+    fn new(args: CommandArgs) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            args.len() >= 6,
+            "ERR wrong number of arguments for 'geosearch' command"
+        );
+        let key = args[0].key()?;
+        let search = GeoSearch::parse(&args[1..], false)?;
+
+        Ok(Self { key, search })
+    }
+
+    fn to_message(&self) -> Message {
+        let mut elements = vec![
+            Message::bulk_string(String::from("GEOSEARCH")),
+            Message::bulk_string(self.key.to_string()),
+        ];
+        elements.extend(self.search.to_args());
+
+        Message::array(elements)
+    }
+
+    fn execute(&self, context: &mut ExecutionContext) -> anyhow::Result<Message> {
+        self.search(context)
+    }
+}
@@ -0,0 +1,103 @@
+use std::fmt;
+
+use crate::{
+    db::{Entry, Value},
+    message::Message,
+    notify::EventClass,
+    sorted_set::SortedSet,
+};
+
+use super::{geosearch::GeoSearch, Command, CommandArgs, ExecutionContext};
+
+#[derive(Debug)]
+pub(crate) struct GeoSearchStoreCommand {
+    destination: String,
+    source: String,
+    search: GeoSearch,
+}
+
+impl fmt::Display for GeoSearchStoreCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "GEOSEARCHSTORE {} {}", self.destination, self.source)
+    }
+}
+
+impl GeoSearchStoreCommand {
+    fn store(&self, context: &mut ExecutionContext) -> anyhow::Result<usize> {
+        let points = match context.keyspace.lookup_read(&self.source) {
+            Some(entry) => self.search.search(entry.value.as_sorted_set()?)?,
+            None => Vec::new(),
+        };
+
+        let count = points.len();
+        let changed = if points.is_empty() {
+            let removed = context.keyspace.remove(&self.destination);
+            if removed {
+                context
+                    .keyspace
+                    .notify(EventClass::Generic, "del", &self.destination);
+            }
+
+            removed
+        } else {
+            let mut set = SortedSet::new();
+            for point in points {
+                let score = if self.search.store_distance {
+                    point.distance / self.search.unit.to_meters()
+                } else {
+                    point.score
+                };
+                set.insert(point.member, score);
+            }
+
+            context.keyspace.insert(
+                self.destination.to_string(),
+                Entry::new(Value::SortedSet(set), None),
+            );
+            context
+                .keyspace
+                .notify(EventClass::SortedSet, "geosearchstore", &self.destination);
+            true
+        };
+
+        if changed {
+            context.propagate_command(self);
+        }
+
+        Ok(count)
+    }
+}
+
+impl Command for GeoSearchStoreCommand {
+    fn new(args: CommandArgs) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            args.len() >= 7,
+            "ERR wrong number of arguments for 'geosearchstore' command"
+        );
+
+        Ok(Self {
+            destination: args[0].key()?,
+            source: args[1].key()?,
+            search: GeoSearch::parse(&args[2..], true)?,
+        })
+    }
+
+    fn to_message(&self) -> Message {
+        let mut elements = vec![
+            Message::bulk_string(String::from("GEOSEARCHSTORE")),
+            Message::bulk_string(self.destination.to_string()),
+            Message::bulk_string(self.source.to_string()),
+        ];
+        elements.extend(self.search.to_args());
+
+        Message::array(elements)
+    }
+
+    fn is_write(&self) -> bool {
+        true
+    }
+
+    fn execute(&self, context: &mut ExecutionContext) -> anyhow::Result<Message> {
+        Ok(Message::Integer(self.store(context)? as i64))
+    }
+}
@@ -1,15 +1,8 @@
 use std::fmt;
 
-use anyhow::Context;
-use async_trait::async_trait;
-use tokio::{
-    io::{BufWriter, WriteHalf},
-    net::TcpStream,
-};
+use crate::message::Message;
 
-use crate::{db::Db, message::Message};
-
-use super::{Command, CommandArgs};
+use super::{Command, CommandArgs, ExecutionContext};
 
 #[derive(Debug)]
 pub(crate) struct GetCommand {
@@ -22,15 +15,13 @@ impl fmt::Display for GetCommand {
     }
 }
 
-#[async_trait]
 impl Command for GetCommand {
     fn new(args: CommandArgs) -> anyhow::Result<Self> {
-        let key = args
-            .first()
-            .expect("GET message should have key")
-            .to_string();
+        let [key] = args else {
+            anyhow::bail!("ERR wrong number of arguments for 'get' command");
+        };
 
-        Ok(Self { key })
+        Ok(Self { key: key.key()? })
     }
 
     fn to_message(&self) -> Message {
@@ -42,20 +33,10 @@ impl Command for GetCommand {
         Message::array(elements)
     }
 
-    async fn handle(
-        &self,
-        writer: &mut BufWriter<WriteHalf<TcpStream>>,
-        db: &Db,
-    ) -> anyhow::Result<()> {
-        let message = match db.get_key(&self.key).await {
-            Some(value) => Message::bulk_string(value.value.to_string()),
-            None => Message::NullBulkString,
-        };
-        message
-            .send(writer)
-            .await
-            .context("Failed to send GET reply")?;
-
-        Ok(())
+    fn execute(&self, context: &mut ExecutionContext) -> anyhow::Result<Message> {
+        match context.keyspace.lookup_read(&self.key) {
+            Some(entry) => Ok(Message::bulk_string(entry.value.as_string()?.clone())),
+            None => Ok(Message::NullBulkString),
+        }
     }
 }
@@ -0,0 +1,90 @@
+use std::fmt;
+
+use anyhow::Context;
+
+use crate::message::Message;
+
+use super::{Command, CommandArgs, ExecutionContext};
+
+/// Switches the connection protocol and replies with the server information.
+#[derive(Debug)]
+pub(crate) struct HelloCommand {
+    protocol: Option<u8>,
+}
+
+impl fmt::Display for HelloCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "HELLO {:?}", self.protocol)
+    }
+}
+
+impl Command for HelloCommand {
+    fn new(args: CommandArgs) -> anyhow::Result<Self> {
+        let protocol = match args {
+            [] => None,
+            [protocol] => {
+                let protocol: i64 = protocol
+                    .to_string()
+                    .parse()
+                    .ok()
+                    .context("ERR Protocol version is not an integer or out of range")?;
+                anyhow::ensure!(
+                    protocol == 2 || protocol == 3,
+                    "NOPROTO unsupported protocol version"
+                );
+
+                Some(protocol as u8)
+            }
+            _ => anyhow::bail!("ERR syntax error"),
+        };
+
+        Ok(Self { protocol })
+    }
+
+    fn to_message(&self) -> Message {
+        let mut elements = vec![Message::bulk_string("HELLO")];
+        if let Some(protocol) = self.protocol {
+            elements.push(Message::bulk_string(protocol.to_string()));
+        }
+
+        Message::array(elements)
+    }
+
+    fn execute(&self, context: &mut ExecutionContext) -> anyhow::Result<Message> {
+        if let Some(protocol) = self.protocol {
+            context.client.protocol = protocol;
+        }
+
+        let version = &context.db.state.version;
+        let role = if context.db.is_master() {
+            "master"
+        } else {
+            "replica"
+        };
+
+        Ok(Message::Map(vec![
+            (
+                Message::bulk_string("server"),
+                Message::bulk_string("redis"),
+            ),
+            (
+                Message::bulk_string("version"),
+                Message::bulk_string(version.clone()),
+            ),
+            (
+                Message::bulk_string("proto"),
+                Message::Integer(context.client.protocol as i64),
+            ),
+            (
+                Message::bulk_string("id"),
+                Message::Integer(context.client.id as i64),
+            ),
+            (
+                Message::bulk_string("mode"),
+                Message::bulk_string("standalone"),
+            ),
+            (Message::bulk_string("role"), Message::bulk_string(role)),
+            (Message::bulk_string("modules"), Message::array(Vec::new())),
+        ]))
+    }
+}
@@ -1,22 +1,19 @@
 use std::{collections::HashSet, fmt, io::Write};
 
 use anyhow::{Context, Ok};
-use async_trait::async_trait;
-use tokio::{
-    io::{BufWriter, WriteHalf},
-    net::TcpStream,
-};
 
 use crate::{
     db::{Db, State},
     message::Message,
+    replication::{MasterLink, Role},
 };
 
-use super::{Command, CommandArgs};
+use super::{Command, CommandArgs, ExecutionContext};
 
 #[derive(Debug, Eq, PartialEq, Hash)]
 pub(crate) enum InfoSection {
     Server,
+    Persistence,
     Replication,
     Default,
 }
@@ -25,6 +22,7 @@ impl fmt::Display for InfoSection {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             InfoSection::Server => write!(f, "server"),
+            InfoSection::Persistence => write!(f, "persistence"),
             InfoSection::Replication => write!(f, "replication"),
             InfoSection::Default => write!(f, "default"),
         }
@@ -32,12 +30,15 @@ impl fmt::Display for InfoSection {
 }
 
 impl InfoSection {
-    fn parse(value: &str) -> anyhow::Result<Self> {
-        match value {
-            "default" => Ok(Self::Default),
-            "server" => Ok(Self::Server),
-            "replication" => Ok(Self::Replication),
-            value => anyhow::bail!("Unsupported option {value}"),
+    /// Parses a section name, returning `None` for the sections that aren't reported, which
+    /// Redis ignores. The default sections are all of those reported.
+    fn parse(value: &str) -> Option<Self> {
+        match value.to_lowercase().as_str() {
+            "default" | "all" | "everything" => Some(Self::Default),
+            "server" => Some(Self::Server),
+            "persistence" => Some(Self::Persistence),
+            "replication" => Some(Self::Replication),
+            _ => None,
         }
     }
 }
@@ -53,12 +54,14 @@ impl fmt::Display for InfoCommand {
     }
 }
 
-#[async_trait]
 impl Command for InfoCommand {
     fn new(args: CommandArgs) -> anyhow::Result<Self> {
-        let mut sections = HashSet::new();
-        for args in args {
-            sections.insert(InfoSection::parse(&args.data)?);
+        let mut sections: HashSet<InfoSection> = args
+            .iter()
+            .filter_map(|arg| InfoSection::parse(&arg.to_string()))
+            .collect();
+        if args.is_empty() {
+            sections.insert(InfoSection::Default);
         }
 
         Ok(Self { sections })
@@ -74,24 +77,24 @@ impl Command for InfoCommand {
         Message::array(elements)
     }
 
-    async fn handle(
-        &self,
-        writer: &mut BufWriter<WriteHalf<TcpStream>>,
-        db: &Db,
-    ) -> anyhow::Result<()> {
+    fn execute(&self, context: &mut ExecutionContext) -> anyhow::Result<Message> {
         let mut buf = Vec::new();
 
-        if self.sections.is_empty() || self.sections.contains(&InfoSection::Default) {
-            get_default_info(&mut buf, &db.state)?;
+        if self.sections.contains(&InfoSection::Default) {
+            get_default_info(&mut buf, context)?;
         } else {
             for section in &self.sections {
                 match section {
                     InfoSection::Server => {
-                        get_server_info(&mut buf, &db.state)
+                        get_server_info(&mut buf, &context.db.state)
                             .context("Failed to get server info")?;
                     }
+                    InfoSection::Persistence => {
+                        get_persistence_info(&mut buf, context)
+                            .context("Failed to get persistence info")?;
+                    }
                     InfoSection::Replication => {
-                        get_replication_info(&mut buf, &db.state)
+                        get_replication_info(&mut buf, context.db)
                             .context("Failed to get replication info")?;
                     }
                     InfoSection::Default => unreachable!(),
@@ -99,68 +102,169 @@ impl Command for InfoCommand {
             }
         }
 
-        let message = Message::bulk_string(String::from_utf8(buf)?);
-        message
-            .send(writer)
-            .await
-            .context("Failed to send INFO reply")?;
-
-        Ok(())
+        Ok(Message::bulk_string(buf))
     }
 }
 
-fn get_default_info(writer: &mut impl Write, state: &State) -> anyhow::Result<()> {
+fn get_default_info(writer: &mut impl Write, context: &ExecutionContext) -> anyhow::Result<()> {
+    let state = &context.db.state;
+
     get_server_info(writer, state).context("Failed to get server info")?;
     writeln!(writer)?;
-    get_replication_info(writer, state).context("Failed to get replication info")?;
+    get_persistence_info(writer, context).context("Failed to get persistence info")?;
+    writeln!(writer)?;
+    get_replication_info(writer, context.db).context("Failed to get replication info")?;
 
     Ok(())
 }
 
 fn get_server_info(writer: &mut impl Write, state: &State) -> anyhow::Result<()> {
-    let (version, mode, os, arch_bits) = match state {
-        State::Master {
-            version,
-            mode,
-            os,
-            arch_bits,
-            ..
-        }
-        | State::Slave {
-            version,
-            mode,
-            os,
-            arch_bits,
-            ..
-        } => (version, mode, os, arch_bits),
-    };
-
     writeln!(writer, "# Server")?;
-    writeln!(writer, "redis_version:{}", version)?;
-    writeln!(writer, "redis_mode:{}", mode)?;
-    writeln!(writer, "os:{}", os)?;
-    writeln!(writer, "arch_bits:{}", arch_bits)?;
+    writeln!(writer, "redis_version:{}", state.version)?;
+    writeln!(writer, "redis_mode:{}", state.mode)?;
+    writeln!(writer, "os:{}", state.os)?;
+    writeln!(writer, "arch_bits:{}", state.arch_bits)?;
 
     Ok(())
 }
 
-fn get_replication_info(writer: &mut impl Write, state: &State) -> anyhow::Result<()> {
-    writeln!(writer, "# Replication")?;
+fn get_persistence_info(writer: &mut impl Write, context: &ExecutionContext) -> anyhow::Result<()> {
+    let status = context.db.save_status();
+
+    writeln!(writer, "# Persistence")?;
+    writeln!(writer, "loading:0")?;
+    writeln!(
+        writer,
+        "rdb_changes_since_last_save:{}",
+        context.keyspace.dirty()
+    )?;
+    writeln!(
+        writer,
+        "rdb_bgsave_in_progress:{}",
+        status.bgsave_in_progress as u8
+    )?;
+    writeln!(writer, "rdb_last_save_time:{}", status.last_save)?;
+    writeln!(
+        writer,
+        "rdb_last_bgsave_status:{}",
+        if status.last_bgsave_ok { "ok" } else { "err" }
+    )?;
 
-    match state {
-        State::Master {
-            replication_id,
-            replication_offset,
-            ..
-        } => {
-            writeln!(writer, "role:master")?;
-            writeln!(writer, "master_replid:{}", replication_id)?;
-            writeln!(writer, "master_repl_offset:{}", replication_offset)?;
+    let aof = context.db.aof();
+    writeln!(writer, "aof_enabled:{}", aof.is_some() as u8)?;
+    writeln!(
+        writer,
+        "aof_rewrite_in_progress:{}",
+        aof.as_ref().is_some_and(|aof| aof.is_rewriting()) as u8
+    )?;
+    writeln!(
+        writer,
+        "aof_last_bgrewrite_status:{}",
+        match aof.as_ref() {
+            Some(aof) if !aof.last_rewrite_ok => "err",
+            _ => "ok",
         }
-        State::Slave { .. } => {
+    )?;
+    if let Some(aof) = aof.as_ref() {
+        writeln!(writer, "aof_current_size:{}", aof.current_size)?;
+        writeln!(writer, "aof_base_size:{}", aof.base_size)?;
+    }
+
+    Ok(())
+}
+
+fn get_replication_info(writer: &mut impl Write, db: &Db) -> anyhow::Result<()> {
+    writeln!(writer, "# Replication")?;
+
+    let role = db.replication().role.clone();
+    match role {
+        Role::Master => writeln!(writer, "role:master")?,
+        Role::Replica { master_address } => {
+            let (host, port) = master_address
+                .rsplit_once(':')
+                .unwrap_or((&master_address, ""));
+            let replication = db.replication();
             writeln!(writer, "role:slave")?;
+            writeln!(writer, "master_host:{}", host)?;
+            writeln!(writer, "master_port:{}", port)?;
+            match replication.master_link {
+                MasterLink::Up => {
+                    writeln!(writer, "master_link_status:up")?;
+                    writeln!(
+                        writer,
+                        "master_last_io_seconds_ago:{}",
+                        replication.master_last_io.elapsed().as_secs()
+                    )?;
+                    writeln!(writer, "master_sync_in_progress:0")?;
+                }
+                MasterLink::Syncing => {
+                    writeln!(writer, "master_link_status:down")?;
+                    writeln!(writer, "master_last_io_seconds_ago:-1")?;
+                    writeln!(writer, "master_sync_in_progress:1")?;
+                }
+                MasterLink::Down { since } => {
+                    writeln!(writer, "master_link_status:down")?;
+                    writeln!(writer, "master_last_io_seconds_ago:-1")?;
+                    writeln!(writer, "master_sync_in_progress:0")?;
+                    writeln!(
+                        writer,
+                        "master_link_down_since_seconds:{}",
+                        since.elapsed().as_secs()
+                    )?;
+                }
+            }
+            writeln!(writer, "slave_repl_offset:{}", replication.offset)?;
         }
     }
 
+    // Replicas can have replicas of their own.
+    let (min_replicas, max_lag) = {
+        let config = db.config();
+        (config.min_replicas_to_write, config.min_replicas_max_lag)
+    };
+    let replicas = db.replicas();
+    writeln!(writer, "connected_slaves:{}", replicas.len())?;
+    if min_replicas > 0 {
+        writeln!(writer, "min_slaves_good_slaves:{}", replicas.good(max_lag))?;
+    }
+    for (i, replica) in replicas.iter().enumerate() {
+        writeln!(
+            writer,
+            "slave{}:ip={},port={},state=online,offset={},lag={}",
+            i,
+            replica.ip,
+            replica.port,
+            replica.ack_offset,
+            replica.last_ack.elapsed().as_secs()
+        )?;
+    }
+    drop(replicas);
+
+    let backlog_size = db.config().repl_backlog_size;
+    let replication = db.replication();
+    let (backlog_start, backlog_len) = replication.backlog_range();
+    writeln!(writer, "master_replid:{}", replication.id)?;
+    writeln!(writer, "master_replid2:{}", replication.previous_id)?;
+    writeln!(writer, "master_repl_offset:{}", replication.offset)?;
+    writeln!(
+        writer,
+        "second_repl_offset:{}",
+        replication
+            .previous_offset
+            .map_or(-1, |offset| offset as i64 + 1)
+    )?;
+    writeln!(
+        writer,
+        "repl_backlog_active:{}",
+        replication.has_backlog() as u8
+    )?;
+    writeln!(writer, "repl_backlog_size:{}", backlog_size)?;
+    writeln!(
+        writer,
+        "repl_backlog_first_byte_offset:{}",
+        backlog_start + 1
+    )?;
+    writeln!(writer, "repl_backlog_histlen:{}", backlog_len)?;
+
     Ok(())
 }
@@ -0,0 +1,36 @@
+use std::fmt;
+
+use crate::message::Message;
+
+use super::{Command, CommandArgs, ExecutionContext};
+
+/// Returns the unix time of the last successful save.
+#[derive(Debug)]
+pub(crate) struct LastSaveCommand;
+
+impl fmt::Display for LastSaveCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "LASTSAVE")
+    }
+}
+
+impl Command for LastSaveCommand {
+    fn new(args: CommandArgs) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            args.is_empty(),
+            "ERR wrong number of arguments for 'lastsave' command"
+        );
+
+        Ok(Self)
+    }
+
+    fn to_message(&self) -> Message {
+        Message::array(vec![Message::bulk_string("LASTSAVE")])
+    }
+
+    fn execute(&self, context: &mut ExecutionContext) -> anyhow::Result<Message> {
+        let last_save = context.db.save_status().last_save;
+
+        Ok(Message::Integer(last_save as i64))
+    }
+}
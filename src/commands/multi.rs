@@ -0,0 +1,39 @@
+use std::fmt;
+
+use crate::{message::Message, transaction::Transaction};
+
+use super::{Command, CommandArgs, ExecutionContext};
+
+#[derive(Debug)]
+pub(crate) struct MultiCommand;
+
+impl fmt::Display for MultiCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "MULTI")
+    }
+}
+
+impl Command for MultiCommand {
+    fn new(args: CommandArgs) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            args.is_empty(),
+            "ERR wrong number of arguments for 'multi' command"
+        );
+
+        Ok(Self)
+    }
+
+    fn to_message(&self) -> Message {
+        Message::array(vec![Message::bulk_string(String::from("MULTI"))])
+    }
+
+    fn execute(&self, context: &mut ExecutionContext) -> anyhow::Result<Message> {
+        anyhow::ensure!(
+            context.client.transaction.is_none(),
+            "ERR MULTI calls can not be nested"
+        );
+        context.client.transaction = Some(Transaction::new());
+
+        Ok(Message::ok_message())
+    }
+}
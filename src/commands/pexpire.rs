@@ -0,0 +1,56 @@
+use std::fmt;
+
+use anyhow::Context;
+
+use crate::message::Message;
+
+use super::{
+    pexpireat::{Conditions, PExpireAtCommand},
+    Command, CommandArgs, ExecutionContext,
+};
+
+/// Sets the number of milliseconds after which a key expires, replicated as a `PEXPIREAT`.
+#[derive(Debug)]
+pub(crate) struct PExpireCommand {
+    key: String,
+    milliseconds: i64,
+    conditions: Conditions,
+}
+
+impl fmt::Display for PExpireCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "PEXPIRE {} {}", self.key, self.milliseconds)
+    }
+}
+
+impl Command for PExpireCommand {
+    fn new(args: CommandArgs) -> anyhow::Result<Self> {
+        let (key, milliseconds, conditions) = PExpireAtCommand::parse_args("pexpire", args)?;
+
+        Ok(Self {
+            key,
+            milliseconds,
+            conditions,
+        })
+    }
+
+    fn to_message(&self) -> Message {
+        Message::array(vec![
+            Message::bulk_string("PEXPIRE"),
+            Message::bulk_string(self.key.clone()),
+            Message::bulk_string(self.milliseconds.to_string()),
+        ])
+    }
+
+    fn is_write(&self) -> bool {
+        true
+    }
+
+    fn execute(&self, context: &mut ExecutionContext) -> anyhow::Result<Message> {
+        let expire_at = self
+            .milliseconds
+            .checked_add(context.now as i64)
+            .context("ERR invalid expire time in 'pexpire' command")?;
+        PExpireAtCommand::new_command(self.key.clone(), expire_at, self.conditions).execute(context)
+    }
+}
@@ -0,0 +1,145 @@
+use std::fmt;
+
+use anyhow::Context;
+
+use crate::{message::Message, notify::EventClass};
+
+use super::{Command, CommandArgs, ExecutionContext};
+
+/// The `NX`, `XX`, `GT` and `LT` options. Keys without an expiration are treated as never
+/// expiring by `GT` and `LT`.
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct Conditions {
+    nx: bool,
+    xx: bool,
+    gt: bool,
+    lt: bool,
+}
+
+impl Conditions {
+    fn parse(options: CommandArgs) -> anyhow::Result<Self> {
+        let mut conditions = Self::default();
+        for option in options {
+            match option.to_string().to_lowercase().as_str() {
+                "nx" => conditions.nx = true,
+                "xx" => conditions.xx = true,
+                "gt" => conditions.gt = true,
+                "lt" => conditions.lt = true,
+                option => anyhow::bail!("ERR Unsupported option {}", option),
+            }
+        }
+        anyhow::ensure!(
+            !conditions.nx || !(conditions.xx || conditions.gt || conditions.lt),
+            "ERR NX and XX, GT or LT options at the same time are not compatible"
+        );
+        anyhow::ensure!(
+            !(conditions.gt && conditions.lt),
+            "ERR GT and LT options at the same time are not compatible"
+        );
+
+        Ok(conditions)
+    }
+
+    fn allow(&self, current: Option<u128>, expire_at: u128) -> bool {
+        (!self.nx || current.is_none())
+            && (!self.xx || current.is_some())
+            && (!self.gt || current.is_some_and(|current| expire_at > current))
+            // A key without expiration never expires, anything is less.
+            && (!self.lt || expire_at < current.unwrap_or(u128::MAX))
+    }
+}
+
+/// Sets the absolute unix time in milliseconds a key expires at.
+#[derive(Debug)]
+pub(crate) struct PExpireAtCommand {
+    key: String,
+    expire_at: i64,
+    conditions: Conditions,
+}
+
+impl fmt::Display for PExpireAtCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "PEXPIREAT {} {}", self.key, self.expire_at)
+    }
+}
+
+impl PExpireAtCommand {
+    /// Parses the arguments shared by the expiration commands: a key, a time and conditions.
+    pub(crate) fn parse_args(
+        name: &str,
+        args: CommandArgs,
+    ) -> anyhow::Result<(String, i64, Conditions)> {
+        let [key, time, options @ ..] = args else {
+            anyhow::bail!("ERR wrong number of arguments for '{}' command", name);
+        };
+        let time = time
+            .to_string()
+            .parse()
+            .ok()
+            .context("ERR value is not an integer or out of range")?;
+
+        Ok((key.key()?, time, Conditions::parse(options)?))
+    }
+
+    pub(crate) fn new_command(key: String, expire_at: i64, conditions: Conditions) -> Self {
+        Self {
+            key,
+            expire_at,
+            conditions,
+        }
+    }
+}
+
+impl Command for PExpireAtCommand {
+    fn new(args: CommandArgs) -> anyhow::Result<Self> {
+        let (key, expire_at, conditions) = Self::parse_args("pexpireat", args)?;
+
+        Ok(Self::new_command(key, expire_at, conditions))
+    }
+
+    fn to_message(&self) -> Message {
+        Message::array(vec![
+            Message::bulk_string("PEXPIREAT"),
+            Message::bulk_string(self.key.clone()),
+            Message::bulk_string(self.expire_at.to_string()),
+        ])
+    }
+
+    fn is_write(&self) -> bool {
+        true
+    }
+
+    fn execute(&self, context: &mut ExecutionContext) -> anyhow::Result<Message> {
+        let Some(mut entry) = context.keyspace.get(&self.key).cloned() else {
+            return Ok(Message::Integer(0));
+        };
+
+        let current = entry.expire_at();
+        let expire_at = self.expire_at.max(0) as u128;
+        if !self.conditions.allow(current, expire_at) {
+            return Ok(Message::Integer(0));
+        }
+
+        // Only a master deletes the key right away, replicas and the AOF replay keep the deadline
+        // and wait for the `DEL` that follows.
+        if expire_at <= context.now && context.keyspace.expires_keys() {
+            context.keyspace.remove(&self.key);
+            context
+                .keyspace
+                .notify(EventClass::Generic, "del", &self.key);
+            context.propagate(Message::array(vec![
+                Message::bulk_string("DEL"),
+                Message::bulk_string(self.key.clone()),
+            ]));
+        } else {
+            entry.set_expire_at(expire_at);
+            context.keyspace.insert(self.key.clone(), entry);
+            context
+                .keyspace
+                .notify(EventClass::Generic, "expire", &self.key);
+            context.propagate_command(self);
+        }
+
+        Ok(Message::Integer(1))
+    }
+}
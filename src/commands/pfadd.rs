@@ -0,0 +1,88 @@
+use std::fmt;
+
+use anyhow::Context;
+
+use crate::{
+    db::{Entry, Value},
+    hyperloglog::HyperLogLog,
+    message::Message,
+    notify::EventClass,
+};
+
+use super::{Command, CommandArgs, ExecutionContext};
+
+#[derive(Debug)]
+pub(crate) struct PfAddCommand {
+    key: String,
+    elements: Vec<Vec<u8>>,
+}
+
+impl fmt::Display for PfAddCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "PFADD {}", self.key)
+    }
+}
+
+impl PfAddCommand {
+    fn add(&self, context: &mut ExecutionContext) -> anyhow::Result<bool> {
+        let (mut entry, mut hll, mut updated) = match context.keyspace.get(&self.key).cloned() {
+            Some(entry) => {
+                let hll = HyperLogLog::from_bytes(entry.value.as_string()?.clone())?;
+                (entry, hll, false)
+            }
+            None => (
+                Entry::new(Value::String(Vec::new()), None),
+                HyperLogLog::new(),
+                true,
+            ),
+        };
+
+        for element in &self.elements {
+            updated |= hll.add(element)?;
+        }
+
+        if updated {
+            entry.set_value(Value::String(hll.into_bytes()));
+            context.keyspace.insert(self.key.to_string(), entry);
+            context
+                .keyspace
+                .notify(EventClass::String, "pfadd", &self.key);
+            context.propagate_command(self);
+        }
+
+        Ok(updated)
+    }
+}
+
+impl Command for PfAddCommand {
+    fn new(args: CommandArgs) -> anyhow::Result<Self> {
+        let key = args
+            .first()
+            .context("ERR wrong number of arguments for 'pfadd' command")?
+            .key()?;
+        let elements = args[1..].iter().map(|arg| arg.data.clone()).collect();
+
+        Ok(Self { key, elements })
+    }
+
+    fn to_message(&self) -> Message {
+        let mut elements = vec![
+            Message::bulk_string(String::from("PFADD")),
+            Message::bulk_string(self.key.to_string()),
+        ];
+
+        for element in &self.elements {
+            elements.push(Message::bulk_string(element.clone()));
+        }
+
+        Message::array(elements)
+    }
+
+    fn is_write(&self) -> bool {
+        true
+    }
+
+    fn execute(&self, context: &mut ExecutionContext) -> anyhow::Result<Message> {
+        Ok(Message::Integer(self.add(context)? as i64))
+    }
+}
@@ -0,0 +1,82 @@
+use std::fmt;
+
+use crate::{
+    db::Value,
+    hyperloglog::{self, HyperLogLog},
+    message::Message,
+};
+
+use super::{Command, CommandArgs, ExecutionContext};
+
+#[derive(Debug)]
+pub(crate) struct PfCountCommand {
+    keys: Vec<String>,
+}
+
+impl fmt::Display for PfCountCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "PFCOUNT {}", self.keys.join(" "))
+    }
+}
+
+impl PfCountCommand {
+    fn count(&self, context: &mut ExecutionContext) -> anyhow::Result<u64> {
+        if let [key] = self.keys.as_slice() {
+            context.keyspace.record_read(key);
+            let Some(entry) = context.keyspace.get(key) else {
+                return Ok(0);
+            };
+
+            let value = entry.value.as_string()?;
+            let mut hll = HyperLogLog::from_bytes(value.clone())?;
+            let count = hll.count()?;
+
+            // Caching the cardinality doesn't change the set, so it isn't a write to propagate.
+            let hll = hll.into_bytes();
+            if &hll != value {
+                context.keyspace.update_cache(key, Value::String(hll));
+            }
+
+            return Ok(count);
+        }
+
+        let mut registers = [0; hyperloglog::HLL_REGISTERS];
+        for key in &self.keys {
+            if let Some(entry) = context.keyspace.lookup_read(key) {
+                HyperLogLog::from_bytes(entry.value.as_string()?.clone())?
+                    .merge_into(&mut registers)?;
+            }
+        }
+
+        Ok(hyperloglog::count_registers(&registers))
+    }
+}
+
+impl Command for PfCountCommand {
+    fn new(args: CommandArgs) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            !args.is_empty(),
+            "ERR wrong number of arguments for 'pfcount' command"
+        );
+        let keys = args
+            .iter()
+            .map(|arg| arg.key())
+            .collect::<anyhow::Result<_>>()?;
+
+        Ok(Self { keys })
+    }
+
+    fn to_message(&self) -> Message {
+        let mut elements = vec![Message::bulk_string(String::from("PFCOUNT"))];
+
+        for key in &self.keys {
+            elements.push(Message::bulk_string(key.to_string()));
+        }
+
+        Message::array(elements)
+    }
+
+    fn execute(&self, context: &mut ExecutionContext) -> anyhow::Result<Message> {
+        Ok(Message::Integer(self.count(context)? as i64))
+    }
+}
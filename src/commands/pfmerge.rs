@@ -0,0 +1,99 @@
+use std::fmt;
+
+use anyhow::Context;
+
+use crate::{
+    db::{Entry, Value},
+    hyperloglog::{self, HyperLogLog},
+    message::Message,
+    notify::EventClass,
+};
+
+use super::{Command, CommandArgs, ExecutionContext};
+
+#[derive(Debug)]
+pub(crate) struct PfMergeCommand {
+    destination: String,
+    sources: Vec<String>,
+}
+
+impl fmt::Display for PfMergeCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "PFMERGE {} {}", self.destination, self.sources.join(" "))
+    }
+}
+
+impl PfMergeCommand {
+    fn merge(&self, context: &mut ExecutionContext) -> anyhow::Result<()> {
+        let mut registers = [0; hyperloglog::HLL_REGISTERS];
+        let mut use_dense = false;
+
+        // The destination is part of the union, like in Redis.
+        let destination = context.keyspace.get(&self.destination).cloned();
+        if let Some(entry) = &destination {
+            let hll = HyperLogLog::from_bytes(entry.value.as_string()?.clone())?;
+            use_dense |= hll.is_dense();
+            hll.merge_into(&mut registers)?;
+        }
+
+        for key in &self.sources {
+            if let Some(entry) = context.keyspace.lookup_read(key) {
+                let hll = HyperLogLog::from_bytes(entry.value.as_string()?.clone())?;
+                use_dense |= hll.is_dense();
+                hll.merge_into(&mut registers)?;
+            }
+        }
+
+        let hll = HyperLogLog::from_registers(&registers, !use_dense);
+        let mut entry = destination.unwrap_or_else(|| Entry::new(Value::String(Vec::new()), None));
+        entry.set_value(Value::String(hll.into_bytes()));
+        context.keyspace.insert(self.destination.to_string(), entry);
+        context
+            .keyspace
+            .notify(EventClass::String, "pfadd", &self.destination);
+        context.propagate_command(self);
+
+        Ok(())
+    }
+}
+
+impl Command for PfMergeCommand {
+    fn new(args: CommandArgs) -> anyhow::Result<Self> {
+        let destination = args
+            .first()
+            .context("ERR wrong number of arguments for 'pfmerge' command")?
+            .key()?;
+        let sources = args[1..]
+            .iter()
+            .map(|arg| arg.key())
+            .collect::<anyhow::Result<_>>()?;
+
+        Ok(Self {
+            destination,
+            sources,
+        })
+    }
+
+    fn to_message(&self) -> Message {
+        let mut elements = vec![
+            Message::bulk_string(String::from("PFMERGE")),
+            Message::bulk_string(self.destination.to_string()),
+        ];
+
+        for key in &self.sources {
+            elements.push(Message::bulk_string(key.to_string()));
+        }
+
+        Message::array(elements)
+    }
+
+    fn is_write(&self) -> bool {
+        true
+    }
+
+    fn execute(&self, context: &mut ExecutionContext) -> anyhow::Result<Message> {
+        self.merge(context)?;
+
+        Ok(Message::ok_message())
+    }
+}
@@ -1,15 +1,8 @@
 use std::fmt;
 
-use anyhow::Context;
-use async_trait::async_trait;
-use tokio::{
-    io::{BufWriter, WriteHalf},
-    net::TcpStream,
-};
+use crate::message::Message;
 
-use crate::{db::Db, message::Message};
-
-use super::{Command, CommandArgs};
+use super::{Command, CommandArgs, ExecutionContext};
 
 #[derive(Debug)]
 pub(crate) struct PingCommand {
@@ -28,9 +21,13 @@ impl fmt::Display for PingCommand {
     }
 }
 
-#[async_trait]
 impl Command for PingCommand {
     fn new(args: CommandArgs) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            args.len() <= 1,
+            "ERR wrong number of arguments for 'ping' command"
+        );
+
         Ok(Self {
             message: args.first().map(std::string::ToString::to_string),
         })
@@ -46,20 +43,18 @@ impl Command for PingCommand {
         Message::array(elements)
     }
 
-    async fn handle(
-        &self,
-        writer: &mut BufWriter<WriteHalf<TcpStream>>,
-        _: &Db,
-    ) -> anyhow::Result<()> {
-        let message = Message::simple_string(match &self.message {
+    fn execute(&self, context: &mut ExecutionContext) -> anyhow::Result<Message> {
+        // RESP2 subscribers can't tell replies from published messages, so they get an array.
+        if context.client.in_subscriber_mode() {
+            return Ok(Message::array(vec![
+                Message::bulk_string("pong"),
+                Message::bulk_string(self.message.clone().unwrap_or_default()),
+            ]));
+        }
+
+        Ok(Message::simple_string(match &self.message {
             Some(value) => value.to_string(),
             None => String::from("PONG"),
-        });
-        message
-            .send(writer)
-            .await
-            .context("Failed to send PING reply")?;
-
-        Ok(())
+        }))
     }
 }
@@ -0,0 +1,55 @@
+use std::fmt;
+
+use crate::{message::Message, pubsub};
+
+use super::{subscribe::join, Command, CommandArgs, ExecutionContext};
+
+#[derive(Debug)]
+pub(crate) struct PSubscribeCommand {
+    patterns: Vec<Vec<u8>>,
+}
+
+impl fmt::Display for PSubscribeCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "PSUBSCRIBE {}", join(&self.patterns))
+    }
+}
+
+impl Command for PSubscribeCommand {
+    fn new(args: CommandArgs) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            !args.is_empty(),
+            "ERR wrong number of arguments for 'psubscribe' command"
+        );
+
+        Ok(Self {
+            patterns: args.iter().map(|pattern| pattern.data.clone()).collect(),
+        })
+    }
+
+    fn to_message(&self) -> Message {
+        let mut elements = vec![Message::bulk_string("PSUBSCRIBE")];
+        elements.extend(self.patterns.iter().cloned().map(Message::bulk_string));
+
+        Message::array(elements)
+    }
+
+    fn execute(&self, context: &mut ExecutionContext) -> anyhow::Result<Message> {
+        let mut pubsub = context.db.pubsub();
+
+        let replies = self
+            .patterns
+            .iter()
+            .map(|pattern| {
+                context.client.psubscribe(&mut pubsub, pattern.clone());
+                pubsub::subscription_message(
+                    "psubscribe",
+                    Some(pattern),
+                    context.client.subscription_count(),
+                )
+            })
+            .collect();
+
+        Ok(Message::Sequence(replies))
+    }
+}
@@ -1,19 +1,10 @@
 use std::fmt;
 
 use anyhow::Context;
-use async_trait::async_trait;
-use base64::{engine, Engine};
-use tokio::{
-    io::{AsyncWriteExt, BufWriter, WriteHalf},
-    net::TcpStream,
-};
 
-use crate::{
-    db::{Db, State},
-    message::Message,
-};
+use crate::{client::Resync, message::Message, replication::MasterLink};
 
-use super::{Command, CommandArgs};
+use super::{Command, CommandArgs, ExecutionContext};
 
 #[derive(Debug)]
 pub(crate) struct PSyncCommand {
@@ -36,19 +27,17 @@ impl fmt::Display for PSyncCommand {
     }
 }
 
-#[async_trait]
 impl Command for PSyncCommand {
     fn new(args: CommandArgs) -> anyhow::Result<Self> {
-        let replication_id = args
-            .first()
-            .expect("PSYNC message should have replication_id")
-            .to_string();
-        let offset: isize = args
-            .get(1)
-            .expect("PSYNC message should have offset")
-            .data
+        let [replication_id, offset] = args else {
+            anyhow::bail!("ERR wrong number of arguments for 'psync' command");
+        };
+        let replication_id = replication_id.to_string();
+        let offset: isize = offset
+            .to_string()
             .parse()
-            .expect("offset should be a integer");
+            .ok()
+            .context("ERR value is not an integer or out of range")?;
 
         Ok(Self {
             replication_id,
@@ -66,49 +55,46 @@ impl Command for PSyncCommand {
         Message::array(elements)
     }
 
-    async fn handle(
-        &self,
-        writer: &mut BufWriter<WriteHalf<TcpStream>>,
-        db: &Db,
-    ) -> anyhow::Result<()> {
-        match &*db.state {
-            State::Master {
-                replication_id,
-                replication_offset,
-                tx,
-                ..
-            } => {
-                let message = Message::simple_string(format!(
-                    "FULLRESYNC {} {}",
-                    replication_id, replication_offset
-                ));
-                message
-                    .send(writer)
-                    .await
-                    .context("Failed to send PSYNC FULLRESYNC reply")?;
-
-                // https://github.com/codecrafters-io/redis-tester/blob/main/internal/assets/empty_rdb_hex.md
-                let empty_rdb = engine::general_purpose::STANDARD.decode("UkVESVMwMDEx+glyZWRpcy12ZXIFNy4yLjD6CnJlZGlzLWJpdHPAQPoFY3RpbWXCbQi8ZfoIdXNlZC1tZW3CsMQQAPoIYW9mLWJhc2XAAP/wbjv+wP9aog==")?;
-
-                writer.write_all(b"$").await?;
-                writer
-                    .write_all(empty_rdb.len().to_string().as_bytes())
-                    .await?;
-                writer.write_all(b"\r\n").await?;
-                writer.write_all(&empty_rdb).await?;
-                writer.flush().await?;
-
-                let mut rx = tx.subscribe();
-                while let Ok(message) = rx.recv().await {
-                    message
-                        .send(writer)
-                        .await
-                        .context("Failed to broadcast message to replica")?;
-                }
-
-                Ok(())
-            }
-            State::Slave { .. } => anyhow::bail!("Slave can not handle PSYNC command"),
+    fn execute(&self, context: &mut ExecutionContext) -> anyhow::Result<Message> {
+        let mut replication = context.db.replication();
+        // A replica serves the stream of its master, once it has it.
+        anyhow::ensure!(
+            replication.is_master() || matches!(replication.master_link, MasterLink::Up),
+            "NOMASTERLINK Can't SYNC while not connected with my master"
+        );
+
+        // Subscribing while holding the keyspace lock, so the replica receives every write
+        // applied after the point it synchronizes to.
+        let feed = replication.subscribe();
+        replication.create_backlog();
+
+        // The offset sent is the one of the next byte the replica needs.
+        let missing = usize::try_from(self.offset - 1)
+            .ok()
+            .and_then(|offset| replication.since(&self.replication_id, offset));
+        if let Some(missing) = missing {
+            println!(
+                "Partial resynchronization accepted, sending {} bytes of backlog",
+                missing.len()
+            );
+            context.client.resync = Some(Resync::Partial { missing, feed });
+
+            return Ok(Message::simple_string(format!(
+                "CONTINUE {}",
+                replication.id
+            )));
         }
+
+        let snapshot = context
+            .keyspace
+            .entries()
+            .map(|(key, entry)| (key.clone(), entry.clone()))
+            .collect();
+        context.client.resync = Some(Resync::Full { snapshot, feed });
+
+        Ok(Message::simple_string(format!(
+            "FULLRESYNC {} {}",
+            replication.id, replication.offset
+        )))
     }
 }
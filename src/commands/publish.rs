@@ -0,0 +1,46 @@
+use std::fmt;
+
+use crate::message::Message;
+
+use super::{Command, CommandArgs, ExecutionContext};
+
+#[derive(Debug)]
+pub(crate) struct PublishCommand {
+    channel: Vec<u8>,
+    message: Vec<u8>,
+}
+
+impl fmt::Display for PublishCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "PUBLISH {}", String::from_utf8_lossy(&self.channel))
+    }
+}
+
+impl Command for PublishCommand {
+    fn new(args: CommandArgs) -> anyhow::Result<Self> {
+        let [channel, message] = args else {
+            anyhow::bail!("ERR wrong number of arguments for 'publish' command");
+        };
+
+        Ok(Self {
+            channel: channel.data.clone(),
+            message: message.data.clone(),
+        })
+    }
+
+    fn to_message(&self) -> Message {
+        Message::array(vec![
+            Message::bulk_string("PUBLISH"),
+            Message::bulk_string(self.channel.clone()),
+            Message::bulk_string(self.message.clone()),
+        ])
+    }
+
+    fn execute(&self, context: &mut ExecutionContext) -> anyhow::Result<Message> {
+        let receivers = context.db.pubsub().publish(&self.channel, &self.message);
+        // Clients subscribed on replicas receive the message too.
+        context.propagate_command(self);
+
+        Ok(Message::Integer(receivers as i64))
+    }
+}
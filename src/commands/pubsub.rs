@@ -0,0 +1,130 @@
+use std::fmt;
+
+use anyhow::Context;
+
+use crate::message::Message;
+
+use super::{Command, CommandArgs, ExecutionContext};
+
+#[derive(Debug)]
+enum Subcommand {
+    Channels(Option<Vec<u8>>),
+    NumSub(Vec<Vec<u8>>),
+    NumPat,
+    ShardChannels(Option<Vec<u8>>),
+    ShardNumSub(Vec<Vec<u8>>),
+}
+
+#[derive(Debug)]
+pub(crate) struct PubSubCommand {
+    subcommand: Subcommand,
+}
+
+impl fmt::Display for PubSubCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "PUBSUB {:?}", self.subcommand)
+    }
+}
+
+impl Command for PubSubCommand {
+    fn new(args: CommandArgs) -> anyhow::Result<Self> {
+        let subcommand = args
+            .first()
+            .context("ERR wrong number of arguments for 'pubsub' command")?
+            .to_string()
+            .to_lowercase();
+        let subcommand_args = &args[1..];
+
+        let subcommand = match (subcommand.as_str(), subcommand_args) {
+            ("channels", []) => Subcommand::Channels(None),
+            ("channels", [pattern]) => Subcommand::Channels(Some(pattern.data.clone())),
+            ("numsub", channels) => Subcommand::NumSub(
+                channels
+                    .iter()
+                    .map(|channel| channel.data.clone())
+                    .collect(),
+            ),
+            ("numpat", []) => Subcommand::NumPat,
+            ("shardchannels", []) => Subcommand::ShardChannels(None),
+            ("shardchannels", [pattern]) => Subcommand::ShardChannels(Some(pattern.data.clone())),
+            ("shardnumsub", channels) => Subcommand::ShardNumSub(
+                channels
+                    .iter()
+                    .map(|channel| channel.data.clone())
+                    .collect(),
+            ),
+            ("channels" | "numpat" | "shardchannels", _) => anyhow::bail!(
+                "ERR wrong number of arguments for 'pubsub|{}' command",
+                subcommand
+            ),
+            (subcommand, _) => {
+                anyhow::bail!("ERR unknown subcommand '{}'. Try PUBSUB HELP.", subcommand)
+            }
+        };
+
+        Ok(Self { subcommand })
+    }
+
+    fn to_message(&self) -> Message {
+        let mut elements = vec![Message::bulk_string("PUBSUB")];
+
+        match &self.subcommand {
+            Subcommand::Channels(pattern) => {
+                elements.push(Message::bulk_string("CHANNELS"));
+                elements.extend(pattern.iter().cloned().map(Message::bulk_string));
+            }
+            Subcommand::NumSub(channels) => {
+                elements.push(Message::bulk_string("NUMSUB"));
+                elements.extend(channels.iter().cloned().map(Message::bulk_string));
+            }
+            Subcommand::NumPat => elements.push(Message::bulk_string("NUMPAT")),
+            Subcommand::ShardChannels(pattern) => {
+                elements.push(Message::bulk_string("SHARDCHANNELS"));
+                elements.extend(pattern.iter().cloned().map(Message::bulk_string));
+            }
+            Subcommand::ShardNumSub(channels) => {
+                elements.push(Message::bulk_string("SHARDNUMSUB"));
+                elements.extend(channels.iter().cloned().map(Message::bulk_string));
+            }
+        }
+
+        Message::array(elements)
+    }
+
+    fn execute(&self, context: &mut ExecutionContext) -> anyhow::Result<Message> {
+        let pubsub = context.db.pubsub();
+
+        Ok(match &self.subcommand {
+            Subcommand::Channels(pattern) => names_message(pubsub.channels(pattern.as_deref())),
+            Subcommand::NumSub(channels) => {
+                counts_message(channels, |channel| pubsub.subscriber_count(channel))
+            }
+            Subcommand::NumPat => Message::Integer(pubsub.pattern_count() as i64),
+            Subcommand::ShardChannels(pattern) => {
+                names_message(pubsub.shard_channels(pattern.as_deref()))
+            }
+            Subcommand::ShardNumSub(channels) => {
+                counts_message(channels, |channel| pubsub.shard_subscriber_count(channel))
+            }
+        })
+    }
+}
+
+fn names_message(names: Vec<Vec<u8>>) -> Message {
+    Message::array(names.into_iter().map(Message::bulk_string).collect())
+}
+
+/// Builds the flat channel and subscriber count array replied by `NUMSUB` and `SHARDNUMSUB`.
+fn counts_message(channels: &[Vec<u8>], count: impl Fn(&[u8]) -> usize) -> Message {
+    Message::array(
+        channels
+            .iter()
+            .flat_map(|channel| {
+                [
+                    Message::bulk_string(channel.clone()),
+                    Message::Integer(count(channel) as i64),
+                ]
+            })
+            .collect(),
+    )
+}
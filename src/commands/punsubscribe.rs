@@ -0,0 +1,63 @@
+use std::fmt;
+
+use crate::{message::Message, pubsub};
+
+use super::{subscribe::join, Command, CommandArgs, ExecutionContext};
+
+/// Unsubscribes from the given patterns, or from every pattern when none is given.
+#[derive(Debug)]
+pub(crate) struct PUnsubscribeCommand {
+    patterns: Vec<Vec<u8>>,
+}
+
+impl fmt::Display for PUnsubscribeCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "PUNSUBSCRIBE {}", join(&self.patterns))
+    }
+}
+
+impl Command for PUnsubscribeCommand {
+    fn new(args: CommandArgs) -> anyhow::Result<Self> {
+        Ok(Self {
+            patterns: args.iter().map(|pattern| pattern.data.clone()).collect(),
+        })
+    }
+
+    fn to_message(&self) -> Message {
+        let mut elements = vec![Message::bulk_string("PUNSUBSCRIBE")];
+        elements.extend(self.patterns.iter().cloned().map(Message::bulk_string));
+
+        Message::array(elements)
+    }
+
+    fn execute(&self, context: &mut ExecutionContext) -> anyhow::Result<Message> {
+        let mut pubsub = context.db.pubsub();
+
+        let patterns = if self.patterns.is_empty() {
+            context.client.patterns()
+        } else {
+            self.patterns.clone()
+        };
+        if patterns.is_empty() {
+            return Ok(pubsub::subscription_message(
+                "punsubscribe",
+                None,
+                context.client.subscription_count(),
+            ));
+        }
+
+        let replies = patterns
+            .iter()
+            .map(|pattern| {
+                context.client.punsubscribe(&mut pubsub, pattern);
+                pubsub::subscription_message(
+                    "punsubscribe",
+                    Some(pattern),
+                    context.client.subscription_count(),
+                )
+            })
+            .collect();
+
+        Ok(Message::Sequence(replies))
+    }
+}
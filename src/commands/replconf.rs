@@ -1,23 +1,30 @@
 use std::fmt;
 
 use anyhow::Context;
-use async_trait::async_trait;
-use tokio::{
-    io::{BufWriter, WriteHalf},
-    net::TcpStream,
-};
 
-use crate::{
-    db::{Db, State},
-    message::Message,
-};
+use crate::message::Message;
 
-use super::{Command, CommandArgs};
+use super::{Command, CommandArgs, ExecutionContext};
 
 #[derive(Debug)]
 enum Config {
     ListeningPort(u16),
     Capabilities(String),
+    /// Sent by a master to ask its replicas for their offset.
+    GetAck,
+    /// Sent by a replica with the offset of the replication stream it processed.
+    Ack(usize),
+}
+
+impl Config {
+    fn name(&self) -> &'static str {
+        match self {
+            Config::ListeningPort(_) => "listening-port",
+            Config::Capabilities(_) => "capa",
+            Config::GetAck => "getack",
+            Config::Ack(_) => "ack",
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -37,6 +44,18 @@ impl ReplConfCommand {
             config: Config::Capabilities(String::from("psync2")),
         }
     }
+
+    pub(crate) fn new_getack_command() -> Self {
+        Self {
+            config: Config::GetAck,
+        }
+    }
+
+    pub(crate) fn new_ack_command(offset: usize) -> Self {
+        Self {
+            config: Config::Ack(offset),
+        }
+    }
 }
 
 impl fmt::Display for ReplConfCommand {
@@ -45,33 +64,35 @@ impl fmt::Display for ReplConfCommand {
     }
 }
 
-#[async_trait]
 impl Command for ReplConfCommand {
     fn new(args: CommandArgs) -> anyhow::Result<Self> {
-        let config = args
-            .first()
-            .expect("REPLCONF message should have configuration");
-
-        let config = match config.data.as_str() {
-            "listening-port" => {
-                let port: u16 = args
-                    .get(1)
-                    .expect("listening-port should have port number")
-                    .data
+        // Options come in pairs of a name and a value.
+        let [config, value, ..] = args else {
+            anyhow::bail!("ERR wrong number of arguments for 'replconf' command");
+        };
+        anyhow::ensure!(
+            args.chunks_exact(2).remainder().is_empty(),
+            "ERR wrong number of arguments for 'replconf' command"
+        );
+
+        let config = match config.to_string().to_lowercase().as_str() {
+            "listening-port" => Config::ListeningPort(
+                value
+                    .to_string()
                     .parse()
-                    .expect("listening-port port should be a integer");
-
-                Config::ListeningPort(port)
-            }
-            "capa" => {
-                let capabilities = args
-                    .get(1)
-                    .expect("capa should have capabilities")
-                    .to_string();
-
-                Config::Capabilities(capabilities)
-            }
-            config => anyhow::bail!("Unsupported REPLCONF config {}", config),
+                    .ok()
+                    .context("ERR value is not an integer or out of range")?,
+            ),
+            "capa" => Config::Capabilities(value.to_string()),
+            "getack" => Config::GetAck,
+            "ack" => Config::Ack(
+                value
+                    .to_string()
+                    .parse()
+                    .ok()
+                    .context("ERR value is not an integer or out of range")?,
+            ),
+            config => anyhow::bail!("ERR Unrecognized REPLCONF option: {}", config),
         };
 
         Ok(Self { config })
@@ -89,29 +110,37 @@ impl Command for ReplConfCommand {
                 elements.push(Message::bulk_string(String::from("capa")));
                 elements.push(Message::bulk_string(capabilities.to_string()));
             }
+            Config::GetAck => {
+                elements.push(Message::bulk_string(String::from("GETACK")));
+                elements.push(Message::bulk_string(String::from("*")));
+            }
+            Config::Ack(offset) => {
+                elements.push(Message::bulk_string(String::from("ACK")));
+                elements.push(Message::bulk_string(offset.to_string()));
+            }
         }
 
         Message::array(elements)
     }
 
-    async fn handle(
-        &self,
-        writer: &mut BufWriter<WriteHalf<TcpStream>>,
-        db: &Db,
-    ) -> anyhow::Result<()> {
-        match &*db.state {
-            State::Master { .. } => match self.config {
-                Config::ListeningPort(_) | Config::Capabilities(_) => {
-                    let message = Message::ok_message();
-                    message
-                        .send(writer)
-                        .await
-                        .context("Failed to send REPLCONF reply")?;
-
-                    Ok(())
-                }
-            },
-            State::Slave { .. } => anyhow::bail!("Slave can not handle REPLCONF command"),
+    fn execute(&self, context: &mut ExecutionContext) -> anyhow::Result<Message> {
+        match &self.config {
+            Config::ListeningPort(port) => {
+                context.client.listening_port = Some(*port);
+                Ok(Message::ok_message())
+            }
+            Config::Capabilities(_) => Ok(Message::ok_message()),
+            // Acknowledgements are never replied to, the replica doesn't read them: the empty
+            // sequence writes nothing.
+            Config::Ack(offset) => {
+                context.db.replicas().ack(context.client.id, *offset);
+                Ok(Message::Sequence(Vec::new()))
+            }
+            Config::GetAck if context.client.is_master_link() => {
+                context.client.master_force_reply = true;
+                Ok(Self::new_ack_command(context.db.replication_offset()).to_message())
+            }
+            config => anyhow::bail!("ERR Unrecognized REPLCONF option: {}", config.name()),
         }
     }
 }
@@ -0,0 +1,106 @@
+use std::fmt;
+
+use crate::{
+    message::Message,
+    replication::{self, Role},
+};
+
+use super::{Command, CommandArgs, ExecutionContext};
+
+/// Makes the server a replica of another one, or a master with `NO ONE`. The dataset is kept:
+/// a replica resynchronizes it with its new master, partially when their streams allow it.
+#[derive(Debug)]
+pub(crate) struct ReplicaOfCommand {
+    /// `None` for `NO ONE`.
+    master: Option<(String, u16)>,
+}
+
+impl fmt::Display for ReplicaOfCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.master {
+            Some((host, port)) => write!(f, "REPLICAOF {} {}", host, port),
+            None => write!(f, "REPLICAOF NO ONE"),
+        }
+    }
+}
+
+impl ReplicaOfCommand {
+    /// Parses the arguments of `REPLICAOF` or of its alias `name`.
+    pub(crate) fn parse(name: &str, args: CommandArgs) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            args.len() == 2,
+            "ERR wrong number of arguments for '{}' command",
+            name
+        );
+
+        let host = args[0].to_string();
+        let port = args[1].to_string();
+        if host.eq_ignore_ascii_case("no") && port.eq_ignore_ascii_case("one") {
+            return Ok(Self { master: None });
+        }
+
+        let port = port
+            .parse()
+            .map_err(|_| anyhow::anyhow!("ERR Invalid master port"))?;
+
+        Ok(Self {
+            master: Some((host, port)),
+        })
+    }
+}
+
+impl Command for ReplicaOfCommand {
+    fn new(args: CommandArgs) -> anyhow::Result<Self> {
+        Self::parse("replicaof", args)
+    }
+
+    fn to_message(&self) -> Message {
+        let (host, port) = match &self.master {
+            Some((host, port)) => (host.clone(), port.to_string()),
+            None => (String::from("NO"), String::from("ONE")),
+        };
+
+        Message::array(vec![
+            Message::bulk_string("REPLICAOF"),
+            Message::bulk_string(host),
+            Message::bulk_string(port),
+        ])
+    }
+
+    fn execute(&self, context: &mut ExecutionContext) -> anyhow::Result<Message> {
+        let mut replication = context.db.replication();
+
+        let Some((host, port)) = &self.master else {
+            if !replication.is_master() {
+                // The stream of the previous master stays valid up to this point, so the other
+                // replicas can continue from this server.
+                replication.shift_id(replication::random_id());
+                replication.set_role(Role::Master);
+                context.keyspace.set_replica(false);
+                println!("Promoted to master with replication id {}", replication.id);
+            }
+
+            return Ok(Message::ok_message());
+        };
+
+        let master_address = format!("{}:{}", host, port);
+        if let Role::Replica {
+            master_address: current,
+        } = &replication.role
+        {
+            if *current == master_address {
+                return Ok(Message::simple_string(String::from(
+                    "OK Already connected to specified master",
+                )));
+            }
+        }
+
+        // Keeping the stream of this server, so it can continue from where the new master is.
+        replication.create_backlog();
+        replication.set_role(Role::Replica { master_address });
+        context.keyspace.set_replica(true);
+        println!("Replicating {}:{}", host, port);
+
+        Ok(Message::ok_message())
+    }
+}
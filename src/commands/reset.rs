@@ -0,0 +1,42 @@
+use std::fmt;
+
+use crate::message::Message;
+
+use super::{Command, CommandArgs, ExecutionContext};
+
+/// Restores the connection to its initial state, leaving transactions, subscriber mode and client
+/// tracking, and switching back to RESP2.
+#[derive(Debug)]
+pub(crate) struct ResetCommand;
+
+impl fmt::Display for ResetCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "RESET")
+    }
+}
+
+impl Command for ResetCommand {
+    fn new(args: CommandArgs) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            args.is_empty(),
+            "ERR wrong number of arguments for 'reset' command"
+        );
+
+        Ok(Self)
+    }
+
+    fn to_message(&self) -> Message {
+        Message::array(vec![Message::bulk_string("RESET")])
+    }
+
+    fn execute(&self, context: &mut ExecutionContext) -> anyhow::Result<Message> {
+        context.client.transaction = None;
+        context.client.watched_keys.unwatch(context.keyspace);
+        context.client.unsubscribe_all(&mut context.db.pubsub());
+        context.db.tracking().disable(context.client.id);
+        context.client.caching = None;
+        context.client.protocol = 2;
+
+        Ok(Message::simple_string(String::from("RESET")))
+    }
+}
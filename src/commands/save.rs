@@ -0,0 +1,36 @@
+use std::fmt;
+
+use crate::message::Message;
+
+use super::{Command, CommandArgs, ExecutionContext};
+
+/// Writes the dataset to the RDB file, blocking every client until it is done.
+#[derive(Debug)]
+pub(crate) struct SaveCommand;
+
+impl fmt::Display for SaveCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SAVE")
+    }
+}
+
+impl Command for SaveCommand {
+    fn new(args: CommandArgs) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            args.is_empty(),
+            "ERR wrong number of arguments for 'save' command"
+        );
+
+        Ok(Self)
+    }
+
+    fn to_message(&self) -> Message {
+        Message::array(vec![Message::bulk_string("SAVE")])
+    }
+
+    fn execute(&self, context: &mut ExecutionContext) -> anyhow::Result<Message> {
+        context.db.save(context.keyspace)?;
+
+        Ok(Message::ok_message())
+    }
+}
@@ -1,63 +1,94 @@
-use std::fmt;
+use std::{fmt, time::SystemTime};
 
 use anyhow::Context;
-use async_trait::async_trait;
-use tokio::{
-    io::{BufWriter, WriteHalf},
-    net::TcpStream,
-};
 
 use crate::{
-    db::{Db, Entry, State},
-    message::Message,
+    db::{Entry, Value},
+    message::{BulkString, Message},
+    notify::EventClass,
 };
 
-use super::{Command, CommandArgs};
+use super::{Command, CommandArgs, ExecutionContext};
+
+#[derive(Debug, Clone, Copy)]
+enum Expiration {
+    /// Milliseconds from now, set with `PX`.
+    In(u128),
+    /// Unix time in milliseconds, set with `PXAT`.
+    At(u128),
+}
 
 #[derive(Debug)]
 pub(crate) struct SetCommand {
     key: String,
-    value: String,
-    expiration: Option<u128>,
+    value: Vec<u8>,
+    expiration: Option<Expiration>,
 }
 
-impl SetCommand {
-    pub(crate) fn new_command(key: String, value: String, expiration: Option<u128>) -> Self {
-        Self {
-            key,
-            value,
-            expiration,
-        }
+impl fmt::Display for SetCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "SET {} {} {:?}",
+            self.key,
+            String::from_utf8_lossy(&self.value),
+            self.expiration
+        )
     }
 }
 
-impl fmt::Display for SetCommand {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "SET {} {} {:?}", self.key, self.value, self.expiration)
+impl SetCommand {
+    /// Returns the unix time in milliseconds the key expires at when set at `now`.
+    fn expire_at(&self, now: u128) -> Option<u128> {
+        match self.expiration? {
+            Expiration::In(milliseconds) => Some(now.saturating_add(milliseconds)),
+            Expiration::At(expire_at) => Some(expire_at),
+        }
     }
 }
 
-#[async_trait]
 impl Command for SetCommand {
     fn new(args: CommandArgs) -> anyhow::Result<Self> {
-        let key = args.first().expect("SET message should have key");
-        let value = args.get(1).expect("SET message should have value");
+        let [key, value, options @ ..] = args else {
+            anyhow::bail!("ERR wrong number of arguments for 'set' command");
+        };
+
+        // Like Redis, the time must be positive and the time it expires at fit in an i64.
+        let parse_time = |time: Option<&BulkString>, now: u128| -> anyhow::Result<u128> {
+            let time: i64 = time
+                .context("ERR syntax error")?
+                .to_string()
+                .parse()
+                .ok()
+                .context("ERR value is not an integer or out of range")?;
+            let time = u128::try_from(time).unwrap_or_default();
+            anyhow::ensure!(
+                time > 0 && time.saturating_add(now) <= i64::MAX as u128,
+                "ERR invalid expire time in 'set' command"
+            );
+
+            Ok(time)
+        };
         let mut expiration = None;
-
-        for option in args[2..].windows(2) {
-            let option_key = option.first().expect("SET message option should have key");
-            let option_value = option.get(1).expect("SET message option should have value");
-
-            if option_key.data.to_lowercase() == "px" {
-                expiration = Some(option_value.data.parse::<u128>().with_context(|| {
-                    format!("Failed to parse expiration time {}", option_value.data)
-                })?);
+        let mut options = options.iter();
+        while let Some(option) = options.next() {
+            anyhow::ensure!(expiration.is_none(), "ERR syntax error");
+            match option.to_string().to_lowercase().as_str() {
+                "px" => {
+                    let now = SystemTime::now()
+                        .duration_since(SystemTime::UNIX_EPOCH)
+                        .expect("SystemTime before UNIX EPOCH!")
+                        .as_millis();
+                    expiration = Some(Expiration::In(parse_time(options.next(), now)?));
+                }
+                "pxat" => expiration = Some(Expiration::At(parse_time(options.next(), 0)?)),
+                _ => anyhow::bail!("ERR syntax error"),
             }
         }
 
         Ok(Self {
-            key: key.to_string(),
-            value: value.to_string(),
+            key: key.key()?,
+            value: value.data.clone(),
             expiration,
         })
     }
@@ -66,36 +97,56 @@ impl Command for SetCommand {
         let mut elements = vec![
             Message::bulk_string(String::from("SET")),
             Message::bulk_string(self.key.to_string()),
-            Message::bulk_string(self.value.to_string()),
+            Message::bulk_string(self.value.clone()),
         ];
 
-        if let Some(expiration) = &self.expiration {
-            elements.push(Message::bulk_string(String::from("PX")));
-            elements.push(Message::bulk_string(expiration.to_string()));
+        match self.expiration {
+            Some(Expiration::In(milliseconds)) => {
+                elements.push(Message::bulk_string(String::from("PX")));
+                elements.push(Message::bulk_string(milliseconds.to_string()));
+            }
+            Some(Expiration::At(expire_at)) => {
+                elements.push(Message::bulk_string(String::from("PXAT")));
+                elements.push(Message::bulk_string(expire_at.to_string()));
+            }
+            None => {}
         }
 
         Message::array(elements)
     }
 
-    async fn handle(
-        &self,
-        writer: &mut BufWriter<WriteHalf<TcpStream>>,
-        db: &Db,
-    ) -> Result<(), anyhow::Error> {
-        db.insert_key(
-            self.key.to_string(),
-            Entry::new(self.value.to_string(), self.expiration),
-        )
-        .await;
-
-        if let State::Master { .. } = &*db.state {
-            let message = Message::ok_message();
-            message
-                .send(writer)
-                .await
-                .context("Failed to send SET reply")?;
+    fn is_write(&self) -> bool {
+        true
+    }
+
+    /// The expiration is replicated as an absolute time, so replicas and the AOF expire the key
+    /// when this server does, whenever they apply the command.
+    fn replicated(&self, now: u128) -> Message {
+        Self {
+            key: self.key.clone(),
+            value: self.value.clone(),
+            expiration: self.expire_at(now).map(Expiration::At),
+        }
+        .to_message()
+    }
+
+    fn execute(&self, context: &mut ExecutionContext) -> anyhow::Result<Message> {
+        let value = Value::String(self.value.clone());
+        let entry = match self.expire_at(context.now) {
+            Some(expire_at) => Entry::with_expire_at(value, expire_at),
+            None => Entry::new(value, None),
+        };
+        context.keyspace.insert(self.key.to_string(), entry);
+        context
+            .keyspace
+            .notify(EventClass::String, "set", &self.key);
+        if self.expiration.is_some() {
+            context
+                .keyspace
+                .notify(EventClass::Generic, "expire", &self.key);
         }
+        context.propagate_command(self);
 
-        Ok(())
+        Ok(Message::ok_message())
     }
 }
@@ -0,0 +1,57 @@
+use std::fmt;
+
+use crate::{message::Message, pubsub, slot};
+
+use super::{subscribe::join, Command, CommandArgs, ExecutionContext};
+
+#[derive(Debug)]
+pub(crate) struct SSubscribeCommand {
+    channels: Vec<Vec<u8>>,
+}
+
+impl fmt::Display for SSubscribeCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SSUBSCRIBE {}", join(&self.channels))
+    }
+}
+
+impl Command for SSubscribeCommand {
+    fn new(args: CommandArgs) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            !args.is_empty(),
+            "ERR wrong number of arguments for 'ssubscribe' command"
+        );
+
+        slot::ensure_same_slot(args.iter().map(|channel| channel.data.as_slice()))?;
+
+        Ok(Self {
+            channels: args.iter().map(|channel| channel.data.clone()).collect(),
+        })
+    }
+
+    fn to_message(&self) -> Message {
+        let mut elements = vec![Message::bulk_string("SSUBSCRIBE")];
+        elements.extend(self.channels.iter().cloned().map(Message::bulk_string));
+
+        Message::array(elements)
+    }
+
+    fn execute(&self, context: &mut ExecutionContext) -> anyhow::Result<Message> {
+        let mut pubsub = context.db.pubsub();
+
+        let replies = self
+            .channels
+            .iter()
+            .map(|channel| {
+                context.client.ssubscribe(&mut pubsub, channel.clone());
+                pubsub::subscription_message(
+                    "ssubscribe",
+                    Some(channel),
+                    context.client.shard_subscription_count(),
+                )
+            })
+            .collect();
+
+        Ok(Message::Sequence(replies))
+    }
+}
@@ -0,0 +1,64 @@
+use std::fmt;
+
+use crate::{message::Message, pubsub};
+
+use super::{Command, CommandArgs, ExecutionContext};
+
+#[derive(Debug)]
+pub(crate) struct SubscribeCommand {
+    channels: Vec<Vec<u8>>,
+}
+
+impl fmt::Display for SubscribeCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SUBSCRIBE {}", join(&self.channels))
+    }
+}
+
+impl Command for SubscribeCommand {
+    fn new(args: CommandArgs) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            !args.is_empty(),
+            "ERR wrong number of arguments for 'subscribe' command"
+        );
+
+        Ok(Self {
+            channels: args.iter().map(|channel| channel.data.clone()).collect(),
+        })
+    }
+
+    fn to_message(&self) -> Message {
+        let mut elements = vec![Message::bulk_string("SUBSCRIBE")];
+        elements.extend(self.channels.iter().cloned().map(Message::bulk_string));
+
+        Message::array(elements)
+    }
+
+    fn execute(&self, context: &mut ExecutionContext) -> anyhow::Result<Message> {
+        let mut pubsub = context.db.pubsub();
+
+        let replies = self
+            .channels
+            .iter()
+            .map(|channel| {
+                context.client.subscribe(&mut pubsub, channel.clone());
+                pubsub::subscription_message(
+                    "subscribe",
+                    Some(channel),
+                    context.client.subscription_count(),
+                )
+            })
+            .collect();
+
+        Ok(Message::Sequence(replies))
+    }
+}
+
+/// Joins channel names for logging.
+pub(crate) fn join(names: &[Vec<u8>]) -> String {
+    names
+        .iter()
+        .map(|name| String::from_utf8_lossy(name))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
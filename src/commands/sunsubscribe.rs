@@ -0,0 +1,65 @@
+use std::fmt;
+
+use crate::{message::Message, pubsub, slot};
+
+use super::{subscribe::join, Command, CommandArgs, ExecutionContext};
+
+/// Unsubscribes from the given shard channels, or from every shard channel when none is given.
+#[derive(Debug)]
+pub(crate) struct SUnsubscribeCommand {
+    channels: Vec<Vec<u8>>,
+}
+
+impl fmt::Display for SUnsubscribeCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SUNSUBSCRIBE {}", join(&self.channels))
+    }
+}
+
+impl Command for SUnsubscribeCommand {
+    fn new(args: CommandArgs) -> anyhow::Result<Self> {
+        slot::ensure_same_slot(args.iter().map(|channel| channel.data.as_slice()))?;
+
+        Ok(Self {
+            channels: args.iter().map(|channel| channel.data.clone()).collect(),
+        })
+    }
+
+    fn to_message(&self) -> Message {
+        let mut elements = vec![Message::bulk_string("SUNSUBSCRIBE")];
+        elements.extend(self.channels.iter().cloned().map(Message::bulk_string));
+
+        Message::array(elements)
+    }
+
+    fn execute(&self, context: &mut ExecutionContext) -> anyhow::Result<Message> {
+        let mut pubsub = context.db.pubsub();
+
+        let channels = if self.channels.is_empty() {
+            context.client.shard_channels()
+        } else {
+            self.channels.clone()
+        };
+        if channels.is_empty() {
+            return Ok(pubsub::subscription_message(
+                "sunsubscribe",
+                None,
+                context.client.shard_subscription_count(),
+            ));
+        }
+
+        let replies = channels
+            .iter()
+            .map(|channel| {
+                context.client.sunsubscribe(&mut pubsub, channel);
+                pubsub::subscription_message(
+                    "sunsubscribe",
+                    Some(channel),
+                    context.client.shard_subscription_count(),
+                )
+            })
+            .collect();
+
+        Ok(Message::Sequence(replies))
+    }
+}
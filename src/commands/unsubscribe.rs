@@ -0,0 +1,63 @@
+use std::fmt;
+
+use crate::{message::Message, pubsub};
+
+use super::{subscribe::join, Command, CommandArgs, ExecutionContext};
+
+/// Unsubscribes from the given channels, or from every channel when none is given.
+#[derive(Debug)]
+pub(crate) struct UnsubscribeCommand {
+    channels: Vec<Vec<u8>>,
+}
+
+impl fmt::Display for UnsubscribeCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "UNSUBSCRIBE {}", join(&self.channels))
+    }
+}
+
+impl Command for UnsubscribeCommand {
+    fn new(args: CommandArgs) -> anyhow::Result<Self> {
+        Ok(Self {
+            channels: args.iter().map(|channel| channel.data.clone()).collect(),
+        })
+    }
+
+    fn to_message(&self) -> Message {
+        let mut elements = vec![Message::bulk_string("UNSUBSCRIBE")];
+        elements.extend(self.channels.iter().cloned().map(Message::bulk_string));
+
+        Message::array(elements)
+    }
+
+    fn execute(&self, context: &mut ExecutionContext) -> anyhow::Result<Message> {
+        let mut pubsub = context.db.pubsub();
+
+        let channels = if self.channels.is_empty() {
+            context.client.channels()
+        } else {
+            self.channels.clone()
+        };
+        if channels.is_empty() {
+            return Ok(pubsub::subscription_message(
+                "unsubscribe",
+                None,
+                context.client.subscription_count(),
+            ));
+        }
+
+        let replies = channels
+            .iter()
+            .map(|channel| {
+                context.client.unsubscribe(&mut pubsub, channel);
+                pubsub::subscription_message(
+                    "unsubscribe",
+                    Some(channel),
+                    context.client.subscription_count(),
+                )
+            })
+            .collect();
+
+        Ok(Message::Sequence(replies))
+    }
+}
@@ -0,0 +1,76 @@
+use std::{fmt, time::Duration};
+
+use anyhow::Context;
+
+use crate::{message::Message, replication::WaitAcks};
+
+use super::{replconf::ReplConfCommand, Command, CommandArgs, ExecutionContext};
+
+/// Blocks until the writes of the connection are acknowledged by `numreplicas` replicas, or
+/// `timeout` milliseconds passed. A timeout of 0 waits forever.
+#[derive(Debug)]
+pub(crate) struct WaitCommand {
+    numreplicas: usize,
+    timeout: u64,
+}
+
+impl fmt::Display for WaitCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "WAIT {} {}", self.numreplicas, self.timeout)
+    }
+}
+
+impl Command for WaitCommand {
+    fn new(args: CommandArgs) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            args.len() == 2,
+            "ERR wrong number of arguments for 'wait' command"
+        );
+
+        let numreplicas: usize = args[0]
+            .to_string()
+            .parse()
+            .context("ERR value is not an integer or out of range")?;
+        let timeout: i64 = args[1]
+            .to_string()
+            .parse()
+            .context("ERR timeout is not an integer or out of range")?;
+        anyhow::ensure!(timeout >= 0, "ERR timeout is negative");
+
+        Ok(Self {
+            numreplicas,
+            timeout: timeout as u64,
+        })
+    }
+
+    fn to_message(&self) -> Message {
+        Message::array(vec![
+            Message::bulk_string("WAIT"),
+            Message::bulk_string(self.numreplicas.to_string()),
+            Message::bulk_string(self.timeout.to_string()),
+        ])
+    }
+
+    fn execute(&self, context: &mut ExecutionContext) -> anyhow::Result<Message> {
+        anyhow::ensure!(
+            context.db.is_master(),
+            "ERR WAIT cannot be used with replica instances. Please also note that writes to replicas are just local and are not propagated."
+        );
+
+        let offset = context.client.write_offset;
+        let acknowledged = context.db.replicas().acknowledged(offset);
+        if acknowledged < self.numreplicas {
+            // Replicas report their offset every second, asking them speeds it up.
+            context
+                .db
+                .send_to_replicas(vec![ReplConfCommand::new_getack_command().to_message()]);
+            context.client.wait = Some(WaitAcks {
+                numreplicas: self.numreplicas,
+                offset,
+                timeout: (self.timeout > 0).then(|| Duration::from_millis(self.timeout)),
+            });
+        }
+
+        Ok(Message::Integer(acknowledged as i64))
+    }
+}
@@ -0,0 +1,59 @@
+use std::fmt;
+
+use crate::message::Message;
+
+use super::{Command, CommandArgs, ExecutionContext};
+
+#[derive(Debug)]
+pub(crate) struct WatchCommand {
+    keys: Vec<String>,
+}
+
+impl fmt::Display for WatchCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "WATCH {}", self.keys.join(" "))
+    }
+}
+
+impl Command for WatchCommand {
+    fn new(args: CommandArgs) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            !args.is_empty(),
+            "ERR wrong number of arguments for 'watch' command"
+        );
+
+        Ok(Self {
+            keys: args
+                .iter()
+                .map(|key| key.key())
+                .collect::<anyhow::Result<_>>()?,
+        })
+    }
+
+    fn to_message(&self) -> Message {
+        let mut elements = vec![Message::bulk_string(String::from("WATCH"))];
+        elements.extend(
+            self.keys
+                .iter()
+                .map(|key| Message::bulk_string(key.clone())),
+        );
+
+        Message::array(elements)
+    }
+
+    fn execute(&self, context: &mut ExecutionContext) -> anyhow::Result<Message> {
+        anyhow::ensure!(
+            context.client.transaction.is_none(),
+            "ERR WATCH inside MULTI is not allowed"
+        );
+
+        for key in &self.keys {
+            context
+                .client
+                .watched_keys
+                .watch(context.keyspace, key.to_string());
+        }
+
+        Ok(Message::ok_message())
+    }
+}
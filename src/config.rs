@@ -0,0 +1,419 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+
+use crate::{aof::AppendFsync, notify::NotifyFlags, pubsub};
+
+/// Parameters supported by `CONFIG GET` and `CONFIG SET`.
+const PARAMETERS: &[&str] = &[
+    "aof-load-truncated",
+    "appenddirname",
+    "appendfilename",
+    "appendfsync",
+    "appendonly",
+    "auto-aof-rewrite-min-size",
+    "auto-aof-rewrite-percentage",
+    "client-output-buffer-limit",
+    "dbfilename",
+    "dir",
+    "min-replicas-max-lag",
+    "min-replicas-to-write",
+    "notify-keyspace-events",
+    "repl-backlog-size",
+    "repl-backlog-ttl",
+    "repl-timeout",
+    "replica-read-only",
+    "save",
+];
+/// Parameters only set on startup.
+const IMMUTABLE_PARAMETERS: &[&str] = &["appenddirname", "appendfilename"];
+
+/// Snapshot automatically after `seconds` when at least `changes` writes happened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct SavePoint {
+    pub(crate) seconds: u64,
+    pub(crate) changes: u64,
+}
+
+impl SavePoint {
+    /// Parses `<seconds> <changes>` pairs, an empty value disables automatic snapshots.
+    fn parse_list(value: &str) -> anyhow::Result<Vec<Self>> {
+        let numbers = value
+            .split_whitespace()
+            .map(str::parse)
+            .collect::<Result<Vec<u64>, _>>()
+            .ok()
+            .filter(|numbers| numbers.len() % 2 == 0);
+        let Some(numbers) = numbers else {
+            anyhow::bail!(
+                "ERR CONFIG SET failed (possibly related to argument 'save') - Invalid save parameters"
+            );
+        };
+
+        Ok(numbers
+            .chunks(2)
+            .map(|pair| Self {
+                seconds: pair[0],
+                changes: pair[1],
+            })
+            .collect())
+    }
+}
+
+/// Disconnects a client when its output buffer exceeds `hard` bytes, or stays above `soft` bytes
+/// for more than `soft_seconds`. A limit of 0 is disabled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct OutputBufferLimit {
+    pub(crate) hard: u64,
+    pub(crate) soft: u64,
+    pub(crate) soft_seconds: u64,
+}
+
+impl OutputBufferLimit {
+    /// Parses `<class> <hard> <soft> <soft seconds>` groups, returning the limit of the replica
+    /// class, the only one enforced.
+    fn parse_replica(value: &str) -> anyhow::Result<Self> {
+        let name = "client-output-buffer-limit";
+        let args: Vec<&str> = value.split_whitespace().collect();
+        let groups = args.chunks_exact(4);
+        anyhow::ensure!(
+            !args.is_empty() && groups.remainder().is_empty(),
+            "ERR CONFIG SET failed (possibly related to argument '{}') - Wrong number of arguments in buffer limit configuration.",
+            name
+        );
+
+        let mut limit = None;
+        for group in groups {
+            anyhow::ensure!(
+                matches!(group[0].to_lowercase().as_str(), "replica" | "slave"),
+                "ERR CONFIG SET failed (possibly related to argument '{}') - Only the replica class is supported.",
+                name
+            );
+            limit = Some(Self {
+                hard: parse_memory(name, group[1])?,
+                soft: parse_memory(name, group[2])?,
+                soft_seconds: parse_integer(name, group[3])?,
+            });
+        }
+
+        limit.context("client-output-buffer-limit should have a group")
+    }
+}
+
+/// Runtime configuration.
+#[derive(Debug)]
+pub(crate) struct Config {
+    /// Directory of the RDB file.
+    pub(crate) dir: String,
+    pub(crate) dbfilename: String,
+    pub(crate) notify_keyspace_events: NotifyFlags,
+    pub(crate) save: Vec<SavePoint>,
+    pub(crate) appendonly: bool,
+    pub(crate) appendfilename: String,
+    /// Directory of the AOF files and manifest, inside `dir`.
+    pub(crate) appenddirname: String,
+    pub(crate) appendfsync: AppendFsync,
+    /// Whether an AOF with an incomplete last command is loaded, dropping that command.
+    pub(crate) aof_load_truncated: bool,
+    /// Growth since the last rewrite, in percent, that triggers an automatic rewrite.
+    pub(crate) auto_aof_rewrite_percentage: u64,
+    pub(crate) auto_aof_rewrite_min_size: u64,
+    /// Bytes of the replication stream kept for replicas to continue after a disconnection.
+    pub(crate) repl_backlog_size: u64,
+    /// Seconds without replicas after which a master frees its backlog, 0 keeps it forever.
+    pub(crate) repl_backlog_ttl: u64,
+    /// Seconds without data on the replication link before it is considered broken.
+    pub(crate) repl_timeout: u64,
+    /// Whether replicas reject writes from their clients.
+    pub(crate) replica_read_only: bool,
+    pub(crate) replica_output_buffer_limit: OutputBufferLimit,
+    /// A master rejects writes with fewer replicas that acknowledged within
+    /// `min_replicas_max_lag` seconds, 0 disables the check.
+    pub(crate) min_replicas_to_write: u64,
+    pub(crate) min_replicas_max_lag: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            dir: String::from("."),
+            dbfilename: String::from("dump.rdb"),
+            notify_keyspace_events: NotifyFlags::default(),
+            save: vec![
+                SavePoint {
+                    seconds: 3600,
+                    changes: 1,
+                },
+                SavePoint {
+                    seconds: 300,
+                    changes: 100,
+                },
+                SavePoint {
+                    seconds: 60,
+                    changes: 10000,
+                },
+            ],
+            appendonly: false,
+            appendfilename: String::from("appendonly.aof"),
+            appenddirname: String::from("appendonlydir"),
+            appendfsync: AppendFsync::EverySec,
+            aof_load_truncated: true,
+            auto_aof_rewrite_percentage: 100,
+            auto_aof_rewrite_min_size: 64 * 1024 * 1024,
+            repl_backlog_size: 1024 * 1024,
+            repl_backlog_ttl: 3600,
+            repl_timeout: 60,
+            replica_read_only: true,
+            replica_output_buffer_limit: OutputBufferLimit {
+                hard: 256 * 1024 * 1024,
+                soft: 64 * 1024 * 1024,
+                soft_seconds: 60,
+            },
+            min_replicas_to_write: 0,
+            min_replicas_max_lag: 10,
+        }
+    }
+}
+
+impl Config {
+    pub(crate) fn rdb_path(&self) -> PathBuf {
+        Path::new(&self.dir).join(&self.dbfilename)
+    }
+
+    /// Path of an AOF written before multi part AOFs, which is upgraded when loaded.
+    pub(crate) fn aof_path(&self) -> PathBuf {
+        Path::new(&self.dir).join(&self.appendfilename)
+    }
+
+    pub(crate) fn aof_dir(&self) -> PathBuf {
+        Path::new(&self.dir).join(&self.appenddirname)
+    }
+
+    pub(crate) fn is_immutable(name: &str) -> bool {
+        IMMUTABLE_PARAMETERS.contains(&name.to_lowercase().as_str())
+    }
+
+    /// Returns the parameters matching the glob pattern, with their values.
+    pub(crate) fn get(&self, pattern: &str) -> Vec<(&'static str, String)> {
+        let pattern = pattern.to_lowercase();
+
+        PARAMETERS
+            .iter()
+            .filter(|name| pubsub::glob_match(pattern.as_bytes(), name.as_bytes()))
+            .map(|name| (*name, self.value(name)))
+            .collect()
+    }
+
+    pub(crate) fn set(&mut self, name: &str, value: &str) -> anyhow::Result<()> {
+        match name.to_lowercase().as_str() {
+            "dir" => {
+                anyhow::ensure!(
+                    Path::new(value).is_dir(),
+                    "ERR CONFIG SET failed (possibly related to argument 'dir') - No such file or directory"
+                );
+                self.dir = value.to_string();
+            }
+            "dbfilename" => {
+                anyhow::ensure!(
+                    !value.contains('/'),
+                    "ERR CONFIG SET failed (possibly related to argument 'dbfilename') - dbfilename can't be a path, just a filename"
+                );
+                self.dbfilename = value.to_string();
+            }
+            "notify-keyspace-events" => {
+                self.notify_keyspace_events = NotifyFlags::parse(value)?;
+            }
+            "save" => self.save = SavePoint::parse_list(value)?,
+            "appendonly" => self.appendonly = parse_bool(name, value)?,
+            "appendfilename" => {
+                anyhow::ensure!(
+                    !value.contains('/'),
+                    "ERR CONFIG SET failed (possibly related to argument 'appendfilename') - appendfilename can't be a path, just a filename"
+                );
+                self.appendfilename = value.to_string();
+            }
+            "appenddirname" => {
+                anyhow::ensure!(
+                    !value.contains('/'),
+                    "ERR CONFIG SET failed (possibly related to argument 'appenddirname') - appenddirname can't be a path, just a dirname"
+                );
+                self.appenddirname = value.to_string();
+            }
+            "appendfsync" => self.appendfsync = AppendFsync::parse(value)?,
+            "aof-load-truncated" => self.aof_load_truncated = parse_bool(name, value)?,
+            "auto-aof-rewrite-percentage" => {
+                self.auto_aof_rewrite_percentage = parse_integer(name, value)?;
+            }
+            "auto-aof-rewrite-min-size" => {
+                self.auto_aof_rewrite_min_size = parse_memory(name, value)?;
+            }
+            "repl-backlog-size" => {
+                let size = parse_memory(name, value)?;
+                anyhow::ensure!(
+                    size > 0,
+                    "ERR CONFIG SET failed (possibly related to argument 'repl-backlog-size') - argument must be between 1 and 9223372036854775807 inclusive"
+                );
+                self.repl_backlog_size = size;
+            }
+            "repl-backlog-ttl" => self.repl_backlog_ttl = parse_integer(name, value)?,
+            "repl-timeout" => {
+                let timeout = parse_integer(name, value)?;
+                anyhow::ensure!(
+                    timeout > 0,
+                    "ERR CONFIG SET failed (possibly related to argument 'repl-timeout') - argument must be between 1 and 2147483647 inclusive"
+                );
+                self.repl_timeout = timeout;
+            }
+            "replica-read-only" => self.replica_read_only = parse_bool(name, value)?,
+            "client-output-buffer-limit" => {
+                self.replica_output_buffer_limit = OutputBufferLimit::parse_replica(value)?;
+            }
+            "min-replicas-to-write" => self.min_replicas_to_write = parse_integer(name, value)?,
+            "min-replicas-max-lag" => self.min_replicas_max_lag = parse_integer(name, value)?,
+            _ => anyhow::bail!(
+                "ERR Unknown option or number of arguments for CONFIG SET - '{}'",
+                name
+            ),
+        }
+
+        Ok(())
+    }
+
+    fn value(&self, name: &str) -> String {
+        match name {
+            "dir" => self.dir.clone(),
+            "dbfilename" => self.dbfilename.clone(),
+            "notify-keyspace-events" => self.notify_keyspace_events.to_string(),
+            "appendonly" => format_bool(self.appendonly),
+            "appendfilename" => self.appendfilename.clone(),
+            "appenddirname" => self.appenddirname.clone(),
+            "appendfsync" => self.appendfsync.to_string(),
+            "aof-load-truncated" => format_bool(self.aof_load_truncated),
+            "auto-aof-rewrite-percentage" => self.auto_aof_rewrite_percentage.to_string(),
+            "auto-aof-rewrite-min-size" => self.auto_aof_rewrite_min_size.to_string(),
+            "repl-backlog-size" => self.repl_backlog_size.to_string(),
+            "repl-backlog-ttl" => self.repl_backlog_ttl.to_string(),
+            "repl-timeout" => self.repl_timeout.to_string(),
+            "replica-read-only" => format_bool(self.replica_read_only),
+            "client-output-buffer-limit" => {
+                let limit = self.replica_output_buffer_limit;
+                format!(
+                    "replica {} {} {}",
+                    limit.hard, limit.soft, limit.soft_seconds
+                )
+            }
+            "min-replicas-to-write" => self.min_replicas_to_write.to_string(),
+            "min-replicas-max-lag" => self.min_replicas_max_lag.to_string(),
+            "save" => self
+                .save
+                .iter()
+                .map(|point| format!("{} {}", point.seconds, point.changes))
+                .collect::<Vec<_>>()
+                .join(" "),
+            _ => unreachable!("{} is not a configuration parameter", name),
+        }
+    }
+}
+
+fn parse_bool(name: &str, value: &str) -> anyhow::Result<bool> {
+    match value.to_lowercase().as_str() {
+        "yes" => Ok(true),
+        "no" => Ok(false),
+        _ => anyhow::bail!(
+            "ERR CONFIG SET failed (possibly related to argument '{}') - argument must be 'yes' or 'no'",
+            name.to_lowercase()
+        ),
+    }
+}
+
+fn format_bool(value: bool) -> String {
+    String::from(if value { "yes" } else { "no" })
+}
+
+fn parse_integer(name: &str, value: &str) -> anyhow::Result<u64> {
+    value.parse().map_err(|_| {
+        anyhow::anyhow!(
+            "ERR CONFIG SET failed (possibly related to argument '{}') - argument couldn't be parsed into an integer",
+            name.to_lowercase()
+        )
+    })
+}
+
+/// Parses a size like `64mb`, `k`, `m` and `g` are powers of 1000 and `kb`, `mb` and `gb`
+/// powers of 1024, as in Redis.
+fn parse_memory(name: &str, value: &str) -> anyhow::Result<u64> {
+    let value = value.to_lowercase();
+    let digits = value.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+    let unit = match &value[digits.len()..] {
+        "" | "b" => Some(1),
+        "k" => Some(1000),
+        "kb" => Some(1024),
+        "m" => Some(1000 * 1000),
+        "mb" => Some(1024 * 1024),
+        "g" => Some(1000 * 1000 * 1000),
+        "gb" => Some(1024 * 1024 * 1024),
+        _ => None,
+    };
+
+    let bytes = digits
+        .parse::<u64>()
+        .ok()
+        .zip(unit)
+        .and_then(|(number, unit)| number.checked_mul(unit));
+    match bytes {
+        Some(bytes) => Ok(bytes),
+        None => anyhow::bail!(
+            "ERR CONFIG SET failed (possibly related to argument '{}') - argument must be a memory value",
+            name.to_lowercase()
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn memory_units() {
+        let memory = |value| parse_memory("maxmemory", value).unwrap();
+        assert_eq!(memory("100"), 100);
+        assert_eq!(memory("100b"), 100);
+        assert_eq!(memory("1k"), 1000);
+        assert_eq!(memory("1KB"), 1024);
+        assert_eq!(memory("64mb"), 64 * 1024 * 1024);
+        assert_eq!(memory("2m"), 2_000_000);
+        assert_eq!(memory("1gb"), 1 << 30);
+        assert_eq!(memory("3g"), 3_000_000_000);
+    }
+
+    #[test]
+    fn invalid_memory() {
+        for value in ["", "mb", "-1", "1.5mb", "1tb", "18446744073709551615gb"] {
+            let error = parse_memory("maxmemory", value).unwrap_err();
+            assert!(
+                error
+                    .to_string()
+                    .ends_with("argument must be a memory value"),
+                "{}: {}",
+                value,
+                error
+            );
+        }
+    }
+
+    #[test]
+    fn replica_output_buffer_limit() {
+        let limit = OutputBufferLimit::parse_replica("replica 256mb 64mb 60").unwrap();
+        assert_eq!(
+            limit,
+            OutputBufferLimit {
+                hard: 256 * 1024 * 1024,
+                soft: 64 * 1024 * 1024,
+                soft_seconds: 60,
+            }
+        );
+
+        assert!(OutputBufferLimit::parse_replica("replica 256mb 64mb").is_err());
+        assert!(OutputBufferLimit::parse_replica("normal 0 0 0").is_err());
+    }
+}
@@ -0,0 +1,344 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use bytes::BytesMut;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt, BufReader, BufWriter, ReadHalf, WriteHalf},
+    net::TcpStream,
+};
+
+use crate::{
+    client::{Client, Resync},
+    commands::{self, replconf::ReplConfCommand, Command},
+    db::{Db, Entry},
+    message::{self, BulkString, Message},
+    rdb,
+    replication::{self, ReplicaOutput},
+};
+
+/// How often a replica reports the offset it processed to its master.
+const ACK_PERIOD: Duration = Duration::from_secs(1);
+
+pub(crate) async fn handle_connection(
+    writer: &mut BufWriter<WriteHalf<TcpStream>>,
+    reader: &mut BufReader<ReadHalf<TcpStream>>,
+    db: Db,
+    mut client: Client,
+    mut buf: BytesMut,
+) -> anyhow::Result<()> {
+    let mut ack_interval = tokio::time::interval(ACK_PERIOD);
+    let mut scanner = message::FrameScanner::default();
+
+    loop {
+        // The stream can't be resynchronized after a protocol error, so like Redis the error is
+        // replied and the connection closed.
+        let frame_len = match scanner.frame_len(&buf) {
+            Ok(frame_len) => frame_len,
+            Err(err) => {
+                if !client.is_master_link() {
+                    Message::error(&err).send(writer).await?;
+                }
+                return Err(err);
+            }
+        };
+        let Some(frame_len) = frame_len else {
+            tokio::select! {
+                bytes_read = reader.read_buf(&mut buf) => {
+                    if bytes_read.context("Failed to read stream")? == 0 {
+                        break;
+                    }
+                    if client.is_master_link() {
+                        db.replication().master_last_io = Instant::now();
+                    }
+                }
+                Some(message) = client.pushed.recv() => {
+                    if let Some(message) = adapt_to_protocol(&client, message) {
+                        message.send(writer).await?;
+                    }
+                }
+                _ = ack_interval.tick(), if client.is_master_link() => {
+                    // Masters send `PING`s, so a silent link is broken.
+                    let timeout = Duration::from_secs(db.config().repl_timeout);
+                    anyhow::ensure!(
+                        db.replication().master_last_io.elapsed() < timeout,
+                        "Timeout receiving from master"
+                    );
+
+                    let offset = db.replication_offset();
+                    ReplConfCommand::new_ack_command(offset)
+                        .to_message()
+                        .send(writer)
+                        .await?;
+                }
+            }
+            continue;
+        };
+
+        // Commands still buffered are dropped once the server stops following this master.
+        if client.is_stale_master_link() {
+            break;
+        }
+
+        let frame = buf.split_to(frame_len);
+        // The stream of the master is kept, for replicas of this server and to continue from it
+        // once promoted. It is fed while the command runs.
+        if client.is_master_link() {
+            client.master_frame = Some(frame.to_vec());
+        }
+        // A well-formed message that isn't a command is only replied an error.
+        let mut message = match commands::parse_message(&frame) {
+            Ok(args) => process_command(&db, &mut client, &args),
+            Err(err) => Message::error(&err),
+        };
+        // Like commands queued in a transaction, which don't run yet.
+        if let Some(frame) = client.master_frame.take() {
+            let _keyspace = db.keyspace();
+            db.feed_replication(&frame);
+        }
+        if let Some(wait) = client.wait.take() {
+            message = Message::Integer(replication::wait_for_acks(&db, wait).await as i64);
+        }
+
+        // Replicas stay silent on the master link, unless asked for their offset.
+        if !client.is_master_link() || std::mem::take(&mut client.master_force_reply) {
+            if let Some(message) = adapt_to_protocol(&client, message) {
+                message.send(writer).await?;
+            }
+        }
+
+        if client.close_after_reply {
+            break;
+        }
+
+        if let Some(resync) = client.resync.take() {
+            return replicate(writer, reader, &db, &mut client, buf, resync).await;
+        }
+    }
+
+    Ok(())
+}
+
+pub(crate) fn process_command(db: &Db, client: &mut Client, args: &[BulkString]) -> Message {
+    let name = commands::command_name(args);
+    let command = match commands::parse_command(args) {
+        Ok(command) => command,
+        Err(err) => {
+            if let Some(transaction) = &mut client.transaction {
+                transaction.abort();
+            }
+            return Message::error(&err);
+        }
+    };
+
+    if client.in_subscriber_mode()
+        && !matches!(
+            name.as_str(),
+            "subscribe"
+                | "unsubscribe"
+                | "psubscribe"
+                | "punsubscribe"
+                | "ssubscribe"
+                | "sunsubscribe"
+                | "ping"
+                | "quit"
+                | "reset"
+        )
+    {
+        return Message::error(&anyhow::anyhow!(
+            "ERR Can't execute '{}': only (P|S)SUBSCRIBE / (P|S)UNSUBSCRIBE / PING / QUIT / RESET are allowed in this context",
+            name
+        ));
+    }
+
+    if command.is_write() && !client.replays_writes() {
+        if let Err(err) = check_write_allowed(db) {
+            if let Some(transaction) = &mut client.transaction {
+                transaction.abort();
+            }
+            return Message::error(&err);
+        }
+    }
+
+    if let Some(transaction) = &mut client.transaction {
+        if !matches!(
+            name.as_str(),
+            "multi" | "exec" | "discard" | "watch" | "quit" | "reset"
+        ) {
+            println!("Command queued: {}", command);
+            transaction.queue(command);
+            return Message::simple_string(String::from("QUEUED"));
+        }
+    }
+
+    println!("Command received: {}", command);
+    commands::execute(db, client, command.as_ref())
+}
+
+/// Fails when the server can't accept writes from its clients: a read only replica, or a master
+/// without enough replicas to replicate them.
+fn check_write_allowed(db: &Db) -> anyhow::Result<()> {
+    let (read_only, min_replicas, max_lag) = {
+        let config = db.config();
+        (
+            config.replica_read_only,
+            config.min_replicas_to_write,
+            config.min_replicas_max_lag,
+        )
+    };
+    if !db.is_master() {
+        anyhow::ensure!(
+            !read_only,
+            "READONLY You can't write against a read only replica."
+        );
+        return Ok(());
+    }
+
+    if min_replicas > 0 {
+        anyhow::ensure!(
+            db.replicas().good(max_lag) >= min_replicas as usize,
+            "NOREPLICAS Not enough good replicas to write."
+        );
+    }
+
+    Ok(())
+}
+
+/// Converts a message to the protocol of the client, returning `None` when it can't be delivered.
+fn adapt_to_protocol(client: &Client, message: Message) -> Option<Message> {
+    if client.protocol == 3 {
+        return Some(message.into_resp3());
+    }
+
+    let Message::Push(elements) = &message else {
+        return Some(message.into_resp2());
+    };
+
+    let kind = match elements.first() {
+        Some(Message::BulkString(kind)) => kind.data.as_slice(),
+        _ => &[],
+    };
+
+    match kind {
+        // RESP2 connections receive invalidations as messages of the invalidation channel, when
+        // another connection redirects them there.
+        b"invalidate" if client.is_subscriber() => Some(Message::array(vec![
+            Message::bulk_string("message"),
+            Message::bulk_string("__redis__:invalidate"),
+            elements[1].clone(),
+        ])),
+        b"invalidate" | b"tracking-redir-broken" => None,
+        _ => Some(message.into_resp2()),
+    }
+}
+
+/// Sends the dataset to a replica after `PSYNC`, then streams it the propagated commands while
+/// reading its acknowledgements.
+async fn replicate(
+    writer: &mut BufWriter<WriteHalf<TcpStream>>,
+    reader: &mut BufReader<ReadHalf<TcpStream>>,
+    db: &Db,
+    client: &mut Client,
+    buf: BytesMut,
+    resync: Resync,
+) -> anyhow::Result<()> {
+    let feed = match resync {
+        Resync::Full { snapshot, feed } => {
+            send_snapshot(writer, snapshot).await?;
+            feed
+        }
+        Resync::Partial { missing, feed } => {
+            writer.write_all(&missing).await?;
+            writer.flush().await?;
+            feed
+        }
+    };
+
+    // The replica is online once synchronized.
+    let ip = client
+        .addr
+        .map_or_else(|| String::from("?"), |addr| addr.ip().to_string());
+    db.replicas()
+        .register(client.id, ip, client.listening_port.unwrap_or_default());
+
+    let result = stream_to_replica(writer, reader, db, client, buf, feed).await;
+    db.replicas().unregister(client.id);
+
+    result
+}
+
+async fn stream_to_replica(
+    writer: &mut BufWriter<WriteHalf<TcpStream>>,
+    reader: &mut BufReader<ReadHalf<TcpStream>>,
+    db: &Db,
+    client: &mut Client,
+    mut buf: BytesMut,
+    feed: Arc<ReplicaOutput>,
+) -> anyhow::Result<()> {
+    let mut timeout_interval = tokio::time::interval(ACK_PERIOD);
+    let mut scanner = message::FrameScanner::default();
+
+    loop {
+        while let Some(frame_len) = scanner.frame_len(&buf)? {
+            let frame = buf.split_to(frame_len);
+            // Replicas only send `REPLCONF ACK`, which is not replied to.
+            if let Ok(args) = commands::parse_message(&frame) {
+                process_command(db, client, &args);
+            }
+        }
+
+        tokio::select! {
+            bytes = feed.next() => {
+                let bytes = bytes?;
+                let write = async {
+                    writer.write_all(&bytes).await?;
+                    writer.flush().await
+                };
+                tokio::select! {
+                    result = write => result.context("Failed to send the stream to replica")?,
+                    reason = feed.closed() => anyhow::bail!(reason),
+                }
+            }
+            bytes_read = reader.read_buf(&mut buf) => {
+                if bytes_read.context("Failed to read stream")? == 0 {
+                    return Ok(());
+                }
+            }
+            _ = timeout_interval.tick() => {
+                // Replicas acknowledge every second, so a silent one is gone.
+                let timeout = Duration::from_secs(db.config().repl_timeout);
+                let last_ack = db.replicas().last_ack(client.id);
+                if let Some(last_ack) = last_ack {
+                    anyhow::ensure!(
+                        last_ack.elapsed() < timeout,
+                        "Disconnecting timedout replica"
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Sends the snapshot as `$<len>\r\n<payload>`. Writes keep arriving while it is serialized,
+/// they wait in the output buffer of the replica and are sent after it so the replica ends up
+/// with the same dataset.
+async fn send_snapshot(
+    writer: &mut BufWriter<WriteHalf<TcpStream>>,
+    snapshot: Vec<(String, Entry)>,
+) -> anyhow::Result<()> {
+    let rdb = tokio::task::spawn_blocking(move || {
+        rdb::serialize(snapshot.iter().map(|(key, entry)| (key, entry)), None)
+    })
+    .await
+    .context("Failed to serialize RDB snapshot")?;
+
+    writer.write_all(b"$").await?;
+    writer.write_all(rdb.len().to_string().as_bytes()).await?;
+    writer.write_all(b"\r\n").await?;
+    writer.write_all(&rdb).await?;
+    writer.flush().await?;
+
+    Ok(())
+}
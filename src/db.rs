@@ -1,17 +1,36 @@
 use std::{
     collections::HashMap,
-    env, fmt,
-    sync::Arc,
+    env, fmt, fs, io,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, MutexGuard, PoisonError,
+    },
     time::{Duration, SystemTime},
 };
 
-use tokio::sync::broadcast;
+use anyhow::Context;
 
 use crate::{
-    commands::{set, Command},
+    aof::{self, Aof, AppendFsync},
+    commands::{del::DelCommand, Command},
+    config::Config,
     message::Message,
+    notify::{EventClass, KeyspaceEvent},
+    pubsub::PubSub,
+    rdb::{self, SaveStatus},
+    replication::{self, Replicas, Replication, Role},
+    sorted_set::SortedSet,
+    tracking::TrackingTable,
 };
 
+/// Seconds to wait before retrying a failed background save triggered by a save point.
+const BGSAVE_RETRY_DELAY: u64 = 5;
+/// Seconds between the `PING`s a master sends to its replicas.
+const REPL_PING_PERIOD: u64 = 10;
+
+pub(crate) const WRONG_TYPE_ERROR: &str =
+    "WRONGTYPE Operation against a key holding the wrong kind of value";
+
 #[derive(Hash, Debug, Clone)]
 struct Ttl {
     expiration: u128,
@@ -27,25 +46,87 @@ impl Ttl {
 
         Self {
             expiration,
-            ttl: now + expiration,
+            ttl: now.saturating_add(expiration),
+        }
+    }
+
+    fn at(expire_at: u128) -> Self {
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .expect("SystemTime before UNIX EPOCH!")
+            .as_millis();
+
+        Self {
+            expiration: expire_at.saturating_sub(now),
+            ttl: expire_at,
         }
     }
 }
 
-#[derive(Hash, Debug, Clone)]
+#[derive(Debug, Clone)]
+pub(crate) enum Value {
+    String(Vec<u8>),
+    SortedSet(SortedSet),
+}
+
+impl Value {
+    pub(crate) fn as_string(&self) -> anyhow::Result<&Vec<u8>> {
+        match self {
+            Value::String(value) => Ok(value),
+            _ => anyhow::bail!(WRONG_TYPE_ERROR),
+        }
+    }
+
+    pub(crate) fn as_sorted_set(&self) -> anyhow::Result<&SortedSet> {
+        match self {
+            Value::SortedSet(value) => Ok(value),
+            _ => anyhow::bail!(WRONG_TYPE_ERROR),
+        }
+    }
+
+    pub(crate) fn as_sorted_set_mut(&mut self) -> anyhow::Result<&mut SortedSet> {
+        match self {
+            Value::SortedSet(value) => Ok(value),
+            _ => anyhow::bail!(WRONG_TYPE_ERROR),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub(crate) struct Entry {
-    pub(crate) value: String,
+    pub(crate) value: Value,
     ttl: Option<Ttl>,
 }
 
 impl Entry {
-    pub(crate) fn new(value: String, expiration: Option<u128>) -> Self {
+    pub(crate) fn new(value: Value, expiration: Option<u128>) -> Self {
         Self {
             value,
             ttl: expiration.map(Ttl::new),
         }
     }
 
+    /// Creates an entry expiring at an absolute unix time in milliseconds.
+    pub(crate) fn with_expire_at(value: Value, expire_at: u128) -> Self {
+        Self {
+            value,
+            ttl: Some(Ttl::at(expire_at)),
+        }
+    }
+
+    /// Returns the unix time in milliseconds the entry expires at.
+    pub(crate) fn expire_at(&self) -> Option<u128> {
+        self.ttl.as_ref().map(|ttl| ttl.ttl)
+    }
+
+    pub(crate) fn set_expire_at(&mut self, expire_at: u128) {
+        self.ttl = Some(Ttl::at(expire_at));
+    }
+
+    pub(crate) fn set_value(&mut self, value: Value) {
+        self.value = value;
+    }
+
     pub(crate) fn has_ttl(&self) -> bool {
         self.ttl.is_some()
     }
@@ -78,34 +159,237 @@ impl fmt::Display for ServerMode {
 }
 
 #[derive(Debug)]
-pub(crate) enum State {
-    Master {
-        version: String,
-        mode: ServerMode,
-        os: String,
-        arch_bits: String,
-        replication_id: String,
-        replication_offset: usize,
-        entries: tokio::sync::Mutex<HashMap<String, Entry>>,
-        tx: tokio::sync::broadcast::Sender<Message>,
-    },
-    Slave {
-        version: String,
-        mode: ServerMode,
-        os: String,
-        arch_bits: String,
-        master_address: String,
-        entries: tokio::sync::Mutex<HashMap<String, Entry>>,
-    },
+pub(crate) struct State {
+    pub(crate) version: String,
+    pub(crate) mode: ServerMode,
+    pub(crate) os: String,
+    pub(crate) arch_bits: String,
+}
+
+/// The dataset, only accessed while holding the `Db` lock.
+#[derive(Debug, Default)]
+pub(crate) struct Keyspace {
+    entries: HashMap<String, Entry>,
+    /// Dirty flags of the connections watching each key.
+    watched_keys: HashMap<String, Vec<Arc<AtomicBool>>>,
+    /// Events waiting to be published as keyspace notifications.
+    events: Vec<KeyspaceEvent>,
+    /// Keys read and modified by the running command, for client side caching.
+    reads: Vec<String>,
+    modified: Vec<String>,
+    /// Changes since the last successful snapshot.
+    dirty: u64,
+    /// Keys deleted because they expired, propagated as `DEL`s.
+    expired: Vec<String>,
+    /// Set on replicas, which keep expired keys until their master deletes them.
+    replica: bool,
+    /// Set while the AOF is replayed: its writes are already persisted, and keys are only
+    /// expired once it is fully loaded.
+    loading: bool,
+}
+
+impl Keyspace {
+    /// Returns the entry of a key, unless it expired.
+    pub(crate) fn get(&mut self, key: &str) -> Option<&Entry> {
+        self.expire_if_needed(key);
+
+        self.entries.get(key).filter(|entry| !entry.is_expired())
+    }
+
+    /// Looks up a key for reading, notifying a key miss when it doesn't exist.
+    pub(crate) fn lookup_read(&mut self, key: &str) -> Option<&Entry> {
+        self.record_read(key);
+        if self.get(key).is_none() {
+            self.notify(EventClass::KeyMiss, "keymiss", key);
+        }
+
+        self.entries.get(key).filter(|entry| !entry.is_expired())
+    }
+
+    pub(crate) fn insert(&mut self, key: String, value: Entry) {
+        self.expire_if_needed(&key);
+        if !self.entries.contains_key(&key) {
+            self.notify(EventClass::New, "new", &key);
+        }
+
+        self.touch(&key);
+        self.entries.insert(key, value);
+    }
+
+    /// Stores a key loaded from a snapshot, without notifying anyone.
+    pub(crate) fn load(&mut self, key: String, value: Entry) {
+        self.entries.insert(key, value);
+    }
+
+    /// Replaces the value of a key with an equivalent one, like a HyperLogLog caching its
+    /// cardinality. It isn't a change: the key isn't touched, and no one is notified.
+    pub(crate) fn update_cache(&mut self, key: &str, value: Value) {
+        if let Some(entry) = self.entries.get_mut(key) {
+            entry.set_value(value);
+        }
+    }
+
+    pub(crate) fn entries(&self) -> impl Iterator<Item = (&String, &Entry)> {
+        self.entries.iter()
+    }
+
+    pub(crate) fn dirty(&self) -> u64 {
+        self.dirty
+    }
+
+    /// Forgets the changes written by a snapshot, keeping the ones made while it was written.
+    fn clear_dirty(&mut self, saved: u64) {
+        self.dirty = self.dirty.saturating_sub(saved);
+    }
+
+    pub(crate) fn remove(&mut self, key: &str) -> bool {
+        let removed = self.entries.remove(key).is_some();
+        if removed {
+            self.touch(key);
+        }
+
+        removed
+    }
+
+    pub(crate) fn clear(&mut self) {
+        let keys: Vec<String> = self.entries.keys().cloned().collect();
+        for key in keys {
+            self.remove(&key);
+        }
+    }
+
+    pub(crate) fn notify(&mut self, class: EventClass, event: &'static str, key: &str) {
+        self.events.push(KeyspaceEvent {
+            class,
+            event,
+            key: key.to_string(),
+        });
+    }
+
+    pub(crate) fn take_events(&mut self) -> Vec<KeyspaceEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    /// Records a key read by the running command, so tracking connections can cache it.
+    pub(crate) fn record_read(&mut self, key: &str) {
+        self.reads.push(key.to_string());
+    }
+
+    pub(crate) fn take_reads(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.reads)
+    }
+
+    pub(crate) fn take_modified(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.modified)
+    }
+
+    pub(crate) fn take_expired(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.expired)
+    }
+
+    pub(crate) fn set_replica(&mut self, replica: bool) {
+        self.replica = replica;
+    }
+
+    pub(crate) fn set_loading(&mut self, loading: bool) {
+        self.loading = loading;
+    }
+
+    pub(crate) fn is_loading(&self) -> bool {
+        self.loading
+    }
+
+    /// Whether keys are deleted once expired, which only a master does out of loading.
+    pub(crate) fn expires_keys(&self) -> bool {
+        !self.replica && !self.loading
+    }
+
+    fn expire_if_needed(&mut self, key: &str) {
+        if self.expires_keys() && self.entries.get(key).is_some_and(Entry::is_expired) {
+            self.remove(key);
+            self.notify(EventClass::Expired, "expired", key);
+            self.expired.push(key.to_string());
+        }
+    }
+
+    /// Removes the expired keys, returning them.
+    fn remove_expired(&mut self) -> Vec<String> {
+        if !self.expires_keys() {
+            return Vec::new();
+        }
+
+        let expired: Vec<String> = self
+            .entries
+            .iter()
+            .filter(|(_, value)| value.has_ttl() && value.is_expired())
+            .map(|(key, _)| key.to_string())
+            .collect();
+
+        for key in &expired {
+            self.remove(key);
+            self.notify(EventClass::Expired, "expired", key);
+        }
+        self.expired.extend(expired.iter().cloned());
+
+        expired
+    }
+
+    /// Returns whether the key is stored but already logically expired.
+    pub(crate) fn is_expired(&self, key: &str) -> bool {
+        self.entries.get(key).is_some_and(Entry::is_expired)
+    }
+
+    /// Registers the dirty flag of a connection watching the key, returning whether the key
+    /// currently exists.
+    pub(crate) fn watch(&mut self, key: &str, dirty: &Arc<AtomicBool>) -> bool {
+        self.watched_keys
+            .entry(key.to_string())
+            .or_default()
+            .push(dirty.clone());
+
+        self.entries
+            .get(key)
+            .is_some_and(|entry| !entry.is_expired())
+    }
+
+    pub(crate) fn unwatch(&mut self, key: &str, dirty: &Arc<AtomicBool>) {
+        if let Some(watchers) = self.watched_keys.get_mut(key) {
+            watchers.retain(|watcher| !Arc::ptr_eq(watcher, dirty));
+            if watchers.is_empty() {
+                self.watched_keys.remove(key);
+            }
+        }
+    }
+
+    /// Flags every connection watching the key, so their next `EXEC` fails, and records it for
+    /// the invalidation of client side caches.
+    fn touch(&mut self, key: &str) {
+        for dirty in self.watched_keys.get(key).into_iter().flatten() {
+            dirty.store(true, Ordering::SeqCst);
+        }
+        self.modified.push(key.to_string());
+        if !self.loading {
+            self.dirty += 1;
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub(crate) struct Db {
     pub(crate) state: Arc<State>,
+    keyspace: Arc<Mutex<Keyspace>>,
+    pubsub: Arc<Mutex<PubSub>>,
+    config: Arc<Mutex<Config>>,
+    tracking: Arc<Mutex<TrackingTable>>,
+    save_status: Arc<Mutex<SaveStatus>>,
+    /// Open while `appendonly` is enabled.
+    aof: Arc<Mutex<Option<Aof>>>,
+    replication: Arc<Mutex<Replication>>,
+    replicas: Arc<Mutex<Replicas>>,
 }
 
 impl Db {
-    pub(crate) fn new(replica_of: Option<String>) -> Self {
+    pub(crate) fn new(replica_of: Option<String>, config: Config) -> Self {
         let version = String::from("0.0.0");
         let os = env::consts::OS.to_string();
         let mode = ServerMode::Standalone;
@@ -115,7 +399,7 @@ impl Db {
             String::from("32")
         };
 
-        if let Some(replica_of) = replica_of {
+        let role = if let Some(replica_of) = replica_of {
             let mut address_parts = replica_of.splitn(2, ' ');
             let host = address_parts
                 .next()
@@ -126,85 +410,508 @@ impl Db {
                 .parse()
                 .expect("replica_of port should be a integer");
 
-            Self {
-                state: Arc::new(State::Slave {
-                    version,
-                    mode,
-                    os,
-                    arch_bits,
-                    master_address: format!("{}:{}", host, port),
-                    entries: tokio::sync::Mutex::new(HashMap::new()),
-                }),
+            Role::Replica {
+                master_address: format!("{}:{}", host, port),
             }
         } else {
-            let (tx, mut _rx) = broadcast::channel(1024);
+            Role::Master
+        };
+        let mut keyspace = Keyspace::default();
+        keyspace.set_replica(role != Role::Master);
+        let state = State {
+            version,
+            mode,
+            os,
+            arch_bits,
+        };
+
+        Self {
+            state: Arc::new(state),
+            keyspace: Arc::new(Mutex::new(keyspace)),
+            pubsub: Arc::new(Mutex::new(PubSub::default())),
+            config: Arc::new(Mutex::new(config)),
+            tracking: Arc::new(Mutex::new(TrackingTable::default())),
+            save_status: Arc::new(Mutex::new(SaveStatus::new())),
+            aof: Arc::new(Mutex::new(None)),
+            replication: Arc::new(Mutex::new(Replication::new(replication::random_id(), role))),
+            replicas: Arc::new(Mutex::new(Replicas::default())),
+        }
+    }
 
-            Self {
-                state: Arc::new(State::Master {
-                    version,
-                    mode,
-                    os,
-                    arch_bits,
-                    replication_id: String::from("8371b4fb1155b71f4a04d3e1bc3e18c4a990aeeb"),
-                    replication_offset: 0,
-                    entries: tokio::sync::Mutex::new(HashMap::new()),
-                    tx,
-                }),
+    /// Loads the RDB file of the `dir` and `dbfilename` configuration, when there is one.
+    pub(crate) fn load_rdb(&self) -> anyhow::Result<()> {
+        let path = self.config().rdb_path();
+        let bytes = match fs::read(&path) {
+            Ok(bytes) => bytes,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => {
+                return Err(err).with_context(|| format!("Failed to read {}", path.display()))
             }
+        };
+
+        let snapshot =
+            rdb::parse(&bytes).with_context(|| format!("Failed to load {}", path.display()))?;
+        let position = snapshot.replication_position();
+        let loaded = self.load_snapshot(&mut self.keyspace(), snapshot);
+        println!("Loaded {} keys from {}", loaded, path.display());
+
+        if let Some(position) = position {
+            self.restore_replication(position);
         }
+
+        Ok(())
     }
 
-    async fn get_entries(&self) -> tokio::sync::MutexGuard<HashMap<String, Entry>> {
-        match &*self.state {
-            State::Master { entries, .. } | State::Slave { entries, .. } => entries.lock().await,
+    /// Continues the replication stream the RDB file was saved at. A replica tries to continue
+    /// it with its master, while a master keeps it as its previous stream so the replicas that
+    /// followed it can continue from this server.
+    fn restore_replication(&self, position: rdb::ReplicationPosition) {
+        let mut replication = self.replication();
+        let id = replication.id.clone();
+
+        replication.reset(position.id, position.offset);
+        if replication.is_master() {
+            replication.shift_id(id);
         }
+        println!(
+            "Restored replication id {} at offset {}",
+            replication.id, replication.offset
+        );
+    }
+
+    /// Adds the keys of an RDB file to the dataset, returning how many were loaded.
+    pub(crate) fn load_rdb_bytes(&self, bytes: &[u8]) -> anyhow::Result<usize> {
+        let snapshot = rdb::parse(bytes)?;
+
+        Ok(self.load_snapshot(&mut self.keyspace(), snapshot))
     }
 
-    pub(crate) async fn get_key(&self, key: &String) -> Option<Entry> {
-        let mut entries = self.get_entries().await;
+    /// Replaces the dataset with the snapshot sent by the master on a full resynchronization,
+    /// following its stream from `offset`.
+    pub(crate) fn load_full_resync(
+        &self,
+        bytes: &[u8],
+        id: String,
+        offset: usize,
+    ) -> anyhow::Result<()> {
+        let snapshot = rdb::parse(bytes).context("Failed to load the master snapshot")?;
 
-        match entries.get(key) {
-            Some(value) if value.is_expired() => {
-                entries.remove(key);
-                None
+        let mut keyspace = self.keyspace();
+        keyspace.clear();
+        let loaded = self.load_snapshot(&mut keyspace, snapshot);
+        self.update_tracking(&mut keyspace, None, None);
+        println!("Loaded {} keys from master", loaded);
+
+        let mut replication = self.replication();
+        replication.reset(id, offset);
+        replication.disconnect_replicas();
+
+        Ok(())
+    }
+
+    /// Stores the keys of a snapshot, returning how many were loaded.
+    fn load_snapshot(&self, keyspace: &mut Keyspace, snapshot: rdb::Snapshot) -> usize {
+        // Replicas keep expired keys until their master deletes them.
+        let is_master = self.is_master();
+
+        let mut loaded = 0;
+        for (key, entry) in snapshot.entries {
+            if is_master && entry.is_expired() {
+                continue;
             }
-            Some(value) => Some(value.clone()),
-            None => None,
+            keyspace.load(key, entry);
+            loaded += 1;
         }
+
+        loaded
+    }
+
+    /// Locks the keyspace. Commands run while holding it, so each one is atomic. A command that
+    /// panicked doesn't take the server down with it: the locks it poisoned are still taken.
+    pub(crate) fn keyspace(&self) -> MutexGuard<'_, Keyspace> {
+        self.keyspace.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Locks the pub/sub subscriptions. When both are needed, the keyspace is locked first.
+    pub(crate) fn pubsub(&self) -> MutexGuard<'_, PubSub> {
+        self.pubsub.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub(crate) fn config(&self) -> MutexGuard<'_, Config> {
+        self.config.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub(crate) fn aof(&self) -> MutexGuard<'_, Option<Aof>> {
+        self.aof.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Starts appending the propagated commands to a new AOF, its base is written by a rewrite.
+    pub(crate) fn enable_aof(&self, keyspace: &Keyspace) -> anyhow::Result<()> {
+        *self.aof() = Some(aof::create(self)?);
+
+        self.rewrite_aof(keyspace)
+    }
+
+    pub(crate) fn disable_aof(&self) {
+        *self.aof() = None;
+    }
+
+    /// Writes the keyspace as the base of the AOF in a blocking task. Meanwhile writes are
+    /// appended to a new incremental file, so none are lost when the base replaces the others.
+    /// Like Redis, a rewrite while the AOF is disabled writes a new AOF without enabling it.
+    pub(crate) fn rewrite_aof(&self, keyspace: &Keyspace) -> anyhow::Result<()> {
+        let (target, offline_dir) = match self.aof().as_mut() {
+            Some(aof) => (aof.start_rewrite()?, None),
+            None => {
+                let (dir, filename) = {
+                    let config = self.config();
+                    (config.aof_dir(), config.appendfilename.clone())
+                };
+                fs::create_dir_all(&dir)
+                    .with_context(|| format!("Failed to create {}", dir.display()))?;
+                (aof::RewriteTarget::new(&dir), Some((dir, filename)))
+            }
+        };
+
+        let entries: Vec<(String, Entry)> = keyspace
+            .entries()
+            .map(|(key, entry)| (key.clone(), entry.clone()))
+            .collect();
+
+        let db = self.clone();
+        tokio::task::spawn_blocking(move || {
+            let bytes = rdb::serialize(entries.iter().map(|(key, entry)| (key, entry)), None);
+            let result = fs::write(&target.path, &bytes)
+                .with_context(|| format!("Failed to write {}", target.path.display()));
+
+            // The AOF may have been enabled, disabled, or replaced by enabling it again meanwhile.
+            let mut aof = db.aof();
+            let finished = match (aof.as_mut(), offline_dir) {
+                (Some(aof), None) => aof.finish_rewrite(&target, result),
+                (None, Some((dir, filename))) => {
+                    match result.and_then(|()| aof::replace_with_base(&dir, &filename, &target)) {
+                        Ok(()) => {
+                            println!("Background AOF rewrite finished successfully");
+                            true
+                        }
+                        Err(err) => {
+                            eprintln!("AOF REWRITE ERROR: {:?}", err);
+                            false
+                        }
+                    }
+                }
+                _ => false,
+            };
+            if !finished {
+                let _ = fs::remove_file(&target.path);
+            }
+        });
+
+        Ok(())
+    }
+
+    pub(crate) fn replicas(&self) -> MutexGuard<'_, Replicas> {
+        self.replicas.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Locks the replication stream. When both are needed, the keyspace is locked first.
+    pub(crate) fn replication(&self) -> MutexGuard<'_, Replication> {
+        self.replication
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub(crate) fn is_master(&self) -> bool {
+        self.replication().is_master()
     }
 
-    pub(crate) async fn insert_key(&self, key: String, value: Entry) {
-        let mut entries = self.get_entries().await;
+    /// Returns the bytes of commands propagated by a master, or processed by a replica.
+    pub(crate) fn replication_offset(&self) -> usize {
+        self.replication().offset
+    }
 
-        entries.insert(key.clone(), value.clone());
+    /// Adds bytes to the replication stream, its backlog and the output buffers of the replicas,
+    /// returning how many replicas receive them. Called while holding the keyspace lock, so
+    /// replicas synchronizing get every byte after their snapshot.
+    pub(crate) fn feed_replication(&self, bytes: &[u8]) -> usize {
+        let (backlog_size, limit) = {
+            let config = self.config();
+            (config.repl_backlog_size, config.replica_output_buffer_limit)
+        };
+        let mut replication = self.replication();
+        replication.feed(bytes, backlog_size as usize);
 
-        if let State::Master { .. } = &*self.state {
-            self.propagate_command_to_replicas(&set::SetCommand::new_command(
-                key,
-                value.value,
-                value.ttl.map(|value| value.expiration),
-            ));
+        replication.send(bytes, &limit)
+    }
+
+    pub(crate) fn save_status(&self) -> MutexGuard<'_, SaveStatus> {
+        self.save_status
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Writes the RDB file while holding the keyspace lock, blocking every client.
+    pub(crate) fn save(&self, keyspace: &mut Keyspace) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            !self.save_status().bgsave_in_progress,
+            "ERR Background save already in progress"
+        );
+
+        let path = self.config().rdb_path();
+        let position = self.replication().position();
+        let bytes = rdb::serialize(keyspace.entries(), position.as_ref());
+        if let Err(err) = rdb::write(&path, &bytes) {
+            eprintln!("SAVE ERROR: {:?}", err);
+            anyhow::bail!("ERR");
         }
+
+        keyspace.clear_dirty(keyspace.dirty());
+        self.save_status().last_save = rdb::unix_time();
+        println!("DB saved on disk");
+
+        Ok(())
+    }
+
+    /// Writes the RDB file in a blocking task. Clients are only blocked while the keys are
+    /// copied, and the file holds the keyspace as it was at that point.
+    pub(crate) fn bgsave(&self, keyspace: &Keyspace) -> anyhow::Result<()> {
+        {
+            let mut status = self.save_status();
+            anyhow::ensure!(
+                !status.bgsave_in_progress,
+                "ERR Background save already in progress"
+            );
+            status.bgsave_in_progress = true;
+            status.last_bgsave_try = rdb::unix_time();
+        }
+
+        let entries: Vec<(String, Entry)> = keyspace
+            .entries()
+            .map(|(key, entry)| (key.clone(), entry.clone()))
+            .collect();
+        let dirty = keyspace.dirty();
+        let path = self.config().rdb_path();
+        let position = self.replication().position();
+
+        let db = self.clone();
+        tokio::task::spawn_blocking(move || {
+            let bytes = rdb::serialize(
+                entries.iter().map(|(key, entry)| (key, entry)),
+                position.as_ref(),
+            );
+            let result = rdb::write(&path, &bytes);
+
+            let mut keyspace = db.keyspace();
+            let mut status = db.save_status();
+            status.bgsave_in_progress = false;
+            status.last_bgsave_ok = result.is_ok();
+            match result {
+                Ok(()) => {
+                    keyspace.clear_dirty(dirty);
+                    status.last_save = rdb::unix_time();
+                    println!("Background saving terminated with success");
+                }
+                Err(err) => eprintln!("BACKGROUND SAVE ERROR: {:?}", err),
+            }
+        });
+
+        Ok(())
     }
 
-    fn propagate_command_to_replicas(&self, command: &impl Command) {
-        match &*self.state {
-            State::Master { tx, .. } => {
-                let receiver_count = tx.receiver_count();
-                if receiver_count > 0 {
-                    if let Err(err) = tx.send(command.to_message()) {
-                        eprintln!("COMMAND {} PROPAGATION ERROR PROPAGATING: {}", command, err);
-                    } else {
-                        println!(
-                            "Command {} propagated to {} receivers",
-                            command, receiver_count
-                        );
+    pub(crate) fn tracking(&self) -> MutexGuard<'_, TrackingTable> {
+        self.tracking.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Remembers the keys read by a tracking connection and invalidates the modified keys. The
+    /// `caching` argument is the `CLIENT CACHING` choice made for the command.
+    pub(crate) fn update_tracking(
+        &self,
+        keyspace: &mut Keyspace,
+        client_id: Option<u64>,
+        caching: Option<bool>,
+    ) {
+        let reads = keyspace.take_reads();
+        let modified = keyspace.take_modified();
+        if reads.is_empty() && modified.is_empty() {
+            return;
+        }
+
+        let mut tracking = self.tracking();
+        tracking.invalidate(&modified, client_id);
+
+        let Some(client_id) = client_id else {
+            return;
+        };
+        let track = match tracking.options(client_id) {
+            Some(options) if options.bcast => false,
+            Some(options) if options.optin => caching == Some(true),
+            Some(options) if options.optout => caching != Some(false),
+            Some(_) => true,
+            None => false,
+        };
+        if track {
+            tracking.track_keys(client_id, reads);
+        }
+    }
+
+    /// Publishes the keyspace notifications enabled in `notify-keyspace-events`.
+    pub(crate) fn notify_keyspace_events(&self, events: Vec<KeyspaceEvent>) {
+        if events.is_empty() {
+            return;
+        }
+
+        let flags = self.config().notify_keyspace_events;
+        flags.publish(&self.pubsub(), &events);
+    }
+
+    /// Appends the commands to the AOF and sends them to the replicas, it must be called while
+    /// holding the keyspace lock so they are applied in the same order as on this server.
+    pub(crate) fn propagate(&self, messages: Vec<Message>) {
+        if !messages.is_empty() {
+            let fsync = self.config().appendfsync;
+            if let Some(aof) = self.aof().as_mut() {
+                if let Err(err) = aof.append(&messages, fsync) {
+                    eprintln!("AOF WRITE ERROR: {}", err);
+                }
+            }
+        }
+
+        self.send_to_replicas(messages);
+    }
+
+    /// Adds the messages to the replication stream of a master, without writing them to the AOF.
+    pub(crate) fn send_to_replicas(&self, messages: Vec<Message>) {
+        if !self.is_master() {
+            return;
+        }
+
+        for message in messages {
+            let mut buf = Vec::new();
+            message.serialize(&mut buf);
+
+            let receiver_count = self.feed_replication(&buf);
+            if receiver_count > 0 {
+                println!(
+                    "Command {} propagated to {} receivers",
+                    message, receiver_count
+                );
+            } else {
+                println!("No receiver found {}", receiver_count);
+            }
+        }
+    }
+
+    /// Starts a background save whenever one of the `save` points is reached.
+    pub(crate) async fn run_save_points(&self) {
+        loop {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+
+            let keyspace = self.keyspace();
+            let save_points = self.config().save.clone();
+            let now = rdb::unix_time();
+            let (since_last_save, can_retry) = {
+                let status = self.save_status();
+                // A failed save is only retried after a few seconds.
+                let can_retry = !status.bgsave_in_progress
+                    && (status.last_bgsave_ok
+                        || now.saturating_sub(status.last_bgsave_try) > BGSAVE_RETRY_DELAY);
+
+                (now.saturating_sub(status.last_save), can_retry)
+            };
+            if !can_retry {
+                continue;
+            }
+
+            let reached = save_points
+                .iter()
+                .find(|point| keyspace.dirty() >= point.changes && since_last_save > point.seconds);
+            if let Some(point) = reached {
+                println!(
+                    "{} changes in {} seconds. Saving...",
+                    point.changes, point.seconds
+                );
+                if let Err(err) = self.bgsave(&keyspace) {
+                    eprintln!("BACKGROUND SAVE ERROR: {:?}", err);
+                }
+            }
+        }
+    }
+
+    /// Flushes the AOF to the disk every second for the `everysec` policy, and rewrites it once
+    /// it grew past `auto-aof-rewrite-percentage`.
+    pub(crate) async fn run_aof_cron(&self) {
+        loop {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+
+            let (fsync, percentage, min_size) = {
+                let config = self.config();
+                (
+                    config.appendfsync,
+                    config.auto_aof_rewrite_percentage,
+                    config.auto_aof_rewrite_min_size,
+                )
+            };
+
+            {
+                let keyspace = self.keyspace();
+                let needs_rewrite = self
+                    .aof()
+                    .as_ref()
+                    .is_some_and(|aof| aof.needs_rewrite(percentage, min_size));
+                if needs_rewrite {
+                    println!("Starting automatic rewriting of AOF");
+                    if let Err(err) = self.rewrite_aof(&keyspace) {
+                        eprintln!("AOF REWRITE ERROR: {:?}", err);
                     }
-                } else {
-                    println!("No receiver found {}", receiver_count);
                 }
             }
-            State::Slave { .. } => unreachable!(),
+
+            if fsync != AppendFsync::EverySec {
+                continue;
+            }
+            let Some(file) = self.aof().as_mut().and_then(Aof::take_unsynced) else {
+                continue;
+            };
+
+            let result = tokio::task::spawn_blocking(move || file.sync_data()).await;
+            if let Ok(Err(err)) = result {
+                eprintln!("AOF FSYNC ERROR: {}", err);
+            }
+        }
+    }
+
+    /// Pings the replicas of a master so they can tell a broken link from an idle one, and
+    /// frees its backlog once it had no replica for `repl-backlog-ttl` seconds. Replicas keep
+    /// theirs, as they may be promoted.
+    pub(crate) async fn run_replication_cron(&self) {
+        let mut seconds = 0;
+
+        loop {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            seconds += 1;
+
+            if !self.is_master() {
+                continue;
+            }
+
+            // The stream is only written while holding the keyspace lock, and `PSYNC` creates the
+            // backlog while holding it.
+            let _keyspace = self.keyspace();
+            let empty_for = self.replicas().empty_for();
+            if empty_for.is_none() && seconds % REPL_PING_PERIOD == 0 {
+                self.send_to_replicas(vec![Message::array(vec![Message::bulk_string("PING")])]);
+            }
+
+            let ttl = self.config().repl_backlog_ttl;
+            let expired = ttl > 0 && empty_for.is_some_and(|duration| duration.as_secs() >= ttl);
+            let mut replication = self.replication();
+            if expired && replication.has_backlog() {
+                replication.free_backlog();
+                println!(
+                    "Replication backlog freed after {} seconds without connected replicas",
+                    ttl
+                );
+            }
         }
     }
 
@@ -216,17 +923,18 @@ impl Db {
         loop {
             tokio::time::sleep(Duration::from_secs(1)).await;
 
-            let mut entries = self.get_entries().await;
-
-            let entries_to_check = entries.clone();
-            let keys_to_delete = entries_to_check.iter().filter(|(_, value)| value.has_ttl());
-
-            for (key, value) in keys_to_delete {
-                if value.is_expired() {
-                    entries.remove(key);
-                    println!("Entry {} removed", key);
-                }
+            let mut keyspace = self.keyspace();
+            for key in keyspace.remove_expired() {
+                println!("Entry {} removed", key);
             }
+            let deleted = keyspace
+                .take_expired()
+                .into_iter()
+                .map(|key| DelCommand::new_command(vec![key]).to_message())
+                .collect();
+            self.propagate(deleted);
+            self.notify_keyspace_events(keyspace.take_events());
+            self.update_tracking(&mut keyspace, None, None);
         }
     }
 }
@@ -0,0 +1,539 @@
+// Geohash encoding and distance math matching Redis.
+// https://github.com/redis/redis/blob/unstable/src/geohash.c
+// https://github.com/redis/redis/blob/unstable/src/geohash_helper.c
+
+use std::ops::Range;
+
+use anyhow::Context;
+
+use crate::message::BulkString;
+
+const LONGITUDE_MIN: f64 = -180.0;
+const LONGITUDE_MAX: f64 = 180.0;
+const LATITUDE_MIN: f64 = -85.051_128_78;
+const LATITUDE_MAX: f64 = 85.051_128_78;
+
+const STEP_MAX: u32 = 26;
+const EARTH_RADIUS_IN_METERS: f64 = 6_372_797.560_856;
+const MERCATOR_MAX: f64 = 20_037_726.37;
+const GEOHASH_ALPHABET: &[u8; 32] = b"0123456789bcdefghjkmnpqrstuvwxyz";
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Coordinates {
+    pub(crate) longitude: f64,
+    pub(crate) latitude: f64,
+}
+
+impl Coordinates {
+    pub(crate) fn new(longitude: f64, latitude: f64) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            (LONGITUDE_MIN..=LONGITUDE_MAX).contains(&longitude)
+                && (LATITUDE_MIN..=LATITUDE_MAX).contains(&latitude),
+            "ERR invalid longitude,latitude pair {:.6},{:.6}",
+            longitude,
+            latitude
+        );
+
+        Ok(Self {
+            longitude,
+            latitude,
+        })
+    }
+
+    pub(crate) fn parse(longitude: &BulkString, latitude: &BulkString) -> anyhow::Result<Self> {
+        let longitude = parse_float(longitude)?;
+        let latitude = parse_float(latitude)?;
+
+        Self::new(longitude, latitude)
+    }
+
+    /// Encodes the coordinates into the 52-bit interleaved geohash used as sorted set score.
+    pub(crate) fn to_score(self) -> f64 {
+        encode(self, LATITUDE_MIN, LATITUDE_MAX, STEP_MAX) as f64
+    }
+
+    /// Decodes a sorted set score to the center of its geohash cell.
+    pub(crate) fn from_score(score: f64) -> Self {
+        let bounds = Cell {
+            bits: score as u64,
+            step: STEP_MAX,
+        }
+        .bounds();
+
+        Self {
+            longitude: ((bounds.longitude_min + bounds.longitude_max) / 2.0)
+                .clamp(LONGITUDE_MIN, LONGITUDE_MAX),
+            latitude: ((bounds.latitude_min + bounds.latitude_max) / 2.0)
+                .clamp(LATITUDE_MIN, LATITUDE_MAX),
+        }
+    }
+
+    /// Returns the standard 11 characters geohash, which uses the [-90, 90] latitude range.
+    pub(crate) fn geohash(self) -> String {
+        let bits = encode(self, -90.0, 90.0, STEP_MAX);
+
+        (0..11)
+            .map(|i| {
+                let index = if i == 10 {
+                    0
+                } else {
+                    (bits >> (52 - (i + 1) * 5)) & 0x1f
+                };
+                GEOHASH_ALPHABET[index as usize] as char
+            })
+            .collect()
+    }
+
+    pub(crate) fn distance(self, other: Coordinates) -> f64 {
+        let latitude_1 = self.latitude.to_radians();
+        let longitude_1 = self.longitude.to_radians();
+        let latitude_2 = other.latitude.to_radians();
+        let longitude_2 = other.longitude.to_radians();
+
+        let v = ((longitude_2 - longitude_1) / 2.0).sin();
+        if v == 0.0 {
+            return latitude_distance(self.latitude, other.latitude);
+        }
+
+        let u = ((latitude_2 - latitude_1) / 2.0).sin();
+        let a = u * u + latitude_1.cos() * latitude_2.cos() * v * v;
+
+        2.0 * EARTH_RADIUS_IN_METERS * a.sqrt().asin()
+    }
+
+    /// Returns the distance to `point` if it is inside the box centered on these coordinates.
+    pub(crate) fn distance_in_box(
+        self,
+        width: f64,
+        height: f64,
+        point: Coordinates,
+    ) -> Option<f64> {
+        if latitude_distance(point.latitude, self.latitude) > height / 2.0 {
+            return None;
+        }
+
+        let longitude_distance = point.distance(Coordinates {
+            longitude: self.longitude,
+            latitude: point.latitude,
+        });
+        if longitude_distance > width / 2.0 {
+            return None;
+        }
+
+        Some(self.distance(point))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bounds {
+    longitude_min: f64,
+    longitude_max: f64,
+    latitude_min: f64,
+    latitude_max: f64,
+}
+
+impl Bounds {
+    /// Returns the bounds of a box of `width` by `height` meters centered on `center`.
+    fn of_box(center: Coordinates, width: f64, height: f64) -> Self {
+        let latitude_delta = (height / 2.0 / EARTH_RADIUS_IN_METERS).to_degrees();
+        let longitude_delta = |latitude: f64| {
+            (width / 2.0 / EARTH_RADIUS_IN_METERS / latitude.to_radians().cos()).to_degrees()
+        };
+        // The same distance spans more longitude on the side closest to the pole.
+        let longitude_delta = if center.latitude < 0.0 {
+            longitude_delta(center.latitude - latitude_delta)
+        } else {
+            longitude_delta(center.latitude + latitude_delta)
+        };
+
+        Self {
+            longitude_min: center.longitude - longitude_delta,
+            longitude_max: center.longitude + longitude_delta,
+            latitude_min: center.latitude - latitude_delta,
+            latitude_max: center.latitude + latitude_delta,
+        }
+    }
+}
+
+/// A geohash cell, `step` bits of each coordinate interleaved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Cell {
+    bits: u64,
+    step: u32,
+}
+
+impl Cell {
+    fn new(coordinates: Coordinates, step: u32) -> Self {
+        Self {
+            bits: encode(coordinates, LATITUDE_MIN, LATITUDE_MAX, step),
+            step,
+        }
+    }
+
+    fn bounds(self) -> Bounds {
+        let latitude_bits = squash(self.bits);
+        let longitude_bits = squash(self.bits >> 1);
+
+        let cells = (1_u64 << self.step) as f64;
+        let latitude_scale = LATITUDE_MAX - LATITUDE_MIN;
+        let longitude_scale = LONGITUDE_MAX - LONGITUDE_MIN;
+
+        Bounds {
+            longitude_min: LONGITUDE_MIN + (longitude_bits as f64 / cells) * longitude_scale,
+            longitude_max: LONGITUDE_MIN + ((longitude_bits + 1) as f64 / cells) * longitude_scale,
+            latitude_min: LATITUDE_MIN + (latitude_bits as f64 / cells) * latitude_scale,
+            latitude_max: LATITUDE_MIN + ((latitude_bits + 1) as f64 / cells) * latitude_scale,
+        }
+    }
+
+    /// Returns the cell `east` cells to the east and `north` cells to the north, wrapping around.
+    fn neighbour(self, east: i8, north: i8) -> Self {
+        let bits = move_coordinate(self.bits, self.step, east, 0xaaaa_aaaa_aaaa_aaaa);
+        let bits = move_coordinate(bits, self.step, north, 0x5555_5555_5555_5555);
+
+        Self { bits, ..self }
+    }
+
+    /// Returns the range of the scores of the points inside the cell.
+    fn scores(self) -> Range<f64> {
+        let shift = 2 * (STEP_MAX - self.step);
+
+        (self.bits << shift) as f64..((self.bits + 1) << shift) as f64
+    }
+}
+
+/// Returns the ranges of scores to scan for the points within `radius` meters of `center`, in a
+/// box of `width` by `height` meters around it. Like Redis, these are the geohash cell of the
+/// center, large enough for the radius, and its 8 neighbours except those outside of the box.
+pub(crate) fn search_ranges(
+    center: Coordinates,
+    radius: f64,
+    width: f64,
+    height: f64,
+) -> Vec<Range<f64>> {
+    let bounds = Bounds::of_box(center, width, height);
+
+    let mut step = estimate_step(radius, center.latitude);
+    let mut cell = Cell::new(center, step);
+    // Close to the edges of the cell, its neighbours may not reach the edges of the box.
+    let north = cell.neighbour(0, 1).bounds();
+    let south = cell.neighbour(0, -1).bounds();
+    let east = cell.neighbour(1, 0).bounds();
+    let west = cell.neighbour(-1, 0).bounds();
+    if step > 1
+        && (north.latitude_max < bounds.latitude_max
+            || south.latitude_min > bounds.latitude_min
+            || east.longitude_max < bounds.longitude_max
+            || west.longitude_min > bounds.longitude_min)
+    {
+        step -= 1;
+        cell = Cell::new(center, step);
+    }
+
+    let area = cell.bounds();
+    let mut ranges = Vec::new();
+    let mut previous = None;
+    // The cell, then its north, south, east, west, north east, north west, south east and
+    // south west neighbours.
+    for (east, north) in [
+        (0, 0),
+        (0, 1),
+        (0, -1),
+        (1, 0),
+        (-1, 0),
+        (1, 1),
+        (-1, 1),
+        (1, -1),
+        (-1, -1),
+    ] {
+        let outside = (north < 0 && area.latitude_min < bounds.latitude_min)
+            || (north > 0 && area.latitude_max > bounds.latitude_max)
+            || (east < 0 && area.longitude_min < bounds.longitude_min)
+            || (east > 0 && area.longitude_max > bounds.longitude_max);
+        if step >= 2 && outside {
+            continue;
+        }
+
+        // With a huge radius, adjacent neighbours can be the same cell.
+        let neighbour = cell.neighbour(east, north);
+        if previous == Some(neighbour) {
+            continue;
+        }
+        previous = Some(neighbour);
+
+        ranges.push(neighbour.scores());
+    }
+
+    ranges
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Unit {
+    Meters,
+    Kilometers,
+    Feet,
+    Miles,
+}
+
+impl Unit {
+    pub(crate) fn parse(value: &BulkString) -> anyhow::Result<Self> {
+        match value.to_string().to_lowercase().as_str() {
+            "m" => Ok(Self::Meters),
+            "km" => Ok(Self::Kilometers),
+            "ft" => Ok(Self::Feet),
+            "mi" => Ok(Self::Miles),
+            _ => anyhow::bail!("ERR unsupported unit provided. please use M, KM, FT, MI"),
+        }
+    }
+
+    pub(crate) fn to_meters(self) -> f64 {
+        match self {
+            Self::Meters => 1.0,
+            Self::Kilometers => 1000.0,
+            Self::Feet => 0.3048,
+            Self::Miles => 1609.34,
+        }
+    }
+
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            Self::Meters => "m",
+            Self::Kilometers => "km",
+            Self::Feet => "ft",
+            Self::Miles => "mi",
+        }
+    }
+}
+
+pub(crate) fn parse_float(value: &BulkString) -> anyhow::Result<f64> {
+    value
+        .to_string()
+        .parse::<f64>()
+        .ok()
+        .filter(|value| !value.is_nan())
+        .context("ERR value is not a valid float")
+}
+
+/// Formats a distance with the fixed precision Redis uses for replies.
+pub(crate) fn format_distance(meters: f64, unit: Unit) -> String {
+    format!("{:.4}", meters / unit.to_meters())
+}
+
+/// Formats a coordinate like Redis' human readable long double replies.
+pub(crate) fn format_coordinate(value: f64) -> String {
+    let formatted = format!("{:.17}", value);
+    let formatted = formatted.trim_end_matches('0').trim_end_matches('.');
+
+    if formatted == "-0" {
+        String::from("0")
+    } else {
+        formatted.to_string()
+    }
+}
+
+fn latitude_distance(latitude_1: f64, latitude_2: f64) -> f64 {
+    EARTH_RADIUS_IN_METERS * (latitude_2.to_radians() - latitude_1.to_radians()).abs()
+}
+
+/// Returns the number of bits per coordinate of the cells at least as large as `radius` meters.
+fn estimate_step(mut radius: f64, latitude: f64) -> u32 {
+    if radius == 0.0 {
+        return STEP_MAX;
+    }
+
+    let mut step: i32 = 1;
+    while radius < MERCATOR_MAX {
+        radius *= 2.0;
+        step += 1;
+    }
+    // Cells are narrower towards the poles.
+    step -= 2;
+    if latitude.abs() > 66.0 {
+        step -= 1;
+        if latitude.abs() > 80.0 {
+            step -= 1;
+        }
+    }
+
+    step.clamp(1, STEP_MAX as i32) as u32
+}
+
+fn encode(coordinates: Coordinates, latitude_min: f64, latitude_max: f64, step: u32) -> u64 {
+    let cells = (1_u64 << step) as f64;
+
+    let latitude_offset = (coordinates.latitude - latitude_min) / (latitude_max - latitude_min);
+    let longitude_offset =
+        (coordinates.longitude - LONGITUDE_MIN) / (LONGITUDE_MAX - LONGITUDE_MIN);
+
+    let latitude_bits = (latitude_offset * cells) as u32;
+    let longitude_bits = (longitude_offset * cells) as u32;
+
+    spread(latitude_bits) | (spread(longitude_bits) << 1)
+}
+
+/// Moves the coordinate of the interleaved bits selected by `mask` by one cell in `direction`.
+fn move_coordinate(bits: u64, step: u32, direction: i8, mask: u64) -> u64 {
+    if direction == 0 {
+        return bits;
+    }
+
+    let shift = 64 - step * 2;
+    let other = bits & !mask;
+    // Setting the bits of the other coordinate carries the increment across them.
+    let gaps = !mask >> shift;
+    let coordinate = if direction > 0 {
+        (bits & mask).wrapping_add(gaps + 1)
+    } else {
+        ((bits & mask) | gaps).wrapping_sub(gaps + 1)
+    };
+
+    (coordinate & (mask >> shift)) | other
+}
+
+/// Spreads the bits of `value` so they occupy the even positions of the result.
+fn spread(value: u32) -> u64 {
+    let mut x = value as u64;
+    x = (x | (x << 16)) & 0x0000_FFFF_0000_FFFF;
+    x = (x | (x << 8)) & 0x00FF_00FF_00FF_00FF;
+    x = (x | (x << 4)) & 0x0F0F_0F0F_0F0F_0F0F;
+    x = (x | (x << 2)) & 0x3333_3333_3333_3333;
+    (x | (x << 1)) & 0x5555_5555_5555_5555
+}
+
+/// Reverses `spread`, collecting the even bits of `value`.
+fn squash(value: u64) -> u64 {
+    let mut x = value & 0x5555_5555_5555_5555;
+    x = (x | (x >> 1)) & 0x3333_3333_3333_3333;
+    x = (x | (x >> 2)) & 0x0F0F_0F0F_0F0F_0F0F;
+    x = (x | (x >> 4)) & 0x00FF_00FF_00FF_00FF;
+    x = (x | (x >> 8)) & 0x0000_FFFF_0000_FFFF;
+    (x | (x >> 16)) & 0x0000_0000_FFFF_FFFF
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Coordinates and replies from the examples of the Redis geospatial commands.
+    const PALERMO: (f64, f64) = (13.361389, 38.115556);
+    const CATANIA: (f64, f64) = (15.087269, 37.502669);
+
+    fn coordinates((longitude, latitude): (f64, f64)) -> Coordinates {
+        Coordinates::new(longitude, latitude).unwrap()
+    }
+
+    #[test]
+    fn score() {
+        assert_eq!(coordinates(PALERMO).to_score(), 3_479_099_956_230_698.0);
+        assert_eq!(coordinates(CATANIA).to_score(), 3_479_447_370_796_909.0);
+    }
+
+    #[test]
+    fn decodes_score() {
+        let palermo = Coordinates::from_score(3_479_099_956_230_698.0);
+        assert_eq!(format_coordinate(palermo.longitude), "13.36138933897018433");
+        assert_eq!(format_coordinate(palermo.latitude), "38.11555639549629859");
+    }
+
+    #[test]
+    fn geohash() {
+        assert_eq!(coordinates(PALERMO).geohash(), "sqc8b49rny0");
+        assert_eq!(coordinates(CATANIA).geohash(), "sqdtr74hyu0");
+    }
+
+    #[test]
+    fn distance() {
+        let palermo = Coordinates::from_score(coordinates(PALERMO).to_score());
+        let catania = Coordinates::from_score(coordinates(CATANIA).to_score());
+        assert_eq!(
+            format_distance(palermo.distance(catania), Unit::Meters),
+            "166274.1516"
+        );
+        assert_eq!(
+            format_distance(palermo.distance(catania), Unit::Kilometers),
+            "166.2742"
+        );
+        assert_eq!(
+            format_distance(palermo.distance(catania), Unit::Miles),
+            "103.3182"
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_coordinates() {
+        assert!(Coordinates::new(180.1, 0.0).is_err());
+        assert!(Coordinates::new(0.0, 85.06).is_err());
+        assert!(Coordinates::new(-180.0, -85.05112878).is_ok());
+    }
+
+    #[test]
+    fn search_ranges_cover_the_radius() {
+        for (center, radius) in [
+            (PALERMO, 200_000.0),
+            (CATANIA, 1_000.0),
+            ((0.0, 0.0), 500_000.0),
+            ((179.99, 10.0), 50_000.0),
+            ((-120.0, 70.0), 300_000.0),
+            ((30.0, -84.0), 100_000.0),
+            ((2.35, 48.85), 5_000_000.0),
+            ((2.35, 48.85), 0.0),
+        ] {
+            let center = coordinates(center);
+            let ranges = search_ranges(center, radius, 2.0 * radius, 2.0 * radius);
+            assert!(ranges.len() <= 9);
+
+            // The maximum longitude and latitude are the edges of the last cells, out of them.
+            for i in 0..200 {
+                for j in 0..200 {
+                    let point = Coordinates::from_score(
+                        coordinates((
+                            -180.0 + 1.8 * i as f64,
+                            LATITUDE_MIN + (LATITUDE_MAX - LATITUDE_MIN) / 200.0 * j as f64,
+                        ))
+                        .to_score(),
+                    );
+                    if center.distance(point) > radius {
+                        continue;
+                    }
+
+                    let score = point.to_score();
+                    assert!(
+                        ranges.iter().any(|range| range.contains(&score)),
+                        "{:?} within {} of {:?} is not searched",
+                        point,
+                        radius,
+                        center
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn neighbours() {
+        let cell = Cell::new(coordinates(PALERMO), 10);
+        for (east, north) in [(1, 0), (-1, 0), (0, 1), (0, -1)] {
+            let neighbour = cell.neighbour(east, north);
+            assert_eq!(neighbour.neighbour(-east, -north), cell);
+
+            let (bounds, neighbour) = (cell.bounds(), neighbour.bounds());
+            match (east, north) {
+                (1, _) => assert_eq!(neighbour.longitude_min, bounds.longitude_max),
+                (-1, _) => assert_eq!(neighbour.longitude_max, bounds.longitude_min),
+                (_, 1) => assert_eq!(neighbour.latitude_min, bounds.latitude_max),
+                _ => assert_eq!(neighbour.latitude_max, bounds.latitude_min),
+            }
+        }
+
+        // Cells wrap around the antimeridian.
+        let cell = Cell::new(coordinates((179.99, 0.0)), 10);
+        assert_eq!(cell.neighbour(1, 0).bounds().longitude_min, LONGITUDE_MIN);
+    }
+
+    #[test]
+    fn spread_and_squash() {
+        assert_eq!(spread(0b1011), 0b1000101);
+        assert_eq!(squash(spread(0x3ff_ffff)), 0x3ff_ffff);
+        assert_eq!(squash(0b10 << 1), 0b10);
+    }
+}
@@ -1,5 +1,7 @@
-use std::io::Cursor;
+use std::{io::Cursor, time::Duration};
 
+use anyhow::Context;
+use bytes::{Buf, BytesMut};
 use tokio::{
     io::{AsyncReadExt, BufReader, BufWriter, ReadHalf, WriteHalf},
     net::TcpStream,
@@ -7,13 +9,15 @@ use tokio::{
 
 use crate::{
     commands::{ping, psync, replconf, Command},
-    message::{Message, SimpleString},
+    db::Db,
+    message::{self, Message, SimpleString},
 };
 
 pub(crate) struct Handshake<'a> {
     writer: &'a mut BufWriter<WriteHalf<TcpStream>>,
     reader: &'a mut BufReader<ReadHalf<TcpStream>>,
-    buf: [u8; 1024],
+    db: &'a Db,
+    buf: BytesMut,
     port: u16,
 }
 
@@ -21,17 +25,21 @@ impl<'a> Handshake<'a> {
     pub(crate) fn new(
         writer: &'a mut BufWriter<WriteHalf<TcpStream>>,
         reader: &'a mut BufReader<ReadHalf<TcpStream>>,
+        db: &'a Db,
         port: u16,
     ) -> Self {
         Self {
             writer,
             reader,
-            buf: [0; 1024],
+            db,
+            buf: BytesMut::with_capacity(4096),
             port,
         }
     }
 
-    pub(crate) async fn send_handshake(&mut self) -> anyhow::Result<()> {
+    /// Synchronizes with the master, returning the bytes read after the snapshot, which are
+    /// already part of the replication stream.
+    pub(crate) async fn send_handshake(mut self) -> anyhow::Result<BytesMut> {
         let ok_message = Message::ok_message();
 
         let command = ping::PingCommand::new_command(None);
@@ -58,33 +66,110 @@ impl<'a> Handshake<'a> {
             anyhow::bail!("Response is different than OK: {}", response);
         }
 
-        let command = psync::PSyncCommand::new_command(String::from("?"), -1);
+        // Continuing the stream of the master when this replica has its history.
+        let (replication_id, offset) = {
+            let replication = self.db.replication();
+            if replication.has_backlog() {
+                (replication.id.clone(), replication.offset as isize + 1)
+            } else {
+                (String::from("?"), -1)
+            }
+        };
+        let command = psync::PSyncCommand::new_command(replication_id, offset);
         let response: SimpleString = self.send_command(command).await?.try_into()?;
         println!("PSYNC replied {}", response);
-        if response.to_string().starts_with("FULLRESYNC") {
-            println!("Full resync with master");
-        }
+        let response = response.to_string();
+
+        match response.split(' ').collect::<Vec<_>>()[..] {
+            // Masters keeping the same id may not send it.
+            ["CONTINUE"] => {
+                println!(
+                    "Partial resynchronization from offset {}",
+                    self.db.replication_offset()
+                );
+            }
+            ["CONTINUE", id] => {
+                let mut replication = self.db.replication();
+                if replication.id != id {
+                    replication.shift_id(id.to_string());
+                    replication.disconnect_replicas();
+                }
+                println!(
+                    "Partial resynchronization from offset {}",
+                    replication.offset
+                );
+            }
+            ["FULLRESYNC", id, offset] => {
+                let offset: usize = offset
+                    .parse()
+                    .with_context(|| format!("Unexpected PSYNC reply: {}", response))?;
 
-        // consume RDB file
-        let read = self.reader.read(&mut self.buf).await?;
-        if read == 0 {
-            println!("RDB file not sent");
+                let rdb = self.read_rdb().await?;
+                println!("Received RDB file of {} bytes", rdb.len());
+                // The stream following the snapshot starts at the offset of the master.
+                self.db.load_full_resync(&rdb, id.to_string(), offset)?;
+            }
+            _ => anyhow::bail!("Unexpected PSYNC reply: {}", response),
         }
 
-        Ok(())
+        Ok(self.buf)
     }
 
     async fn send_command(&mut self, command: impl Command) -> anyhow::Result<Message> {
         let message = command.to_message();
         message.send(self.writer).await?;
 
-        let read = self.reader.read(&mut self.buf).await?;
-        if read == 0 {
-            anyhow::bail!("Failed to read response");
+        let mut scanner = message::FrameScanner::default();
+        let frame_len = loop {
+            if let Some(frame_len) = scanner.frame_len(&self.buf)? {
+                break frame_len;
+            }
+            self.fill_buf().await?;
+        };
+
+        let frame = self.buf.split_to(frame_len);
+        Message::deserialize(&mut Cursor::new(&frame))
+    }
+
+    /// Reads the snapshot sent as `$<len>\r\n<payload>`, which unlike a bulk string has no
+    /// trailing CRLF.
+    async fn read_rdb(&mut self) -> anyhow::Result<Vec<u8>> {
+        let header_len = loop {
+            // Masters send newlines to keep the link alive while they prepare the snapshot.
+            let newlines = self.buf.iter().take_while(|&&byte| byte == b'\n').count();
+            self.buf.advance(newlines);
+
+            if let Some(position) = self.buf.windows(2).position(|window| window == b"\r\n") {
+                break position;
+            }
+            self.fill_buf().await?;
+        };
+
+        anyhow::ensure!(
+            self.buf.first() == Some(&b'$'),
+            "Expected RDB file, got {:?}",
+            String::from_utf8_lossy(&self.buf[..header_len])
+        );
+        let len: usize = std::str::from_utf8(&self.buf[1..header_len])
+            .ok()
+            .and_then(|len| len.parse().ok())
+            .context("Invalid RDB file length")?;
+        self.buf.advance(header_len + 2);
+
+        while self.buf.len() < len {
+            self.fill_buf().await?;
         }
 
-        let message = Message::deserialize(&mut Cursor::new(&self.buf))?;
-        self.buf.fill(0);
-        Ok(message)
+        Ok(self.buf.split_to(len).to_vec())
+    }
+
+    async fn fill_buf(&mut self) -> anyhow::Result<()> {
+        let timeout = Duration::from_secs(self.db.config().repl_timeout);
+        let read = tokio::time::timeout(timeout, self.reader.read_buf(&mut self.buf))
+            .await
+            .context("Timeout receiving from master")??;
+        anyhow::ensure!(read != 0, "Master closed the connection");
+
+        Ok(())
     }
 }
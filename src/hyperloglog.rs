@@ -0,0 +1,649 @@
+// HyperLogLog implementation compatible with the Redis string representation.
+// https://github.com/redis/redis/blob/unstable/src/hyperloglog.c
+
+use anyhow::Context;
+
+const HLL_P: usize = 14;
+const HLL_Q: usize = 64 - HLL_P;
+pub(crate) const HLL_REGISTERS: usize = 1 << HLL_P;
+const HLL_P_MASK: u64 = (HLL_REGISTERS - 1) as u64;
+const HLL_BITS: usize = 6;
+const HLL_REGISTER_MAX: u8 = (1 << HLL_BITS) - 1;
+const HLL_HDR_SIZE: usize = 16;
+const HLL_DENSE_SIZE: usize = HLL_HDR_SIZE + (HLL_REGISTERS * HLL_BITS).div_ceil(8);
+const HLL_DENSE: u8 = 0;
+const HLL_SPARSE: u8 = 1;
+const HLL_MAGIC: &[u8; 4] = b"HYLL";
+const HLL_ALPHA_INF: f64 = 0.721_347_520_444_481_7;
+const HLL_HASH_SEED: u64 = 0xadc8_3b19;
+
+// Sparse representation opcodes.
+const HLL_SPARSE_XZERO_BIT: u8 = 0x40;
+const HLL_SPARSE_VAL_BIT: u8 = 0x80;
+const HLL_SPARSE_VAL_MAX_VALUE: u8 = 32;
+const HLL_SPARSE_VAL_MAX_LEN: usize = 4;
+const HLL_SPARSE_ZERO_MAX_LEN: usize = 64;
+const HLL_SPARSE_XZERO_MAX_LEN: usize = 16384;
+
+// Sparse HLLs bigger than this are promoted to the dense representation.
+const HLL_SPARSE_MAX_BYTES: usize = 3000;
+
+const CARD_OFFSET: usize = 8;
+const CARD_INVALID_BIT: u8 = 1 << 7;
+
+pub(crate) const INVALID_HLL_ERROR: &str = "WRONGTYPE Key is not a valid HyperLogLog string value.";
+pub(crate) const CORRUPTED_HLL_ERROR: &str = "INVALIDOBJ Corrupted HLL object detected";
+
+pub(crate) type Registers = [u8; HLL_REGISTERS];
+
+#[derive(Debug, Clone)]
+pub(crate) struct HyperLogLog {
+    bytes: Vec<u8>,
+}
+
+impl HyperLogLog {
+    pub(crate) fn new() -> Self {
+        let mut bytes = header(HLL_SPARSE);
+        push_zero_run(&mut bytes, HLL_REGISTERS);
+
+        Self { bytes }
+    }
+
+    pub(crate) fn from_bytes(bytes: Vec<u8>) -> anyhow::Result<Self> {
+        anyhow::ensure!(bytes.len() >= HLL_HDR_SIZE, INVALID_HLL_ERROR);
+        anyhow::ensure!(&bytes[..4] == HLL_MAGIC, INVALID_HLL_ERROR);
+
+        match bytes[4] {
+            HLL_DENSE => anyhow::ensure!(bytes.len() == HLL_DENSE_SIZE, INVALID_HLL_ERROR),
+            HLL_SPARSE => {}
+            _ => anyhow::bail!(INVALID_HLL_ERROR),
+        }
+
+        Ok(Self { bytes })
+    }
+
+    pub(crate) fn from_registers(registers: &Registers, allow_sparse: bool) -> Self {
+        if allow_sparse {
+            if let Some(bytes) = encode_sparse(registers) {
+                return Self { bytes };
+            }
+        }
+
+        let mut hll = Self {
+            bytes: header(HLL_DENSE),
+        };
+        hll.bytes.resize(HLL_DENSE_SIZE, 0);
+        for (index, value) in registers.iter().enumerate() {
+            dense_set(&mut hll.bytes[HLL_HDR_SIZE..], index, *value);
+        }
+        hll.invalidate_cache();
+
+        hll
+    }
+
+    pub(crate) fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+
+    pub(crate) fn is_dense(&self) -> bool {
+        self.bytes[4] == HLL_DENSE
+    }
+
+    /// Adds an element, returning whether any register was altered.
+    pub(crate) fn add(&mut self, element: &[u8]) -> anyhow::Result<bool> {
+        let (index, count) = pattern_len(element);
+
+        let updated = if self.is_dense() {
+            self.dense_add(index, count)
+        } else {
+            match self.sparse_add(index, count)? {
+                Some(updated) => updated,
+                None => {
+                    *self = Self::from_registers(&self.registers()?, false);
+                    self.dense_add(index, count)
+                }
+            }
+        };
+
+        if updated {
+            self.invalidate_cache();
+        }
+
+        Ok(updated)
+    }
+
+    /// Returns the estimated cardinality, using and refreshing the cached value in the header.
+    pub(crate) fn count(&mut self) -> anyhow::Result<u64> {
+        let card = &self.bytes[CARD_OFFSET..HLL_HDR_SIZE];
+        if card[7] & CARD_INVALID_BIT == 0 {
+            let mut cached = [0; 8];
+            cached.copy_from_slice(card);
+            return Ok(u64::from_le_bytes(cached));
+        }
+
+        let count = count_registers(&self.registers()?);
+        self.bytes[CARD_OFFSET..HLL_HDR_SIZE].copy_from_slice(&count.to_le_bytes());
+
+        Ok(count)
+    }
+
+    pub(crate) fn invalidate_cache(&mut self) {
+        self.bytes[CARD_OFFSET + 7] |= CARD_INVALID_BIT;
+    }
+
+    pub(crate) fn registers(&self) -> anyhow::Result<Registers> {
+        let mut registers = [0; HLL_REGISTERS];
+        self.merge_into(&mut registers)?;
+
+        Ok(registers)
+    }
+
+    fn dense_add(&mut self, index: usize, count: u8) -> bool {
+        let registers = &mut self.bytes[HLL_HDR_SIZE..];
+        if dense_get(registers, index) < count {
+            dense_set(registers, index, count);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Raises a register of the sparse representation by splitting the opcode covering it in
+    /// place, returning `None` when the result needs the dense representation.
+    fn sparse_add(&mut self, index: usize, count: u8) -> anyhow::Result<Option<bool>> {
+        if count > HLL_SPARSE_VAL_MAX_VALUE {
+            return Ok(None);
+        }
+
+        let mut position = HLL_HDR_SIZE;
+        let mut previous = None;
+        let mut first = 0;
+        let opcode = loop {
+            let opcode = Opcode::decode(&self.bytes[position..]).context(CORRUPTED_HLL_ERROR)?;
+            if index < first + opcode.len() {
+                break opcode;
+            }
+            previous = Some(position);
+            position += opcode.size();
+            first += opcode.len();
+        };
+
+        let before = index - first;
+        let after = opcode.len() - before - 1;
+        let replacement = match opcode {
+            Opcode::Val(value, _) if value >= count => return Ok(Some(false)),
+            Opcode::Zero(_) | Opcode::XZero(_) => [
+                (before > 0).then(|| Opcode::zeros(before)),
+                Some(Opcode::Val(count, 1)),
+                (after > 0).then(|| Opcode::zeros(after)),
+            ],
+            Opcode::Val(value, _) => [
+                (before > 0).then_some(Opcode::Val(value, before)),
+                Some(Opcode::Val(count, 1)),
+                (after > 0).then_some(Opcode::Val(value, after)),
+            ],
+        };
+
+        let mut sequence = Vec::with_capacity(5);
+        for opcode in replacement.into_iter().flatten() {
+            opcode.encode(&mut sequence);
+        }
+        if sequence.len() > opcode.size()
+            && self.bytes.len() + sequence.len() - opcode.size() > HLL_SPARSE_MAX_BYTES
+        {
+            return Ok(None);
+        }
+        self.bytes
+            .splice(position..position + opcode.size(), sequence);
+
+        self.merge_values(previous.unwrap_or(HLL_HDR_SIZE));
+
+        Ok(Some(true))
+    }
+
+    /// Merges the adjacent `VAL` opcodes of a same value among the 5 opcodes from `position`,
+    /// which a split may have left.
+    fn merge_values(&mut self, mut position: usize) {
+        for _ in 0..5 {
+            let Some(opcode) = Opcode::decode(&self.bytes[position..]) else {
+                return;
+            };
+
+            if let (Opcode::Val(value, len), Some(Opcode::Val(next_value, next_len))) =
+                (opcode, Opcode::decode(&self.bytes[position + 1..]))
+            {
+                if value == next_value && len + next_len <= HLL_SPARSE_VAL_MAX_LEN {
+                    let mut merged = Vec::with_capacity(1);
+                    Opcode::Val(value, len + next_len).encode(&mut merged);
+                    self.bytes.splice(position..position + 2, merged);
+                    // The merged opcode may be merged with the next one.
+                    continue;
+                }
+            }
+
+            position += opcode.size();
+        }
+    }
+
+    /// Merges the registers into `max`, keeping the maximum value of each register.
+    pub(crate) fn merge_into(&self, max: &mut Registers) -> anyhow::Result<()> {
+        let data = &self.bytes[HLL_HDR_SIZE..];
+
+        if self.is_dense() {
+            for (index, register) in max.iter_mut().enumerate() {
+                *register = (*register).max(dense_get(data, index));
+            }
+            return Ok(());
+        }
+
+        let mut index = 0;
+        let mut position = 0;
+        while position < data.len() {
+            let opcode = Opcode::decode(&data[position..]).context(CORRUPTED_HLL_ERROR)?;
+            anyhow::ensure!(index + opcode.len() <= HLL_REGISTERS, CORRUPTED_HLL_ERROR);
+            if let Opcode::Val(value, len) = opcode {
+                for register in &mut max[index..index + len] {
+                    *register = (*register).max(value);
+                }
+            }
+            index += opcode.len();
+            position += opcode.size();
+        }
+        anyhow::ensure!(index == HLL_REGISTERS, CORRUPTED_HLL_ERROR);
+
+        Ok(())
+    }
+}
+
+/// An opcode of the sparse representation, with the number of registers it covers.
+#[derive(Debug, Clone, Copy)]
+enum Opcode {
+    Zero(usize),
+    XZero(usize),
+    /// A value, then the number of registers set to it.
+    Val(u8, usize),
+}
+
+impl Opcode {
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        let opcode = *bytes.first()?;
+
+        let opcode = if opcode & HLL_SPARSE_VAL_BIT != 0 {
+            Self::Val(((opcode >> 2) & 0x1f) + 1, (opcode & 0x3) as usize + 1)
+        } else if opcode & HLL_SPARSE_XZERO_BIT != 0 {
+            Self::XZero(((((opcode & 0x3f) as usize) << 8) | *bytes.get(1)? as usize) + 1)
+        } else {
+            Self::Zero((opcode & 0x3f) as usize + 1)
+        };
+
+        Some(opcode)
+    }
+
+    /// The shortest opcode for a run of `len` zeroes, at most an `XZERO` long.
+    fn zeros(len: usize) -> Self {
+        if len > HLL_SPARSE_ZERO_MAX_LEN {
+            Self::XZero(len)
+        } else {
+            Self::Zero(len)
+        }
+    }
+
+    fn encode(self, bytes: &mut Vec<u8>) {
+        match self {
+            Self::Zero(len) => bytes.push((len - 1) as u8),
+            Self::XZero(len) => {
+                let encoded = len - 1;
+                bytes.push(HLL_SPARSE_XZERO_BIT | (encoded >> 8) as u8);
+                bytes.push((encoded & 0xff) as u8);
+            }
+            Self::Val(value, len) => {
+                bytes.push(HLL_SPARSE_VAL_BIT | ((value - 1) << 2) | (len - 1) as u8)
+            }
+        }
+    }
+
+    fn len(self) -> usize {
+        match self {
+            Self::Zero(len) | Self::XZero(len) | Self::Val(_, len) => len,
+        }
+    }
+
+    /// The number of bytes of the opcode.
+    fn size(self) -> usize {
+        match self {
+            Self::XZero(_) => 2,
+            Self::Zero(_) | Self::Val(..) => 1,
+        }
+    }
+}
+
+pub(crate) fn count_registers(registers: &Registers) -> u64 {
+    let m = HLL_REGISTERS as f64;
+
+    let mut histogram = [0_u32; HLL_Q + 2];
+    for register in registers {
+        histogram[*register as usize] += 1;
+    }
+
+    let mut z = m * tau((m - histogram[HLL_Q + 1] as f64) / m);
+    for j in (1..=HLL_Q).rev() {
+        z += histogram[j] as f64;
+        z *= 0.5;
+    }
+    z += m * sigma(histogram[0] as f64 / m);
+
+    (HLL_ALPHA_INF * m * m / z).round() as u64
+}
+
+fn header(encoding: u8) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(HLL_HDR_SIZE);
+    bytes.extend_from_slice(HLL_MAGIC);
+    bytes.push(encoding);
+    bytes.resize(HLL_HDR_SIZE, 0);
+
+    bytes
+}
+
+fn sigma(mut x: f64) -> f64 {
+    if x == 1.0 {
+        return f64::INFINITY;
+    }
+
+    let mut y = 1.0;
+    let mut z = x;
+    loop {
+        x *= x;
+        let previous = z;
+        z += x * y;
+        y += y;
+        if previous == z {
+            return z;
+        }
+    }
+}
+
+fn tau(mut x: f64) -> f64 {
+    if x == 0.0 || x == 1.0 {
+        return 0.0;
+    }
+
+    let mut y = 1.0;
+    let mut z = 1.0 - x;
+    loop {
+        x = x.sqrt();
+        let previous = z;
+        y *= 0.5;
+        z -= (1.0 - x).powi(2) * y;
+        if previous == z {
+            return z / 3.0;
+        }
+    }
+}
+
+/// Returns the register index and the length of the run of zeroes plus one.
+fn pattern_len(element: &[u8]) -> (usize, u8) {
+    let mut hash = murmur_hash64a(element, HLL_HASH_SEED);
+    let index = (hash & HLL_P_MASK) as usize;
+    hash >>= HLL_P;
+    hash |= 1 << HLL_Q;
+
+    (index, hash.trailing_zeros() as u8 + 1)
+}
+
+fn murmur_hash64a(key: &[u8], seed: u64) -> u64 {
+    const M: u64 = 0xc6a4_a793_5bd1_e995;
+    const R: u32 = 47;
+
+    let mut h = seed ^ (key.len() as u64).wrapping_mul(M);
+
+    let chunks = key.chunks_exact(8);
+    let tail = chunks.remainder();
+    for chunk in chunks {
+        let mut k = u64::from_le_bytes(chunk.try_into().expect("chunk should have 8 bytes"));
+        k = k.wrapping_mul(M);
+        k ^= k >> R;
+        k = k.wrapping_mul(M);
+        h ^= k;
+        h = h.wrapping_mul(M);
+    }
+
+    if !tail.is_empty() {
+        for (i, byte) in tail.iter().enumerate() {
+            h ^= (*byte as u64) << (8 * i);
+        }
+        h = h.wrapping_mul(M);
+    }
+
+    h ^= h >> R;
+    h = h.wrapping_mul(M);
+    h ^= h >> R;
+
+    h
+}
+
+fn dense_get(registers: &[u8], index: usize) -> u8 {
+    let byte = index * HLL_BITS / 8;
+    let fb = (index * HLL_BITS) & 7;
+    let b0 = registers[byte] as u16;
+    let b1 = registers.get(byte + 1).copied().unwrap_or(0) as u16;
+
+    (((b0 >> fb) | (b1 << (8 - fb))) as u8) & HLL_REGISTER_MAX
+}
+
+fn dense_set(registers: &mut [u8], index: usize, value: u8) {
+    let byte = index * HLL_BITS / 8;
+    let fb = (index * HLL_BITS) & 7;
+    let value = value as u16;
+    let mask = HLL_REGISTER_MAX as u16;
+
+    registers[byte] &= !((mask << fb) as u8);
+    registers[byte] |= (value << fb) as u8;
+    if let Some(next) = registers.get_mut(byte + 1) {
+        *next &= !((mask >> (8 - fb)) as u8);
+        *next |= (value >> (8 - fb)) as u8;
+    }
+}
+
+fn push_zero_run(bytes: &mut Vec<u8>, mut len: usize) {
+    while len > 0 {
+        let run = len.min(HLL_SPARSE_XZERO_MAX_LEN);
+        Opcode::zeros(run).encode(bytes);
+        len -= run;
+    }
+}
+
+/// Encodes the registers using the sparse representation, returning `None` when the
+/// registers can not be represented or the result would exceed the sparse size limit.
+fn encode_sparse(registers: &Registers) -> Option<Vec<u8>> {
+    let mut bytes = header(HLL_SPARSE);
+
+    let mut index = 0;
+    while index < HLL_REGISTERS {
+        let value = registers[index];
+        let run = registers[index..]
+            .iter()
+            .take_while(|register| **register == value)
+            .count();
+
+        if value == 0 {
+            push_zero_run(&mut bytes, run);
+        } else {
+            if value > HLL_SPARSE_VAL_MAX_VALUE {
+                return None;
+            }
+
+            let mut len = run;
+            while len > 0 {
+                let chunk = len.min(HLL_SPARSE_VAL_MAX_LEN);
+                Opcode::Val(value, chunk).encode(&mut bytes);
+                len -= chunk;
+            }
+        }
+
+        if bytes.len() > HLL_SPARSE_MAX_BYTES {
+            return None;
+        }
+
+        index += run;
+    }
+
+    bytes[CARD_OFFSET + 7] |= CARD_INVALID_BIT;
+
+    Some(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hll(elements: &[&str]) -> HyperLogLog {
+        let mut hll = HyperLogLog::new();
+        for element in elements {
+            hll.add(element.as_bytes()).unwrap();
+        }
+
+        hll
+    }
+
+    #[test]
+    fn new_is_a_single_sparse_zero_run() {
+        let bytes = HyperLogLog::new().into_bytes();
+        assert_eq!(&bytes[..5], b"HYLL\x01");
+        assert_eq!(&bytes[HLL_HDR_SIZE..], [0x7f, 0xff]);
+    }
+
+    #[test]
+    fn sparse_encoding() {
+        let mut registers = [0; HLL_REGISTERS];
+        registers[0] = 1;
+        registers[1..6].fill(3);
+        registers[HLL_REGISTERS - 1] = 32;
+
+        let bytes = HyperLogLog::from_registers(&registers, true).into_bytes();
+        assert_eq!(bytes[4], HLL_SPARSE);
+        // VAL(1, 1), VAL(3, 4), VAL(3, 1), XZERO(16377), VAL(32, 1).
+        assert_eq!(&bytes[HLL_HDR_SIZE..], [0x80, 0x8b, 0x88, 0x7f, 0xf8, 0xfc]);
+
+        let hll = HyperLogLog::from_bytes(bytes).unwrap();
+        assert_eq!(hll.registers().unwrap(), registers);
+    }
+
+    #[test]
+    fn dense_encoding() {
+        let mut registers = [0; HLL_REGISTERS];
+        for (index, register) in registers.iter_mut().enumerate() {
+            *register = (index % 64) as u8;
+        }
+
+        // A register over the sparse maximum value can only be represented as dense.
+        let hll = HyperLogLog::from_registers(&registers, true);
+        assert!(hll.is_dense());
+        assert_eq!(hll.into_bytes().len(), HLL_DENSE_SIZE);
+
+        let hll = HyperLogLog::from_registers(&registers, false);
+        assert_eq!(hll.registers().unwrap(), registers);
+    }
+
+    #[test]
+    fn rejects_invalid_values() {
+        assert!(HyperLogLog::from_bytes(b"HYLL".to_vec()).is_err());
+        assert!(HyperLogLog::from_bytes(header(HLL_DENSE)).is_err());
+
+        let mut bytes = header(HLL_SPARSE);
+        bytes.push(0x7f);
+        let hll = HyperLogLog::from_bytes(bytes).unwrap();
+        assert!(hll.registers().is_err());
+    }
+
+    #[test]
+    fn sparse_add_splits_opcodes_in_place() {
+        let mut hll = HyperLogLog::new();
+        assert_eq!(hll.sparse_add(5, 3).unwrap(), Some(true));
+        // ZERO(5), VAL(3, 1), XZERO(16378).
+        assert_eq!(&hll.bytes[HLL_HDR_SIZE..], [0x04, 0x88, 0x7f, 0xf9]);
+
+        // The new value is merged with the adjacent one.
+        assert_eq!(hll.sparse_add(6, 3).unwrap(), Some(true));
+        assert_eq!(&hll.bytes[HLL_HDR_SIZE..], [0x04, 0x89, 0x7f, 0xf8]);
+
+        assert_eq!(hll.sparse_add(5, 2).unwrap(), Some(false));
+        assert_eq!(hll.sparse_add(6, 4).unwrap(), Some(true));
+        // ZERO(5), VAL(3, 1), VAL(4, 1), XZERO(16377).
+        assert_eq!(&hll.bytes[HLL_HDR_SIZE..], [0x04, 0x88, 0x8c, 0x7f, 0xf8]);
+
+        // Values over the sparse maximum need the dense representation.
+        assert_eq!(hll.sparse_add(0, 33).unwrap(), None);
+
+        let mut registers = [0; HLL_REGISTERS];
+        registers[5] = 3;
+        registers[6] = 4;
+        assert_eq!(hll.registers().unwrap(), registers);
+    }
+
+    #[test]
+    fn add_reports_updates() {
+        let mut hll = hll(&["foo", "bar", "zap"]);
+        assert!(!hll.add(b"zap").unwrap());
+        assert!(!hll.add(b"foo").unwrap());
+        assert!(hll.add(b"baz").unwrap());
+    }
+
+    // The counts below are the ones Redis replies in its PFADD, PFCOUNT and PFMERGE examples.
+    #[test]
+    fn count() {
+        assert_eq!(HyperLogLog::new().count().unwrap(), 0);
+        assert_eq!(
+            hll(&["a", "b", "c", "d", "e", "f", "g"]).count().unwrap(),
+            7
+        );
+        assert_eq!(hll(&["foo", "bar", "zap"]).count().unwrap(), 3);
+    }
+
+    #[test]
+    fn count_union() {
+        let mut registers = [0; HLL_REGISTERS];
+        hll(&["foo", "bar", "zap"])
+            .merge_into(&mut registers)
+            .unwrap();
+        hll(&["1", "2", "3"]).merge_into(&mut registers).unwrap();
+        assert_eq!(count_registers(&registers), 6);
+
+        let mut registers = [0; HLL_REGISTERS];
+        hll(&["foo", "bar", "zap", "a"])
+            .merge_into(&mut registers)
+            .unwrap();
+        hll(&["a", "b", "c", "foo"])
+            .merge_into(&mut registers)
+            .unwrap();
+        assert_eq!(count_registers(&registers), 6);
+    }
+
+    #[test]
+    fn count_is_cached() {
+        let mut hll = hll(&["foo", "bar", "zap"]);
+        assert_eq!(hll.count().unwrap(), 3);
+        assert_eq!(hll.bytes[CARD_OFFSET..HLL_HDR_SIZE], 3_u64.to_le_bytes());
+
+        hll.add(b"baz").unwrap();
+        assert_ne!(hll.bytes[CARD_OFFSET + 7] & CARD_INVALID_BIT, 0);
+        assert_eq!(hll.count().unwrap(), 4);
+    }
+
+    #[test]
+    fn promotes_to_dense() {
+        let mut hll = HyperLogLog::new();
+        let mut registers = [0; HLL_REGISTERS];
+        for i in 0..20_000 {
+            let element = i.to_string();
+            hll.add(element.as_bytes()).unwrap();
+
+            let (index, count) = pattern_len(element.as_bytes());
+            registers[index] = registers[index].max(count);
+        }
+
+        assert!(hll.is_dense());
+        assert_eq!(hll.registers().unwrap(), registers);
+        let count = hll.count().unwrap();
+        assert!(count.abs_diff(20_000) < 400, "count {}", count);
+    }
+}
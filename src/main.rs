@@ -1,14 +1,27 @@
-use std::result::Result::Ok;
-
 use anyhow::Context;
+use bytes::BytesMut;
 use clap::Parser;
-use tokio::io::{AsyncReadExt, BufReader, BufWriter, ReadHalf, WriteHalf};
+use tokio::io::{BufReader, BufWriter, ReadHalf, WriteHalf};
 use tokio::net::{TcpListener, TcpStream};
 
+pub(crate) mod aof;
+pub(crate) mod client;
 pub(crate) mod commands;
+pub(crate) mod config;
+pub(crate) mod connection;
 pub(crate) mod db;
+pub(crate) mod geo;
 pub(crate) mod handshake;
+pub(crate) mod hyperloglog;
 pub(crate) mod message;
+pub(crate) mod notify;
+pub(crate) mod pubsub;
+pub(crate) mod rdb;
+pub(crate) mod replication;
+pub(crate) mod slot;
+pub(crate) mod sorted_set;
+pub(crate) mod tracking;
+pub(crate) mod transaction;
 
 #[derive(Parser, Debug)]
 #[command()]
@@ -18,6 +31,23 @@ struct Args {
     port: u16,
     #[arg(long)]
     replicaof: Option<String>,
+    /// Directory of the RDB file.
+    #[arg(long, default_value = ".")]
+    dir: String,
+    #[arg(long, default_value = "dump.rdb")]
+    dbfilename: String,
+    #[arg(long)]
+    appendonly: Option<String>,
+    #[arg(long)]
+    appendfilename: Option<String>,
+    #[arg(long)]
+    appenddirname: Option<String>,
+    #[arg(long)]
+    appendfsync: Option<String>,
+    #[arg(long)]
+    aof_load_truncated: Option<String>,
+    #[arg(long)]
+    replica_read_only: Option<String>,
 }
 
 #[tokio::main]
@@ -30,28 +60,47 @@ async fn main() -> anyhow::Result<()> {
         .await
         .context("Failed to bind port")?;
 
-    let db = db::Db::new(args.replicaof);
+    let mut config = config::Config {
+        dir: args.dir,
+        dbfilename: args.dbfilename,
+        ..Default::default()
+    };
+    for (name, value) in [
+        ("appendonly", args.appendonly),
+        ("appendfilename", args.appendfilename),
+        ("appenddirname", args.appenddirname),
+        ("appendfsync", args.appendfsync),
+        ("aof-load-truncated", args.aof_load_truncated),
+        ("replica-read-only", args.replica_read_only),
+    ] {
+        if let Some(value) = value {
+            config.set(name, &value)?;
+        }
+    }
+    let appendonly = config.appendonly;
+
+    let db = db::Db::new(args.replicaof, config);
+    // The AOF is more complete than snapshots, so it is preferred when enabled.
+    if appendonly {
+        aof::load(&db)?;
+    } else {
+        db.load_rdb()?;
+    }
 
     let expired_keys_db = db.clone();
     tokio::spawn(async move { expired_keys_db.remove_expired_keys().await });
 
-    if let db::State::Slave { master_address, .. } = &*db.state {
-        let stream = TcpStream::connect(master_address)
-            .await
-            .context("Failed to connect to master")?;
-        let (mut writer, mut reader) = split_stream(stream);
+    let save_points_db = db.clone();
+    tokio::spawn(async move { save_points_db.run_save_points().await });
 
-        handshake::Handshake::new(&mut writer, &mut reader, args.port)
-            .send_handshake()
-            .await?;
+    let aof_cron_db = db.clone();
+    tokio::spawn(async move { aof_cron_db.run_aof_cron().await });
 
-        let db = db.clone();
-        tokio::spawn(async move {
-            if let Err(err) = handle_connection(&mut writer, &mut reader, db).await {
-                eprintln!("MASTER CONNECTION ERROR: {}", err);
-            };
-        });
-    }
+    let replication_cron_db = db.clone();
+    tokio::spawn(async move { replication_cron_db.run_replication_cron().await });
+
+    let replica_db = db.clone();
+    tokio::spawn(async move { replication::run_replica(replica_db, args.port).await });
 
     loop {
         let (stream, addr) = listener.accept().await.context("Failed to get client")?;
@@ -59,8 +108,13 @@ async fn main() -> anyhow::Result<()> {
         println!("Accepted connection from {}", addr);
 
         let db = db.clone();
+        let mut client = client::Client::new(&db);
+        client.addr = Some(addr);
         tokio::spawn(async move {
-            if let Err(err) = handle_connection(&mut writer, &mut reader, db).await {
+            let buf = BytesMut::with_capacity(4096);
+            if let Err(err) =
+                connection::handle_connection(&mut writer, &mut reader, db, client, buf).await
+            {
                 eprintln!("CONNECTION ERROR: {}", err);
             }
         });
@@ -78,26 +132,3 @@ fn split_stream(
     let reader = BufReader::new(rd);
     (writer, reader)
 }
-
-async fn handle_connection(
-    writer: &mut BufWriter<WriteHalf<TcpStream>>,
-    reader: &mut BufReader<ReadHalf<TcpStream>>,
-    db: db::Db,
-) -> anyhow::Result<()> {
-    let mut buf = [0; 1024];
-    loop {
-        let bytes_read = reader
-            .read(&mut buf)
-            .await
-            .context("Failed to read stream")?;
-        if bytes_read == 0 {
-            break;
-        }
-
-        let command = commands::parse_command(&mut buf[..bytes_read])?;
-        println!("Command received: {}", command);
-        command.handle(writer, &db).await?;
-    }
-
-    Ok(())
-}
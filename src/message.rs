@@ -14,7 +14,7 @@ pub(crate) struct Array {
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub(crate) struct BulkString {
-    pub(crate) data: String,
+    pub(crate) data: Vec<u8>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -22,12 +22,29 @@ pub(crate) struct SimpleString {
     pub(crate) data: String,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct SimpleError {
+    pub(crate) data: String,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub(crate) enum Message {
     Array(Array),
+    NullArray,
     BulkString(BulkString),
     NullBulkString,
     SimpleString(SimpleString),
+    SimpleError(SimpleError),
+    Integer(i64),
+    /// RESP3 null, sent instead of the RESP2 null bulk string and null array.
+    Null,
+    /// RESP3 out of band data like published messages, sent as an array to RESP2 clients.
+    Push(Vec<Message>),
+    /// RESP3 map, sent as a flat array of keys and values to RESP2 clients.
+    Map(Vec<(Message, Message)>),
+    /// Messages written one after the other, for commands replying more than once like
+    /// `SUBSCRIBE`.
+    Sequence(Vec<Message>),
 }
 
 impl fmt::Display for Message {
@@ -44,9 +61,28 @@ impl fmt::Display for Message {
 
                 write!(f, "{}", formatted)
             }
-            Message::BulkString(value) => write!(f, "${}", value.data),
+            Message::NullArray => write!(f, "*-1"),
+            Message::BulkString(value) => write!(f, "${}", value),
             Message::NullBulkString => write!(f, "$-1"),
             Message::SimpleString(value) => write!(f, "+{}", value.data),
+            Message::SimpleError(value) => write!(f, "-{}", value.data),
+            Message::Integer(value) => write!(f, ":{}", value),
+            Message::Null => write!(f, "_"),
+            Message::Push(elements) => {
+                let formatted: Vec<String> = elements.iter().map(Message::to_string).collect();
+                write!(f, ">{}", formatted.join(" "))
+            }
+            Message::Map(entries) => {
+                let formatted: Vec<String> = entries
+                    .iter()
+                    .map(|(key, value)| format!("{} {}", key, value))
+                    .collect();
+                write!(f, "%{}", formatted.join(" "))
+            }
+            Message::Sequence(messages) => {
+                let formatted: Vec<String> = messages.iter().map(Message::to_string).collect();
+                write!(f, "{}", formatted.join(" "))
+            }
         }
     }
 }
@@ -73,9 +109,19 @@ impl TryFrom<&Message> for BulkString {
     }
 }
 
+impl BulkString {
+    /// Returns the data as a key. Keys are stored as strings, so binary ones are rejected
+    /// rather than converted lossily, which would mix them up with each other.
+    pub(crate) fn key(&self) -> anyhow::Result<String> {
+        String::from_utf8(self.data.clone())
+            .ok()
+            .context("ERR keys must be valid UTF-8")
+    }
+}
+
 impl fmt::Display for BulkString {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.data)
+        write!(f, "{}", String::from_utf8_lossy(&self.data))
     }
 }
 
@@ -98,12 +144,29 @@ impl fmt::Display for SimpleString {
 
 const TERMINATOR_SIZE: usize = 2;
 
+const ERROR_CODES: &[&str] = &[
+    "ERR",
+    "WRONGTYPE",
+    "EXECABORT",
+    "INVALIDOBJ",
+    "CROSSSLOT",
+    "NOPROTO",
+    "READONLY",
+    "NOREPLICAS",
+    "NOMASTERLINK",
+];
+
 impl Message {
     pub(crate) fn deserialize(cursor: &mut Cursor<&[u8]>) -> anyhow::Result<Message> {
         let first_byte = cursor.get_u8();
 
         match first_byte {
             b'*' => {
+                if cursor.chunk()[0] == b'-' {
+                    read_line(cursor).context("Failed to read NullArray")?;
+                    return Ok(Message::NullArray);
+                }
+
                 let size = parse_size(cursor).context("Failed to parse Array size")?;
 
                 let mut elements = Vec::with_capacity(size);
@@ -117,6 +180,7 @@ impl Message {
             }
             b'$' => {
                 if cursor.chunk()[0] == b'-' {
+                    read_line(cursor).context("Failed to read NullBulkString")?;
                     Ok(Message::NullBulkString)
                 } else {
                     let size = parse_size(cursor).context("Failed to parse BulkString size")?;
@@ -125,27 +189,28 @@ impl Message {
                     cursor
                         .read_exact(&mut data)
                         .context("Failed to read BulkString data")?;
-                    anyhow::ensure!(data.len() >= 2, "BulkString data size should be at least 2");
+                    anyhow::ensure!(data.ends_with(b"\r\n"), CRLF_ERROR);
+                    data.truncate(size);
 
-                    Ok(Message::BulkString(BulkString {
-                        data: std::str::from_utf8(&data[..data.len() - TERMINATOR_SIZE])
-                            .context("Failed to parse BulkString data")?
-                            .to_string(),
-                    }))
+                    Ok(Message::BulkString(BulkString { data }))
                 }
             }
             b'+' => {
-                let mut data: Vec<u8> = Vec::new();
-                cursor
-                    .read_until(b'\n', &mut data)
-                    .context("Failed to read SimpleString data")?;
-                anyhow::ensure!(data.len() >= 2, "BulkString data size should be at least 2");
-
-                Ok(Message::SimpleString(SimpleString {
-                    data: std::str::from_utf8(&data[..data.len() - TERMINATOR_SIZE])
-                        .context("Failed to parse SimpleString data")?
-                        .to_string(),
-                }))
+                let data = read_line(cursor).context("Failed to read SimpleString data")?;
+
+                Ok(Message::SimpleString(SimpleString { data }))
+            }
+            b'-' => {
+                let data = read_line(cursor).context("Failed to read SimpleError data")?;
+
+                Ok(Message::SimpleError(SimpleError { data }))
+            }
+            b':' => {
+                let data = read_line(cursor).context("Failed to read Integer data")?;
+
+                Ok(Message::Integer(
+                    data.parse().context("Failed to parse Integer data")?,
+                ))
             }
             _ => anyhow::bail!("Unknown message type: {}", first_byte),
         }
@@ -155,36 +220,112 @@ impl Message {
         Message::Array(Array { elements })
     }
 
-    pub(crate) fn bulk_string(data: String) -> Message {
-        Message::BulkString(BulkString { data })
+    pub(crate) fn bulk_string(data: impl Into<Vec<u8>>) -> Message {
+        Message::BulkString(BulkString { data: data.into() })
     }
 
     pub(crate) fn simple_string(data: String) -> Message {
         Message::SimpleString(SimpleString { data })
     }
 
+    pub(crate) fn simple_error(data: String) -> Message {
+        Message::SimpleError(SimpleError { data })
+    }
+
+    /// Builds an error reply, prefixing `ERR` unless the error already starts with a code.
+    pub(crate) fn error(err: &anyhow::Error) -> Message {
+        let data = err.to_string();
+        let has_code = data
+            .split(' ')
+            .next()
+            .is_some_and(|code| ERROR_CODES.contains(&code));
+
+        if has_code {
+            Message::simple_error(data)
+        } else {
+            Message::simple_error(format!("ERR {}", data))
+        }
+    }
+
     pub(crate) fn ok_message() -> Message {
         Message::simple_string(String::from("OK"))
     }
 
-    async fn write_message(
-        self,
-        writer: &mut (impl AsyncWriteExt + std::marker::Unpin),
-    ) -> anyhow::Result<()> {
+    pub(crate) fn serialize(&self, buf: &mut Vec<u8>) {
+        match self {
+            Message::Array(value) => {
+                buf.extend_from_slice(format!("*{}\r\n", value.elements.len()).as_bytes());
+                for element in &value.elements {
+                    element.serialize(buf);
+                }
+            }
+            Message::BulkString(value) => {
+                buf.extend_from_slice(format!("${}\r\n", value.data.len()).as_bytes());
+                buf.extend_from_slice(&value.data);
+                buf.extend_from_slice(b"\r\n");
+            }
+            Message::NullArray => buf.extend_from_slice(b"*-1\r\n"),
+            Message::NullBulkString => buf.extend_from_slice(b"$-1\r\n"),
+            Message::SimpleString(value) => {
+                buf.extend_from_slice(format!("+{}\r\n", value.data).as_bytes())
+            }
+            Message::SimpleError(value) => {
+                buf.extend_from_slice(format!("-{}\r\n", value.data).as_bytes())
+            }
+            Message::Integer(value) => buf.extend_from_slice(format!(":{}\r\n", value).as_bytes()),
+            Message::Null => buf.extend_from_slice(b"_\r\n"),
+            Message::Push(elements) => {
+                buf.extend_from_slice(format!(">{}\r\n", elements.len()).as_bytes());
+                for element in elements {
+                    element.serialize(buf);
+                }
+            }
+            Message::Map(entries) => {
+                buf.extend_from_slice(format!("%{}\r\n", entries.len()).as_bytes());
+                for (key, value) in entries {
+                    key.serialize(buf);
+                    value.serialize(buf);
+                }
+            }
+            Message::Sequence(messages) => {
+                for message in messages {
+                    message.serialize(buf);
+                }
+            }
+        }
+    }
+
+    /// Converts the RESP3 only types to their RESP2 equivalents.
+    pub(crate) fn into_resp2(self) -> Message {
+        match self {
+            Message::Null => Message::NullBulkString,
+            Message::Array(value) => Message::array(into_resp2(value.elements)),
+            Message::Push(elements) => Message::array(into_resp2(elements)),
+            Message::Map(entries) => Message::array(into_resp2(
+                entries
+                    .into_iter()
+                    .flat_map(|(key, value)| [key, value])
+                    .collect(),
+            )),
+            Message::Sequence(messages) => Message::Sequence(into_resp2(messages)),
+            message => message,
+        }
+    }
+
+    /// Converts the RESP2 nulls to the RESP3 null.
+    pub(crate) fn into_resp3(self) -> Message {
         match self {
-            Message::BulkString(value) => writer
-                .write_all(format!("${}\r\n{}\r\n", value.data.len(), value.data).as_bytes())
-                .await
-                .context("Failed to write BulkString"),
-            Message::NullBulkString => writer
-                .write_all(b"$-1\r\n")
-                .await
-                .context("Failed to write NullBulkString"),
-            Message::SimpleString(value) => writer
-                .write_all(format!("+{}\r\n", value.data).as_bytes())
-                .await
-                .context("Failed to write SimpleString"),
-            Message::Array { .. } => unreachable!(),
+            Message::NullBulkString | Message::NullArray => Message::Null,
+            Message::Array(value) => Message::array(into_resp3(value.elements)),
+            Message::Push(elements) => Message::Push(into_resp3(elements)),
+            Message::Map(entries) => Message::Map(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| (key.into_resp3(), value.into_resp3()))
+                    .collect(),
+            ),
+            Message::Sequence(messages) => Message::Sequence(into_resp3(messages)),
+            message => message,
         }
     }
 
@@ -194,26 +335,155 @@ impl Message {
     ) -> anyhow::Result<()> {
         println!("Writing message {}", self);
 
-        match self {
-            Message::Array(value) => {
-                writer
-                    .write_all(format!("*{}\r\n", value.elements.len()).as_bytes())
-                    .await
-                    .context("Failed to write Array")?;
+        let mut buf = Vec::new();
+        self.serialize(&mut buf);
 
-                for element in value.elements {
-                    element.write_message(writer).await?;
-                }
+        writer
+            .write_all(&buf)
+            .await
+            .context("Failed to write message")?;
+        writer.flush().await?;
+
+        Ok(())
+    }
+}
+
+fn into_resp2(messages: Vec<Message>) -> Vec<Message> {
+    messages.into_iter().map(Message::into_resp2).collect()
+}
+
+fn into_resp3(messages: Vec<Message>) -> Vec<Message> {
+    messages.into_iter().map(Message::into_resp3).collect()
+}
+
+/// Longest line accepted for the header of a message, like Redis' inline requests limit.
+const MAX_LINE_LEN: usize = 64 * 1024;
+/// Largest bulk string accepted, the default `proto-max-bulk-len` of Redis.
+const MAX_BULK_LEN: usize = 512 * 1024 * 1024;
+const MAX_ARRAY_LEN: usize = i32::MAX as usize;
+/// Deepest nesting of arrays accepted, as messages are parsed recursively.
+const MAX_NESTING: usize = 32;
+
+/// Reply to a bulk string not followed by a CRLF, which is written out like in Redis.
+const CRLF_ERROR: &str = "Protocol error: expected '\\r\\n'";
+
+/// Finds where the first message of a buffer ends as its data arrives, resuming after the
+/// elements already scanned instead of scanning the buffer from its start again.
+#[derive(Debug, Default)]
+pub(crate) struct FrameScanner {
+    /// End of the elements scanned so far.
+    position: usize,
+    /// Elements left to scan in each array being scanned, the innermost last.
+    remaining: Vec<usize>,
+}
+
+impl FrameScanner {
+    /// Returns the size of the first complete message in `buf`, or `None` if more data is
+    /// needed. The scanner then starts over, for the message following it once it is removed
+    /// from `buf`.
+    pub(crate) fn frame_len(&mut self, buf: &[u8]) -> anyhow::Result<Option<usize>> {
+        while let Some((end, elements)) = scan_element(buf, self.position)? {
+            self.position = end;
+
+            if elements > 0 {
+                anyhow::ensure!(
+                    self.remaining.len() < MAX_NESTING,
+                    "Protocol error: too deeply nested arrays"
+                );
+                self.remaining.push(elements);
+            } else if self.complete_element() {
+                let len = self.position;
+                *self = Self::default();
+                return Ok(Some(len));
             }
-            _ => self.write_message(writer).await?,
         }
 
-        writer.flush().await?;
+        Ok(None)
+    }
 
-        Ok(())
+    /// Counts an element in the arrays containing it, returning whether it ends the message.
+    fn complete_element(&mut self) -> bool {
+        while let Some(remaining) = self.remaining.last_mut() {
+            *remaining -= 1;
+            if *remaining > 0 {
+                return false;
+            }
+            self.remaining.pop();
+        }
+
+        true
+    }
+}
+
+/// Returns the size of the first complete message in `buf`, or `None` if more data is needed.
+pub(crate) fn frame_len(buf: &[u8]) -> anyhow::Result<Option<usize>> {
+    FrameScanner::default().frame_len(buf)
+}
+
+/// Scans the element starting at `start`, returning where it ends and, for an array, the
+/// number of elements that follow it. An array header ends before its elements.
+fn scan_element(buf: &[u8], start: usize) -> anyhow::Result<Option<(usize, usize)>> {
+    let Some(first_byte) = buf.get(start) else {
+        return Ok(None);
+    };
+    let Some(line_len) = buf[start..].windows(2).position(|window| window == b"\r\n") else {
+        anyhow::ensure!(
+            buf.len() - start <= MAX_LINE_LEN,
+            "Protocol error: too big header line"
+        );
+        return Ok(None);
+    };
+    let line = &buf[start + 1..start + line_len];
+    let next = start + line_len + TERMINATOR_SIZE;
+
+    match first_byte {
+        b'+' | b'-' | b':' => Ok(Some((next, 0))),
+        b'$' => {
+            let size = parse_frame_size(line, MAX_BULK_LEN)
+                .context("Protocol error: invalid bulk length")?;
+            match size {
+                Some(size) if buf.len() < next + size + TERMINATOR_SIZE => Ok(None),
+                Some(size) => {
+                    let end = next + size + TERMINATOR_SIZE;
+                    anyhow::ensure!(buf[end - TERMINATOR_SIZE..end] == *b"\r\n", CRLF_ERROR);
+                    Ok(Some((end, 0)))
+                }
+                None => Ok(Some((next, 0))),
+            }
+        }
+        b'*' => {
+            let size = parse_frame_size(line, MAX_ARRAY_LEN)
+                .context("Protocol error: invalid multibulk length")?;
+            Ok(Some((next, size.unwrap_or(0))))
+        }
+        _ => anyhow::bail!(
+            "Protocol error: unknown message type '{}'",
+            first_byte.escape_ascii()
+        ),
     }
 }
 
+/// Parses a size up to `max`, where negative sizes represent null values.
+fn parse_frame_size(line: &[u8], max: usize) -> anyhow::Result<Option<usize>> {
+    let size: i64 = std::str::from_utf8(line)?.parse()?;
+    let size = usize::try_from(size).ok();
+    anyhow::ensure!(size.unwrap_or_default() <= max, "Size over {}", max);
+
+    Ok(size)
+}
+
+fn read_line(cursor: &mut Cursor<&[u8]>) -> anyhow::Result<String> {
+    let mut data: Vec<u8> = Vec::new();
+    cursor
+        .read_until(b'\n', &mut data)
+        .context("Failed to read line")?;
+    anyhow::ensure!(data.len() >= 2, "Line size should be at least 2");
+
+    Ok(std::str::from_utf8(&data[..data.len() - TERMINATOR_SIZE])
+        .context("Failed to parse line")?
+        .to_string())
+}
+
 fn parse_size(cursor: &mut Cursor<&[u8]>) -> anyhow::Result<usize> {
     let mut size_buf = Vec::new();
     cursor
@@ -227,3 +497,47 @@ fn parse_size(cursor: &mut Cursor<&[u8]>) -> anyhow::Result<usize> {
 
     Ok(size)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scanner_resumes_after_scanned_elements() {
+        let message = b"*2\r\n$3\r\nGET\r\n$3\r\nkey\r\n+OK\r\n";
+        let mut scanner = FrameScanner::default();
+
+        for len in 0..22 {
+            assert_eq!(scanner.frame_len(&message[..len]).unwrap(), None);
+        }
+        assert_eq!(scanner.frame_len(message).unwrap(), Some(22));
+        assert_eq!(scanner.frame_len(&message[22..]).unwrap(), Some(5));
+    }
+
+    #[test]
+    fn scanner_handles_nested_arrays_and_nulls() {
+        let message = b"*3\r\n*2\r\n:1\r\n*0\r\n$-1\r\n*-1\r\n";
+        assert_eq!(frame_len(message).unwrap(), Some(message.len()));
+        assert_eq!(frame_len(&message[..message.len() - 1]).unwrap(), None);
+    }
+
+    #[test]
+    fn scanner_rejects_invalid_headers() {
+        assert!(frame_len(b"$536870912\r\n").is_ok());
+        assert!(frame_len(b"$2\r\nab\r\n").is_ok());
+        assert!(frame_len(b"$2\r\nabc\n").is_err());
+        assert!(frame_len(b"$536870913\r\n").is_err());
+        assert!(frame_len(b"*x\r\n").is_err());
+        assert!(frame_len(b"?\r\n").is_err());
+        assert!(frame_len(&vec![b'+'; MAX_LINE_LEN + 1]).is_err());
+        assert!(frame_len(&b"*1\r\n".repeat(MAX_NESTING + 1)).is_err());
+    }
+    #[test]
+    fn bulk_strings_end_with_crlf() {
+        let message = Message::deserialize(&mut Cursor::new(b"$2\r\nab\r\n".as_slice())).unwrap();
+        assert_eq!(message, Message::bulk_string("ab"));
+
+        let error = Message::deserialize(&mut Cursor::new(b"$2\r\nabcd".as_slice())).unwrap_err();
+        assert_eq!(error.to_string(), CRLF_ERROR);
+    }
+}
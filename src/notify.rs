@@ -0,0 +1,151 @@
+// Keyspace notifications.
+// https://redis.io/docs/latest/develop/use/keyspace-notifications/
+
+use std::fmt;
+
+use crate::pubsub::PubSub;
+
+const KEYSPACE: u16 = 1 << 0;
+const KEYEVENT: u16 = 1 << 1;
+const GENERIC: u16 = 1 << 2;
+const STRING: u16 = 1 << 3;
+const LIST: u16 = 1 << 4;
+const SET: u16 = 1 << 5;
+const HASH: u16 = 1 << 6;
+const SORTED_SET: u16 = 1 << 7;
+const EXPIRED: u16 = 1 << 8;
+const EVICTED: u16 = 1 << 9;
+const STREAM: u16 = 1 << 10;
+const KEY_MISS: u16 = 1 << 11;
+const NEW: u16 = 1 << 12;
+/// The `A` alias, which doesn't include key miss and new key events.
+const ALL: u16 = GENERIC | STRING | LIST | SET | HASH | SORTED_SET | EXPIRED | EVICTED | STREAM;
+
+/// The class of an event, notifications are only sent for the classes enabled in
+/// `notify-keyspace-events`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum EventClass {
+    Generic,
+    String,
+    SortedSet,
+    Expired,
+    KeyMiss,
+    New,
+}
+
+impl EventClass {
+    fn flag(self) -> u16 {
+        match self {
+            EventClass::Generic => GENERIC,
+            EventClass::String => STRING,
+            EventClass::SortedSet => SORTED_SET,
+            EventClass::Expired => EXPIRED,
+            EventClass::KeyMiss => KEY_MISS,
+            EventClass::New => NEW,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct KeyspaceEvent {
+    pub(crate) class: EventClass,
+    pub(crate) event: &'static str,
+    pub(crate) key: String,
+}
+
+/// The `notify-keyspace-events` configuration, notifications are disabled by default.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct NotifyFlags(u16);
+
+impl NotifyFlags {
+    pub(crate) fn parse(value: &str) -> anyhow::Result<Self> {
+        let mut flags = 0;
+
+        for c in value.chars() {
+            flags |= match c {
+                'A' => ALL,
+                'g' => GENERIC,
+                '$' => STRING,
+                'l' => LIST,
+                's' => SET,
+                'h' => HASH,
+                'z' => SORTED_SET,
+                'x' => EXPIRED,
+                'e' => EVICTED,
+                't' => STREAM,
+                'K' => KEYSPACE,
+                'E' => KEYEVENT,
+                'm' => KEY_MISS,
+                'n' => NEW,
+                _ => anyhow::bail!(
+                    "ERR Invalid event class character '{}'. Use 'Ag$lshzxeKEtmn'.",
+                    c
+                ),
+            };
+        }
+
+        Ok(Self(flags))
+    }
+
+    /// Publishes the events enabled by the flags to their `__keyspace@0__` and `__keyevent@0__`
+    /// channels.
+    pub(crate) fn publish(self, pubsub: &PubSub, events: &[KeyspaceEvent]) {
+        if self.0 & (KEYSPACE | KEYEVENT) == 0 {
+            return;
+        }
+
+        for event in events {
+            if self.0 & event.class.flag() == 0 {
+                continue;
+            }
+
+            if self.0 & KEYSPACE != 0 {
+                let channel = format!("__keyspace@0__:{}", event.key);
+                pubsub.publish(channel.as_bytes(), event.event.as_bytes());
+            }
+            if self.0 & KEYEVENT != 0 {
+                let channel = format!("__keyevent@0__:{}", event.event);
+                pubsub.publish(channel.as_bytes(), event.key.as_bytes());
+            }
+        }
+    }
+}
+
+impl fmt::Display for NotifyFlags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut flags = String::new();
+
+        if self.0 & ALL == ALL {
+            flags.push('A');
+        } else {
+            for (flag, c) in [
+                (GENERIC, 'g'),
+                (STRING, '$'),
+                (LIST, 'l'),
+                (SET, 's'),
+                (HASH, 'h'),
+                (SORTED_SET, 'z'),
+                (EXPIRED, 'x'),
+                (EVICTED, 'e'),
+                (STREAM, 't'),
+            ] {
+                if self.0 & flag != 0 {
+                    flags.push(c);
+                }
+            }
+        }
+
+        for (flag, c) in [
+            (KEYSPACE, 'K'),
+            (KEYEVENT, 'E'),
+            (KEY_MISS, 'm'),
+            (NEW, 'n'),
+        ] {
+            if self.0 & flag != 0 {
+                flags.push(c);
+            }
+        }
+
+        write!(f, "{}", flags)
+    }
+}
@@ -0,0 +1,272 @@
+use std::collections::HashMap;
+
+use tokio::sync::mpsc;
+
+use crate::message::Message;
+
+/// Queues of the connections subscribed to a channel or pattern, keyed by client id.
+type Subscribers = HashMap<u64, mpsc::UnboundedSender<Message>>;
+
+/// Channel, pattern and shard channel subscriptions of every connection.
+#[derive(Debug, Default)]
+pub(crate) struct PubSub {
+    channels: HashMap<Vec<u8>, Subscribers>,
+    patterns: HashMap<Vec<u8>, Subscribers>,
+    /// Shard channels live in their own namespace, publishing to them never reaches `channels`
+    /// or `patterns` subscribers.
+    shard_channels: HashMap<Vec<u8>, Subscribers>,
+}
+
+impl PubSub {
+    pub(crate) fn subscribe(
+        &mut self,
+        channel: Vec<u8>,
+        client_id: u64,
+        sender: mpsc::UnboundedSender<Message>,
+    ) {
+        self.channels
+            .entry(channel)
+            .or_default()
+            .insert(client_id, sender);
+    }
+
+    pub(crate) fn unsubscribe(&mut self, channel: &[u8], client_id: u64) {
+        remove_subscriber(&mut self.channels, channel, client_id);
+    }
+
+    pub(crate) fn psubscribe(
+        &mut self,
+        pattern: Vec<u8>,
+        client_id: u64,
+        sender: mpsc::UnboundedSender<Message>,
+    ) {
+        self.patterns
+            .entry(pattern)
+            .or_default()
+            .insert(client_id, sender);
+    }
+
+    pub(crate) fn punsubscribe(&mut self, pattern: &[u8], client_id: u64) {
+        remove_subscriber(&mut self.patterns, pattern, client_id);
+    }
+
+    pub(crate) fn ssubscribe(
+        &mut self,
+        channel: Vec<u8>,
+        client_id: u64,
+        sender: mpsc::UnboundedSender<Message>,
+    ) {
+        self.shard_channels
+            .entry(channel)
+            .or_default()
+            .insert(client_id, sender);
+    }
+
+    pub(crate) fn sunsubscribe(&mut self, channel: &[u8], client_id: u64) {
+        remove_subscriber(&mut self.shard_channels, channel, client_id);
+    }
+
+    /// Delivers the message to the channel and matching pattern subscribers, returning how many
+    /// received it.
+    pub(crate) fn publish(&self, channel: &[u8], message: &[u8]) -> usize {
+        let mut receivers = 0;
+
+        for sender in self
+            .channels
+            .get(channel)
+            .into_iter()
+            .flat_map(HashMap::values)
+        {
+            let _ = sender.send(Message::Push(vec![
+                Message::bulk_string("message"),
+                Message::bulk_string(channel),
+                Message::bulk_string(message),
+            ]));
+            receivers += 1;
+        }
+
+        for (pattern, subscribers) in &self.patterns {
+            if !glob_match(pattern, channel) {
+                continue;
+            }
+
+            for sender in subscribers.values() {
+                let _ = sender.send(Message::Push(vec![
+                    Message::bulk_string("pmessage"),
+                    Message::bulk_string(pattern.clone()),
+                    Message::bulk_string(channel),
+                    Message::bulk_string(message),
+                ]));
+                receivers += 1;
+            }
+        }
+
+        receivers
+    }
+
+    /// Delivers the message to the shard channel subscribers, returning how many received it.
+    pub(crate) fn spublish(&self, channel: &[u8], message: &[u8]) -> usize {
+        let subscribers = self.shard_channels.get(channel);
+
+        for sender in subscribers.into_iter().flat_map(HashMap::values) {
+            let _ = sender.send(Message::Push(vec![
+                Message::bulk_string("smessage"),
+                Message::bulk_string(channel),
+                Message::bulk_string(message),
+            ]));
+        }
+
+        subscribers.map_or(0, HashMap::len)
+    }
+
+    /// Returns the channels with at least one subscriber, optionally filtered by a glob pattern.
+    pub(crate) fn channels(&self, pattern: Option<&[u8]>) -> Vec<Vec<u8>> {
+        matching_names(&self.channels, pattern)
+    }
+
+    pub(crate) fn shard_channels(&self, pattern: Option<&[u8]>) -> Vec<Vec<u8>> {
+        matching_names(&self.shard_channels, pattern)
+    }
+
+    pub(crate) fn subscriber_count(&self, channel: &[u8]) -> usize {
+        self.channels.get(channel).map_or(0, HashMap::len)
+    }
+
+    pub(crate) fn shard_subscriber_count(&self, channel: &[u8]) -> usize {
+        self.shard_channels.get(channel).map_or(0, HashMap::len)
+    }
+
+    pub(crate) fn pattern_count(&self) -> usize {
+        self.patterns.len()
+    }
+}
+
+fn matching_names(
+    subscriptions: &HashMap<Vec<u8>, Subscribers>,
+    pattern: Option<&[u8]>,
+) -> Vec<Vec<u8>> {
+    subscriptions
+        .keys()
+        .filter(|name| match pattern {
+            Some(pattern) => glob_match(pattern, name),
+            None => true,
+        })
+        .cloned()
+        .collect()
+}
+
+fn remove_subscriber(subscriptions: &mut HashMap<Vec<u8>, Subscribers>, name: &[u8], id: u64) {
+    if let Some(subscribers) = subscriptions.get_mut(name) {
+        subscribers.remove(&id);
+        if subscribers.is_empty() {
+            subscriptions.remove(name);
+        }
+    }
+}
+
+/// Glob-style matching like Redis' `stringmatchlen`, supporting `*`, `?`, `[...]` classes with
+/// `^` negation and ranges, and `\` escapes.
+pub(crate) fn glob_match(pattern: &[u8], string: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => string.is_empty(),
+        Some((b'*', _)) => {
+            let rest = &pattern[pattern.iter().take_while(|&&c| c == b'*').count()..];
+            rest.is_empty() || (0..=string.len()).any(|i| glob_match(rest, &string[i..]))
+        }
+        Some((b'?', rest)) => !string.is_empty() && glob_match(rest, &string[1..]),
+        Some((b'[', rest)) => {
+            let Some((&c, string_rest)) = string.split_first() else {
+                return false;
+            };
+            let (negated, mut class) = match rest.split_first() {
+                Some((b'^', class)) => (true, class),
+                _ => (false, rest),
+            };
+
+            let mut matched = false;
+            loop {
+                match class {
+                    [] => break,
+                    [b']', remaining @ ..] => {
+                        class = remaining;
+                        break;
+                    }
+                    [b'\\', escaped, remaining @ ..] => {
+                        matched |= *escaped == c;
+                        class = remaining;
+                    }
+                    [start, b'-', end, remaining @ ..] => {
+                        let (start, end) = if start <= end {
+                            (*start, *end)
+                        } else {
+                            (*end, *start)
+                        };
+                        matched |= (start..=end).contains(&c);
+                        class = remaining;
+                    }
+                    [other, remaining @ ..] => {
+                        matched |= *other == c;
+                        class = remaining;
+                    }
+                }
+            }
+
+            matched != negated && glob_match(class, string_rest)
+        }
+        Some((b'\\', [escaped, rest @ ..])) => {
+            string.first() == Some(escaped) && glob_match(rest, &string[1..])
+        }
+        Some((c, rest)) => string.first() == Some(c) && glob_match(rest, &string[1..]),
+    }
+}
+
+/// Builds the confirmation replied for each channel or pattern (un)subscribed.
+pub(crate) fn subscription_message(kind: &str, name: Option<&[u8]>, count: usize) -> Message {
+    Message::Push(vec![
+        Message::bulk_string(kind),
+        name.map_or(Message::NullBulkString, Message::bulk_string),
+        Message::Integer(count as i64),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, string: &str) -> bool {
+        glob_match(pattern.as_bytes(), string.as_bytes())
+    }
+
+    #[test]
+    fn wildcards() {
+        assert!(matches("h?llo", "hello"));
+        assert!(!matches("h?llo", "hllo"));
+        assert!(matches("h*llo", "hllo"));
+        assert!(matches("h*llo", "heeeello"));
+        assert!(matches("*", ""));
+        assert!(matches("**a**", "bab"));
+        assert!(!matches("*a", "ab"));
+        assert!(!matches("hello", "hello!"));
+    }
+
+    #[test]
+    fn classes() {
+        assert!(matches("h[ae]llo", "hello"));
+        assert!(matches("h[ae]llo", "hallo"));
+        assert!(!matches("h[ae]llo", "hillo"));
+        assert!(matches("h[^e]llo", "hallo"));
+        assert!(!matches("h[^e]llo", "hello"));
+        assert!(matches("h[a-b]llo", "hbllo"));
+        assert!(matches("h[b-a]llo", "hallo"));
+        assert!(!matches("h[a-b]llo", "hcllo"));
+        assert!(matches("[\\]]", "]"));
+        assert!(!matches("[a]", ""));
+    }
+
+    #[test]
+    fn escapes() {
+        assert!(matches("h\\*llo", "h*llo"));
+        assert!(!matches("h\\*llo", "hello"));
+        assert!(matches("news.\\?", "news.?"));
+    }
+}
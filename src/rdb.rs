@@ -0,0 +1,890 @@
+// RDB snapshot format.
+// https://rdb.fnordig.de/file_format.html
+// https://github.com/redis/redis/blob/unstable/src/rdb.c
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Context;
+
+use crate::{
+    db::{Entry, Value},
+    sorted_set::SortedSet,
+};
+
+mod crc64;
+mod lzf;
+
+const MAGIC: &[u8] = b"REDIS";
+/// The format version written, the one of Redis 7.2.
+const VERSION: &[u8] = b"0011";
+const REDIS_VERSION: &str = "7.2.0";
+/// The newest format version understood, written by Redis 7.4.
+const MAX_VERSION: u32 = 12;
+
+// Opcodes found where a value type is expected.
+const OPCODE_SLOT_INFO: u8 = 0xf4;
+const OPCODE_FUNCTION2: u8 = 0xf5;
+const OPCODE_FUNCTION_PRE_GA: u8 = 0xf6;
+const OPCODE_MODULE_AUX: u8 = 0xf7;
+const OPCODE_IDLE: u8 = 0xf8;
+const OPCODE_FREQ: u8 = 0xf9;
+const OPCODE_AUX: u8 = 0xfa;
+const OPCODE_RESIZEDB: u8 = 0xfb;
+const OPCODE_EXPIRETIME_MS: u8 = 0xfc;
+const OPCODE_EXPIRETIME: u8 = 0xfd;
+const OPCODE_SELECTDB: u8 = 0xfe;
+const OPCODE_EOF: u8 = 0xff;
+
+// Value types.
+const TYPE_STRING: u8 = 0;
+const TYPE_LIST: u8 = 1;
+const TYPE_SET: u8 = 2;
+const TYPE_ZSET: u8 = 3;
+const TYPE_HASH: u8 = 4;
+const TYPE_ZSET_2: u8 = 5;
+const TYPE_MODULE_2: u8 = 7;
+const TYPE_HASH_ZIPMAP: u8 = 9;
+const TYPE_LIST_ZIPLIST: u8 = 10;
+const TYPE_SET_INTSET: u8 = 11;
+const TYPE_ZSET_ZIPLIST: u8 = 12;
+const TYPE_HASH_ZIPLIST: u8 = 13;
+const TYPE_LIST_QUICKLIST: u8 = 14;
+const TYPE_STREAM_LISTPACKS: u8 = 15;
+const TYPE_HASH_LISTPACK: u8 = 16;
+const TYPE_ZSET_LISTPACK: u8 = 17;
+const TYPE_LIST_QUICKLIST_2: u8 = 18;
+const TYPE_STREAM_LISTPACKS_2: u8 = 19;
+const TYPE_SET_LISTPACK: u8 = 20;
+const TYPE_STREAM_LISTPACKS_3: u8 = 21;
+const TYPE_HASH_METADATA_PRE_GA: u8 = 22;
+const TYPE_HASH_LISTPACK_EX_PRE_GA: u8 = 23;
+const TYPE_HASH_METADATA: u8 = 24;
+const TYPE_HASH_LISTPACK_EX: u8 = 25;
+
+// Opcodes of the values saved by modules.
+const MODULE_OPCODE_EOF: u64 = 0;
+const MODULE_OPCODE_SINT: u64 = 1;
+const MODULE_OPCODE_UINT: u64 = 2;
+const MODULE_OPCODE_FLOAT: u64 = 3;
+const MODULE_OPCODE_DOUBLE: u64 = 4;
+const MODULE_OPCODE_STRING: u64 = 5;
+
+// Special string encodings, flagged by the two most significant bits of the length.
+const ENCODING_INT8: u8 = 0;
+const ENCODING_INT16: u8 = 1;
+const ENCODING_INT32: u8 = 2;
+const ENCODING_LZF: u8 = 3;
+
+/// The content of an RDB file.
+#[derive(Debug, Default)]
+pub(crate) struct Snapshot {
+    /// Keys of the first database, expired keys included.
+    pub(crate) entries: Vec<(String, Entry)>,
+    /// Auxiliary fields, like `redis-ver` or `repl-id`.
+    pub(crate) aux: HashMap<String, Vec<u8>>,
+}
+
+impl Snapshot {
+    /// Returns the replication stream position saved in the `repl-id` and `repl-offset` fields.
+    pub(crate) fn replication_position(&self) -> Option<ReplicationPosition> {
+        let id = String::from_utf8(self.aux.get("repl-id")?.clone()).ok()?;
+        let offset = std::str::from_utf8(self.aux.get("repl-offset")?)
+            .ok()?
+            .parse()
+            .ok()?;
+
+        Some(ReplicationPosition { id, offset })
+    }
+}
+
+/// The position in the replication stream the keys of a snapshot correspond to.
+#[derive(Debug, Clone)]
+pub(crate) struct ReplicationPosition {
+    pub(crate) id: String,
+    pub(crate) offset: usize,
+}
+
+/// Outcome of the snapshots written to disk.
+#[derive(Debug)]
+pub(crate) struct SaveStatus {
+    /// Unix time in seconds of the last successful save.
+    pub(crate) last_save: u64,
+    pub(crate) last_bgsave_ok: bool,
+    /// Unix time in seconds of the last background save attempt.
+    pub(crate) last_bgsave_try: u64,
+    pub(crate) bgsave_in_progress: bool,
+}
+
+impl SaveStatus {
+    pub(crate) fn new() -> Self {
+        Self {
+            last_save: unix_time(),
+            last_bgsave_ok: true,
+            last_bgsave_try: 0,
+            bgsave_in_progress: false,
+        }
+    }
+}
+
+/// Serializes the keys into an RDB file of the first database, with the replication stream
+/// position they correspond to when there is one.
+pub(crate) fn serialize<'a>(
+    entries: impl IntoIterator<Item = (&'a String, &'a Entry)>,
+    position: Option<&ReplicationPosition>,
+) -> Vec<u8> {
+    let entries: Vec<_> = entries.into_iter().collect();
+    let expires = entries
+        .iter()
+        .filter(|(_, entry)| entry.expire_at().is_some())
+        .count();
+
+    let mut writer = Writer::default();
+    writer.write_bytes(MAGIC);
+    writer.write_bytes(VERSION);
+    writer.write_aux("redis-ver", REDIS_VERSION);
+    writer.write_aux("redis-bits", &(usize::BITS).to_string());
+    writer.write_aux("ctime", &unix_time().to_string());
+    writer.write_aux("used-mem", "0");
+    if let Some(position) = position {
+        writer.write_aux("repl-stream-db", "0");
+        writer.write_aux("repl-id", &position.id);
+        writer.write_aux("repl-offset", &position.offset.to_string());
+    }
+    writer.write_aux("aof-base", "0");
+
+    writer.write_bytes(&[OPCODE_SELECTDB]);
+    writer.write_length(0);
+    writer.write_bytes(&[OPCODE_RESIZEDB]);
+    writer.write_length(entries.len() as u64);
+    writer.write_length(expires as u64);
+
+    for (key, entry) in entries {
+        if let Some(expire_at) = entry.expire_at() {
+            writer.write_bytes(&[OPCODE_EXPIRETIME_MS]);
+            writer.write_bytes(&(expire_at as u64).to_le_bytes());
+        }
+
+        match &entry.value {
+            Value::String(value) => {
+                writer.write_bytes(&[TYPE_STRING]);
+                writer.write_string(key.as_bytes());
+                writer.write_string(value);
+            }
+            Value::SortedSet(set) => {
+                writer.write_bytes(&[TYPE_ZSET_2]);
+                writer.write_string(key.as_bytes());
+                writer.write_length(set.len() as u64);
+                for (member, score) in set.iter() {
+                    writer.write_string(member);
+                    writer.write_bytes(&score.to_le_bytes());
+                }
+            }
+        }
+    }
+
+    writer.write_bytes(&[OPCODE_EOF]);
+    let checksum = crc64::crc64(0, &writer.bytes);
+    writer.write_bytes(&checksum.to_le_bytes());
+
+    writer.bytes
+}
+
+/// Writes the file through a temporary file, so a failed save never leaves a truncated RDB.
+pub(crate) fn write(path: &Path, bytes: &[u8]) -> anyhow::Result<()> {
+    let temp_path = path.with_file_name(format!("temp-{}.rdb", std::process::id()));
+    fs::write(&temp_path, bytes)
+        .with_context(|| format!("Failed to write {}", temp_path.display()))?;
+    fs::rename(&temp_path, path).with_context(|| format!("Failed to rename to {}", path.display()))
+}
+
+/// Parses an RDB file, validating its checksum unless it was disabled when writing it.
+pub(crate) fn parse(bytes: &[u8]) -> anyhow::Result<Snapshot> {
+    let mut reader = Reader::new(bytes);
+
+    anyhow::ensure!(
+        reader.read_bytes(MAGIC.len())? == MAGIC,
+        "Invalid RDB magic"
+    );
+    let version: u32 = std::str::from_utf8(reader.read_bytes(4)?)
+        .ok()
+        .and_then(|version| version.parse().ok())
+        .context("Invalid RDB version")?;
+    anyhow::ensure!(
+        (1..=MAX_VERSION).contains(&version),
+        "Unsupported RDB version {}",
+        version
+    );
+
+    let mut snapshot = Snapshot::default();
+    let mut db_index = 0;
+    let mut expire_at = None;
+
+    loop {
+        match reader.read_u8()? {
+            OPCODE_EOF => break,
+            OPCODE_SELECTDB => db_index = reader.read_length()?,
+            OPCODE_RESIZEDB => {
+                reader.read_length()?;
+                reader.read_length()?;
+            }
+            OPCODE_AUX => {
+                let key = reader.read_string()?;
+                let value = reader.read_string()?;
+                snapshot
+                    .aux
+                    .insert(String::from_utf8_lossy(&key).to_string(), value);
+            }
+            OPCODE_EXPIRETIME_MS => {
+                expire_at = Some(u64::from_le_bytes(reader.read_array()?) as u128);
+            }
+            OPCODE_EXPIRETIME => {
+                expire_at = Some(u32::from_le_bytes(reader.read_array()?) as u128 * 1000);
+            }
+            OPCODE_IDLE => {
+                reader.read_length()?;
+            }
+            OPCODE_FREQ => {
+                reader.read_u8()?;
+            }
+            OPCODE_SLOT_INFO => {
+                for _ in 0..3 {
+                    reader.read_length()?;
+                }
+            }
+            // Functions and module data can't be used here, they are skipped.
+            OPCODE_FUNCTION2 => {
+                reader.read_string()?;
+            }
+            OPCODE_MODULE_AUX => {
+                reader.read_length()?;
+                reader.read_length()?;
+                reader.read_length()?;
+                reader.skip_module_data()?;
+            }
+            OPCODE_FUNCTION_PRE_GA => {
+                anyhow::bail!("Unsupported RDB opcode {:#x}", OPCODE_FUNCTION_PRE_GA)
+            }
+            value_type => {
+                let key = reader.read_string()?;
+                let value = reader.read_value(value_type).with_context(|| {
+                    format!("Failed to load key {}", String::from_utf8_lossy(&key))
+                })?;
+                // Keys are stored as strings, binary ones can't be told apart once converted.
+                let Ok(key) = String::from_utf8(key) else {
+                    eprintln!("Skipping key that is not valid UTF-8");
+                    expire_at = None;
+                    continue;
+                };
+                let Some(value) = value else {
+                    eprintln!(
+                        "Skipping key {} of unsupported RDB value type {}",
+                        key, value_type
+                    );
+                    expire_at = None;
+                    continue;
+                };
+                let entry = match expire_at.take() {
+                    Some(expire_at) => Entry::with_expire_at(value, expire_at),
+                    None => Entry::new(value, None),
+                };
+
+                // There is a single database, keys of the others are dropped.
+                if db_index == 0 {
+                    snapshot.entries.push((key, entry));
+                }
+            }
+        }
+    }
+
+    if version >= 5 {
+        let position = reader.position;
+        let checksum = u64::from_le_bytes(reader.read_array()?);
+        anyhow::ensure!(
+            checksum == 0 || checksum == crc64::crc64(0, &bytes[..position]),
+            "Wrong RDB checksum"
+        );
+    }
+
+    Ok(snapshot)
+}
+
+#[derive(Default)]
+struct Writer {
+    bytes: Vec<u8>,
+}
+
+impl Writer {
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        self.bytes.extend_from_slice(bytes);
+    }
+
+    fn write_length(&mut self, length: u64) {
+        if length < 1 << 6 {
+            self.bytes.push(length as u8);
+        } else if length < 1 << 14 {
+            self.bytes
+                .extend_from_slice(&((length as u16) | 0x4000).to_be_bytes());
+        } else if let Ok(length) = u32::try_from(length) {
+            self.bytes.push(0x80);
+            self.bytes.extend_from_slice(&length.to_be_bytes());
+        } else {
+            self.bytes.push(0x81);
+            self.bytes.extend_from_slice(&length.to_be_bytes());
+        }
+    }
+
+    fn write_string(&mut self, string: &[u8]) {
+        self.write_length(string.len() as u64);
+        self.write_bytes(string);
+    }
+
+    fn write_aux(&mut self, key: &str, value: &str) {
+        self.write_bytes(&[OPCODE_AUX]);
+        self.write_string(key.as_bytes());
+        self.write_string(value.as_bytes());
+    }
+}
+
+enum Length {
+    Length(u64),
+    /// A string stored in a special encoding.
+    Encoded(u8),
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, position: 0 }
+    }
+
+    fn read_bytes(&mut self, len: usize) -> anyhow::Result<&'a [u8]> {
+        let bytes = self
+            .position
+            .checked_add(len)
+            .and_then(|end| self.bytes.get(self.position..end))
+            .context("Unexpected end of RDB file")?;
+        self.position += len;
+
+        Ok(bytes)
+    }
+
+    fn read_array<const N: usize>(&mut self) -> anyhow::Result<[u8; N]> {
+        Ok(self
+            .read_bytes(N)?
+            .try_into()
+            .expect("slice should have the array length"))
+    }
+
+    fn read_u8(&mut self) -> anyhow::Result<u8> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    fn read_length_encoding(&mut self) -> anyhow::Result<Length> {
+        let first = self.read_u8()?;
+
+        let length = match first >> 6 {
+            0b00 => Length::Length((first & 0x3f) as u64),
+            0b01 => Length::Length((((first & 0x3f) as u64) << 8) | self.read_u8()? as u64),
+            0b10 => match first {
+                0x80 => Length::Length(u32::from_be_bytes(self.read_array()?) as u64),
+                0x81 => Length::Length(u64::from_be_bytes(self.read_array()?)),
+                _ => anyhow::bail!("Invalid RDB length encoding {:#x}", first),
+            },
+            _ => Length::Encoded(first & 0x3f),
+        };
+
+        Ok(length)
+    }
+
+    fn read_length(&mut self) -> anyhow::Result<u64> {
+        match self.read_length_encoding()? {
+            Length::Length(length) => Ok(length),
+            Length::Encoded(_) => anyhow::bail!("Unexpected RDB string encoding"),
+        }
+    }
+
+    fn read_usize(&mut self) -> anyhow::Result<usize> {
+        usize::try_from(self.read_length()?).context("RDB length out of range")
+    }
+
+    fn read_string(&mut self) -> anyhow::Result<Vec<u8>> {
+        let string = match self.read_length_encoding()? {
+            Length::Length(len) => {
+                let len = usize::try_from(len).context("RDB length out of range")?;
+                self.read_bytes(len)?.to_vec()
+            }
+            Length::Encoded(ENCODING_INT8) => (self.read_u8()? as i8).to_string().into_bytes(),
+            Length::Encoded(ENCODING_INT16) => i16::from_le_bytes(self.read_array()?)
+                .to_string()
+                .into_bytes(),
+            Length::Encoded(ENCODING_INT32) => i32::from_le_bytes(self.read_array()?)
+                .to_string()
+                .into_bytes(),
+            Length::Encoded(ENCODING_LZF) => {
+                let compressed_len = self.read_usize()?;
+                let len = self.read_usize()?;
+                lzf::decompress(self.read_bytes(compressed_len)?, len)?
+            }
+            Length::Encoded(encoding) => {
+                anyhow::bail!("Unknown RDB string encoding {}", encoding)
+            }
+        };
+
+        Ok(string)
+    }
+
+    /// Reads a score of the original sorted set type, stored as a length prefixed string.
+    fn read_double_string(&mut self) -> anyhow::Result<f64> {
+        let score = match self.read_u8()? {
+            253 => f64::NAN,
+            254 => f64::INFINITY,
+            255 => f64::NEG_INFINITY,
+            len => parse_score(self.read_bytes(len as usize)?)?,
+        };
+
+        Ok(score)
+    }
+
+    /// Reads a value, returning `None` for the types this server can't store, which are skipped.
+    fn read_value(&mut self, value_type: u8) -> anyhow::Result<Option<Value>> {
+        let value = match value_type {
+            TYPE_STRING => Value::String(self.read_string()?),
+            TYPE_ZSET | TYPE_ZSET_2 => {
+                let mut set = SortedSet::new();
+                for _ in 0..self.read_length()? {
+                    let member = self.read_string()?;
+                    let score = if value_type == TYPE_ZSET_2 {
+                        f64::from_le_bytes(self.read_array()?)
+                    } else {
+                        self.read_double_string()?
+                    };
+                    set.insert(member, score);
+                }
+
+                Value::SortedSet(set)
+            }
+            TYPE_ZSET_ZIPLIST | TYPE_ZSET_LISTPACK => {
+                let blob = self.read_string()?;
+                let elements = if value_type == TYPE_ZSET_ZIPLIST {
+                    ziplist_elements(&blob)?
+                } else {
+                    listpack_elements(&blob)?
+                };
+                anyhow::ensure!(elements.len() % 2 == 0, "Odd sorted set element count");
+
+                let mut set = SortedSet::new();
+                for pair in elements.chunks(2) {
+                    set.insert(pair[0].clone(), parse_score(&pair[1])?);
+                }
+
+                Value::SortedSet(set)
+            }
+            value_type => {
+                self.skip_value(value_type)?;
+                return Ok(None);
+            }
+        };
+
+        Ok(Some(value))
+    }
+
+    /// Skips a value of a type that isn't supported, failing for the ones that can't be skipped.
+    fn skip_value(&mut self, value_type: u8) -> anyhow::Result<()> {
+        match value_type {
+            TYPE_LIST | TYPE_SET | TYPE_LIST_QUICKLIST => {
+                for _ in 0..self.read_length()? {
+                    self.read_string()?;
+                }
+            }
+            TYPE_HASH => {
+                for _ in 0..self.read_length()? * 2 {
+                    self.read_string()?;
+                }
+            }
+            TYPE_LIST_QUICKLIST_2 => {
+                for _ in 0..self.read_length()? {
+                    // The container of the node, then the node.
+                    self.read_length()?;
+                    self.read_string()?;
+                }
+            }
+            TYPE_HASH_ZIPMAP
+            | TYPE_LIST_ZIPLIST
+            | TYPE_SET_INTSET
+            | TYPE_HASH_ZIPLIST
+            | TYPE_HASH_LISTPACK
+            | TYPE_SET_LISTPACK
+            | TYPE_HASH_LISTPACK_EX_PRE_GA => {
+                self.read_string()?;
+            }
+            TYPE_HASH_LISTPACK_EX => {
+                // The minimum expiration of the fields.
+                self.read_bytes(8)?;
+                self.read_string()?;
+            }
+            TYPE_HASH_METADATA | TYPE_HASH_METADATA_PRE_GA => {
+                if value_type == TYPE_HASH_METADATA {
+                    self.read_bytes(8)?;
+                }
+                for _ in 0..self.read_length()? {
+                    // The expiration of the field, then the field and its value.
+                    if value_type == TYPE_HASH_METADATA {
+                        self.read_length()?;
+                    } else {
+                        self.read_bytes(8)?;
+                    }
+                    self.read_string()?;
+                    self.read_string()?;
+                }
+            }
+            TYPE_STREAM_LISTPACKS | TYPE_STREAM_LISTPACKS_2 | TYPE_STREAM_LISTPACKS_3 => {
+                self.skip_stream(value_type)?;
+            }
+            TYPE_MODULE_2 => {
+                // The id of the module.
+                self.read_length()?;
+                self.skip_module_data()?;
+            }
+            value_type => anyhow::bail!("Unsupported RDB value type {}", value_type),
+        }
+
+        Ok(())
+    }
+
+    fn skip_stream(&mut self, value_type: u8) -> anyhow::Result<()> {
+        for _ in 0..self.read_length()? {
+            // The master id of the node, then the node.
+            self.read_string()?;
+            self.read_string()?;
+        }
+        // The length and the last id, then the first id, the max deleted id and the count of
+        // entries added.
+        let ids = if value_type == TYPE_STREAM_LISTPACKS {
+            3
+        } else {
+            8
+        };
+        for _ in 0..ids {
+            self.read_length()?;
+        }
+
+        for _ in 0..self.read_length()? {
+            // The name of the consumer group, its last id and entries read.
+            self.read_string()?;
+            self.read_length()?;
+            self.read_length()?;
+            if value_type != TYPE_STREAM_LISTPACKS {
+                self.read_length()?;
+            }
+
+            for _ in 0..self.read_length()? {
+                // The id of the pending entry, its delivery time and count.
+                self.read_bytes(16)?;
+                self.read_bytes(8)?;
+                self.read_length()?;
+            }
+
+            for _ in 0..self.read_length()? {
+                // The name of the consumer, its seen and active times, then its pending ids.
+                self.read_string()?;
+                self.read_bytes(8)?;
+                if value_type == TYPE_STREAM_LISTPACKS_3 {
+                    self.read_bytes(8)?;
+                }
+                for _ in 0..self.read_length()? {
+                    self.read_bytes(16)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Skips the values saved by a module, each preceded by an opcode, up to the EOF one.
+    fn skip_module_data(&mut self) -> anyhow::Result<()> {
+        loop {
+            match self.read_length()? {
+                MODULE_OPCODE_EOF => return Ok(()),
+                MODULE_OPCODE_SINT | MODULE_OPCODE_UINT => {
+                    self.read_length()?;
+                }
+                MODULE_OPCODE_FLOAT => {
+                    self.read_bytes(4)?;
+                }
+                MODULE_OPCODE_DOUBLE => {
+                    self.read_bytes(8)?;
+                }
+                MODULE_OPCODE_STRING => {
+                    self.read_string()?;
+                }
+                opcode => anyhow::bail!("Unknown RDB module opcode {}", opcode),
+            }
+        }
+    }
+}
+
+fn parse_score(bytes: &[u8]) -> anyhow::Result<f64> {
+    std::str::from_utf8(bytes)
+        .ok()
+        .and_then(|score| match score {
+            "inf" | "+inf" => Some(f64::INFINITY),
+            "-inf" => Some(f64::NEG_INFINITY),
+            score => score.parse().ok(),
+        })
+        .context("Invalid sorted set score")
+}
+
+/// Decodes the elements of a ziplist, integers are returned in their decimal representation.
+/// https://github.com/redis/redis/blob/5.0/src/ziplist.c
+fn ziplist_elements(blob: &[u8]) -> anyhow::Result<Vec<Vec<u8>>> {
+    let mut reader = Reader::new(blob);
+    // zlbytes, zltail and zllen.
+    reader.read_bytes(10)?;
+
+    let mut elements = Vec::new();
+    loop {
+        let prevlen = reader.read_u8()?;
+        if prevlen == 0xff {
+            break;
+        }
+        if prevlen == 0xfe {
+            reader.read_bytes(4)?;
+        }
+
+        let encoding = reader.read_u8()?;
+        let element = match encoding >> 6 {
+            0b00 => reader.read_bytes((encoding & 0x3f) as usize)?.to_vec(),
+            0b01 => {
+                let len = (((encoding & 0x3f) as usize) << 8) | reader.read_u8()? as usize;
+                reader.read_bytes(len)?.to_vec()
+            }
+            0b10 => {
+                let len = u32::from_be_bytes(reader.read_array()?) as usize;
+                reader.read_bytes(len)?.to_vec()
+            }
+            _ => {
+                let value = match encoding {
+                    0xc0 => i16::from_le_bytes(reader.read_array()?) as i64,
+                    0xd0 => i32::from_le_bytes(reader.read_array()?) as i64,
+                    0xe0 => i64::from_le_bytes(reader.read_array()?),
+                    0xf0 => {
+                        let [a, b, c] = reader.read_array()?;
+                        (i32::from_le_bytes([0, a, b, c]) >> 8) as i64
+                    }
+                    0xfe => reader.read_u8()? as i8 as i64,
+                    0xf1..=0xfd => (encoding & 0x0f) as i64 - 1,
+                    _ => anyhow::bail!("Invalid ziplist encoding {:#x}", encoding),
+                };
+                value.to_string().into_bytes()
+            }
+        };
+        elements.push(element);
+    }
+
+    Ok(elements)
+}
+
+/// Decodes the elements of a listpack, integers are returned in their decimal representation.
+/// https://github.com/redis/redis/blob/unstable/src/listpack.c
+fn listpack_elements(blob: &[u8]) -> anyhow::Result<Vec<Vec<u8>>> {
+    let mut reader = Reader::new(blob);
+    // Total bytes and number of elements.
+    reader.read_bytes(6)?;
+
+    let mut elements = Vec::new();
+    loop {
+        let start = reader.position;
+        let encoding = reader.read_u8()?;
+        let element = match encoding {
+            0xff => break,
+            0x00..=0x7f => encoding.to_string().into_bytes(),
+            0x80..=0xbf => reader.read_bytes((encoding & 0x3f) as usize)?.to_vec(),
+            0xc0..=0xdf => {
+                let value = (((encoding & 0x1f) as u16) << 8) | reader.read_u8()? as u16;
+                // Sign extend the 13 bits integer.
+                (((value << 3) as i16) >> 3).to_string().into_bytes()
+            }
+            0xe0..=0xef => {
+                let len = (((encoding & 0x0f) as usize) << 8) | reader.read_u8()? as usize;
+                reader.read_bytes(len)?.to_vec()
+            }
+            0xf0 => {
+                let len = u32::from_le_bytes(reader.read_array()?) as usize;
+                reader.read_bytes(len)?.to_vec()
+            }
+            0xf1 => i16::from_le_bytes(reader.read_array()?)
+                .to_string()
+                .into_bytes(),
+            0xf2 => {
+                let [a, b, c] = reader.read_array()?;
+                (i32::from_le_bytes([0, a, b, c]) >> 8)
+                    .to_string()
+                    .into_bytes()
+            }
+            0xf3 => i32::from_le_bytes(reader.read_array()?)
+                .to_string()
+                .into_bytes(),
+            0xf4 => i64::from_le_bytes(reader.read_array()?)
+                .to_string()
+                .into_bytes(),
+            _ => anyhow::bail!("Invalid listpack encoding {:#x}", encoding),
+        };
+
+        // Skip the backlen, which takes one byte per 7 bits of the entry length.
+        let entry_len = reader.position - start;
+        reader.read_bytes(match entry_len {
+            0..=127 => 1,
+            128..=16382 => 2,
+            16383..=2097150 => 3,
+            2097151..=268435454 => 4,
+            _ => 5,
+        })?;
+
+        elements.push(element);
+    }
+
+    Ok(elements)
+}
+
+pub(crate) fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("SystemTime before UNIX EPOCH!")
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn elements(elements: &[&str]) -> Vec<Vec<u8>> {
+        elements
+            .iter()
+            .map(|element| element.as_bytes().to_vec())
+            .collect()
+    }
+
+    /// Builds a file with the given keys in the first database, followed by its checksum.
+    fn file(keys: &[u8]) -> Vec<u8> {
+        let mut writer = Writer::default();
+        writer.write_bytes(MAGIC);
+        writer.write_bytes(VERSION);
+        writer.write_bytes(&[OPCODE_SELECTDB, 0]);
+        writer.write_bytes(keys);
+        writer.write_bytes(&[OPCODE_EOF]);
+        let checksum = crc64::crc64(0, &writer.bytes);
+        writer.write_bytes(&checksum.to_le_bytes());
+
+        writer.bytes
+    }
+
+    #[test]
+    fn ziplist() {
+        // The ziplist of `ZADD z 1 a 2 b` in Redis 5.
+        let blob = b"\x15\x00\x00\x00\x12\x00\x00\x00\x04\x00\
+            \x00\x01a\x03\xf2\x02\x01b\x03\xf3\xff";
+        assert_eq!(
+            ziplist_elements(blob).unwrap(),
+            elements(&["a", "1", "b", "2"])
+        );
+    }
+
+    #[test]
+    fn ziplist_integers() {
+        let blob = b"\x00\x00\x00\x00\x00\x00\x00\x00\x05\x00\
+            \x00\xfe\x85\
+            \x03\xc0\x2e\xfb\
+            \x04\xf0\xa0\x86\x01\
+            \x05\xd0\x80\x1a\x06\x00\
+            \x06\xf1\xff";
+        assert_eq!(
+            ziplist_elements(blob).unwrap(),
+            elements(&["-123", "-1234", "100000", "400000", "0"])
+        );
+    }
+
+    #[test]
+    fn listpack() {
+        let blob = b"\x00\x00\x00\x00\x05\x00\
+            \x81a\x02\
+            \x01\x01\
+            \xdf\xfb\x02\
+            \xf1\xe8\x03\x03\
+            \xf3\xa0\x86\x01\x00\x05\xff";
+        assert_eq!(
+            listpack_elements(blob).unwrap(),
+            elements(&["a", "1", "-5", "1000", "100000"])
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut set = SortedSet::new();
+        set.insert(b"a".to_vec(), 1.5);
+        set.insert(b"b".to_vec(), -2.0);
+        let string = String::from("string");
+        let zset = String::from("zset");
+        let entries = [
+            (
+                string.clone(),
+                Entry::new(Value::String(b"value".to_vec()), None),
+            ),
+            (
+                zset.clone(),
+                Entry::with_expire_at(Value::SortedSet(set), 1_000),
+            ),
+        ];
+        let position = ReplicationPosition {
+            id: String::from("8371b4fb1155b71f4a04d3e1bc3e18c4a990aeeb"),
+            offset: 42,
+        };
+
+        let bytes = serialize(
+            entries.iter().map(|(key, entry)| (key, entry)),
+            Some(&position),
+        );
+        let snapshot = parse(&bytes).unwrap();
+
+        assert_eq!(snapshot.entries.len(), 2);
+        let (key, entry) = &snapshot.entries[0];
+        assert_eq!(key, &string);
+        assert_eq!(entry.value.as_string().unwrap(), b"value");
+        let (key, entry) = &snapshot.entries[1];
+        assert_eq!(key, &zset);
+        assert_eq!(entry.expire_at(), Some(1_000));
+        let Value::SortedSet(set) = &entry.value else {
+            panic!("Expected a sorted set");
+        };
+        assert_eq!(set.score(b"b"), Some(-2.0));
+
+        let loaded = snapshot.replication_position().unwrap();
+        assert_eq!((loaded.id, loaded.offset), (position.id, position.offset));
+    }
+
+    #[test]
+    fn skips_unsupported_types() {
+        let keys = b"\x01\x04list\x02\x01a\x01b\
+            \x04\x04hash\x01\x01f\x01v\
+            \x00\x03key\x05value";
+        let snapshot = parse(&file(keys)).unwrap();
+
+        assert_eq!(snapshot.entries.len(), 1);
+        assert_eq!(snapshot.entries[0].0, "key");
+    }
+
+    #[test]
+    fn rejects_wrong_checksum() {
+        let mut bytes = file(b"\x00\x03key\x05value");
+        *bytes.last_mut().unwrap() ^= 1;
+        assert!(parse(&bytes).is_err());
+    }
+}
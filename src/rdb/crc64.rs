@@ -0,0 +1,50 @@
+// CRC-64/Jones, the checksum Redis appends to RDB files.
+// https://github.com/redis/redis/blob/unstable/src/crc64.c
+
+const POLY: u64 = 0x95ac_9329_ac4b_c9b5;
+
+const TABLE: [u64; 256] = {
+    let mut table = [0; 256];
+
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u64;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ POLY
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+
+    table
+};
+
+/// Continues the checksum `crc` with `bytes`, starting from 0 for a new checksum.
+pub(crate) fn crc64(crc: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(crc, |crc, &byte| {
+        TABLE[((crc ^ byte as u64) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_value() {
+        assert_eq!(crc64(0, b"123456789"), 0xe9c6_d914_c4b8_d9ca);
+    }
+
+    #[test]
+    fn continues_a_checksum() {
+        let crc = crc64(0, b"1234");
+        assert_eq!(crc64(crc, b"56789"), crc64(0, b"123456789"));
+        assert_eq!(crc64(0, b""), 0);
+    }
+}
@@ -0,0 +1,90 @@
+// LZF decompression, used by Redis to compress strings in RDB files.
+// https://github.com/redis/redis/blob/unstable/src/lzf_d.c
+
+/// Decompresses `input`, which must expand to exactly `length` bytes.
+pub(crate) fn decompress(input: &[u8], length: usize) -> anyhow::Result<Vec<u8>> {
+    let mut output = Vec::with_capacity(length);
+
+    let mut i = 0;
+    while i < input.len() {
+        let ctrl = input[i] as usize;
+        i += 1;
+
+        if ctrl < 1 << 5 {
+            // Literal run of ctrl + 1 bytes.
+            let run = input
+                .get(i..i + ctrl + 1)
+                .ok_or_else(|| anyhow::anyhow!("LZF literal run out of bounds"))?;
+            output.extend_from_slice(run);
+            i += ctrl + 1;
+        } else {
+            // Back reference of len + 2 bytes.
+            let mut len = ctrl >> 5;
+            if len == 7 {
+                len += *input
+                    .get(i)
+                    .ok_or_else(|| anyhow::anyhow!("LZF back reference out of bounds"))?
+                    as usize;
+                i += 1;
+            }
+            let offset = ((ctrl & 0x1f) << 8)
+                + *input
+                    .get(i)
+                    .ok_or_else(|| anyhow::anyhow!("LZF back reference out of bounds"))?
+                    as usize
+                + 1;
+            i += 1;
+
+            anyhow::ensure!(
+                offset <= output.len(),
+                "LZF back reference before the start of the output"
+            );
+            let start = output.len() - offset;
+            // The reference can overlap the bytes it produces, so copy one byte at a time.
+            for j in 0..len + 2 {
+                output.push(output[start + j]);
+            }
+        }
+    }
+
+    anyhow::ensure!(
+        output.len() == length,
+        "LZF decompressed {} bytes, expected {}",
+        output.len(),
+        length
+    );
+
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn literal_run() {
+        assert_eq!(decompress(b"\x04hello", 5).unwrap(), b"hello");
+    }
+
+    #[test]
+    fn overlapping_back_reference() {
+        // A literal "a" repeated by a back reference of 7 + 2 bytes at offset 1.
+        assert_eq!(decompress(b"\x00a\xe0\x00\x00", 10).unwrap(), b"aaaaaaaaaa");
+    }
+
+    #[test]
+    fn long_back_reference() {
+        // A literal "hello ", then 11 bytes copied from 6 bytes back.
+        assert_eq!(
+            decompress(b"\x05hello \xe0\x02\x05", 17).unwrap(),
+            b"hello hello hello"
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_input() {
+        assert!(decompress(b"\x04hell", 5).is_err());
+        assert!(decompress(b"\x00a\x20\x05", 4).is_err());
+        assert!(decompress(b"\x04hello", 6).is_err());
+    }
+}
@@ -0,0 +1,636 @@
+// Replication stream, replicas connected to this server and the link of a replica with its
+// master.
+// https://redis.io/docs/latest/operate/oss_and_stack/management/replication/
+
+use std::{
+    collections::{hash_map::RandomState, BTreeMap, VecDeque},
+    hash::{BuildHasher, Hasher},
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    time::{Duration, Instant, SystemTime},
+};
+
+use anyhow::Context;
+use tokio::{
+    net::TcpStream,
+    sync::{watch, Notify},
+};
+
+use crate::{
+    client::Client, config::OutputBufferLimit, connection, db::Db, handshake::Handshake,
+    rdb::ReplicationPosition,
+};
+
+/// Delay before reconnecting to the master, doubled after each failed attempt.
+const RECONNECT_DELAY_MIN: Duration = Duration::from_secs(1);
+const RECONNECT_DELAY_MAX: Duration = Duration::from_secs(30);
+
+/// Whether this server accepts writes or follows a master, changed with `REPLICAOF`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Role {
+    Master,
+    Replica { master_address: String },
+}
+
+/// State of the connection of a replica with its master.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum MasterLink {
+    Down {
+        since: Instant,
+    },
+    /// Connected, the handshake and the transfer of the snapshot are in progress.
+    Syncing,
+    Up,
+}
+
+/// The replication stream of this server: the commands it propagates as a master, or receives
+/// from its master as a replica.
+#[derive(Debug)]
+pub(crate) struct Replication {
+    pub(crate) role: Role,
+    /// The id of the stream, the one of the master for a replica.
+    pub(crate) id: String,
+    /// Bytes of the stream since it started.
+    pub(crate) offset: usize,
+    /// The id of the previous master, so its replicas can continue from this server after a
+    /// failover up to `previous_offset`.
+    pub(crate) previous_id: String,
+    pub(crate) previous_offset: Option<usize>,
+    /// The last bytes of the stream, kept while replicas may continue from them.
+    backlog: Option<VecDeque<u8>>,
+    pub(crate) master_link: MasterLink,
+    /// When the replica last received data from its master.
+    pub(crate) master_last_io: Instant,
+    /// The output buffers of the replicas of this server.
+    outputs: Vec<Arc<ReplicaOutput>>,
+    /// Signaled on every role change, to restart the link with the master.
+    role_changes: watch::Sender<()>,
+}
+
+impl Replication {
+    pub(crate) fn new(id: String, role: Role) -> Self {
+        Self {
+            role,
+            id,
+            offset: 0,
+            previous_id: String::from("0000000000000000000000000000000000000000"),
+            previous_offset: None,
+            backlog: None,
+            master_link: MasterLink::Down {
+                since: Instant::now(),
+            },
+            master_last_io: Instant::now(),
+            outputs: Vec::new(),
+            role_changes: watch::channel(()).0,
+        }
+    }
+
+    pub(crate) fn is_master(&self) -> bool {
+        self.role == Role::Master
+    }
+
+    /// Switches role. The replicas of this server are disconnected, as the stream they follow
+    /// changes.
+    pub(crate) fn set_role(&mut self, role: Role) {
+        self.role = role;
+        self.disconnect_replicas();
+        self.master_link = MasterLink::Down {
+            since: Instant::now(),
+        };
+        self.role_changes.send_replace(());
+    }
+
+    pub(crate) fn subscribe_role_changes(&self) -> watch::Receiver<()> {
+        self.role_changes.subscribe()
+    }
+
+    /// Disconnects the replicas of this server, for them to synchronize again when the stream
+    /// they follow changes.
+    pub(crate) fn disconnect_replicas(&mut self) {
+        for output in self.outputs.drain(..) {
+            output.close("Replication stream changed");
+        }
+    }
+
+    /// Creates the output buffer of a replica, receiving the stream from now on.
+    pub(crate) fn subscribe(&mut self) -> Arc<ReplicaOutput> {
+        let output = Arc::new(ReplicaOutput::default());
+        self.outputs.push(output.clone());
+
+        output
+    }
+
+    /// Starts following a new stream from `offset`, after a full resynchronization.
+    pub(crate) fn reset(&mut self, id: String, offset: usize) {
+        self.id = id;
+        self.offset = offset;
+        self.previous_id = String::from("0000000000000000000000000000000000000000");
+        self.previous_offset = None;
+        self.backlog = Some(VecDeque::new());
+    }
+
+    /// Adds bytes to the stream, keeping the last `backlog_size` ones.
+    pub(crate) fn feed(&mut self, bytes: &[u8], backlog_size: usize) {
+        self.offset += bytes.len();
+
+        if let Some(backlog) = &mut self.backlog {
+            backlog.extend(bytes);
+            let excess = backlog.len().saturating_sub(backlog_size);
+            backlog.drain(..excess);
+        }
+    }
+
+    /// Starts keeping the stream from the current offset.
+    pub(crate) fn create_backlog(&mut self) {
+        self.backlog.get_or_insert_with(VecDeque::new);
+    }
+
+    pub(crate) fn free_backlog(&mut self) {
+        self.backlog = None;
+    }
+
+    pub(crate) fn has_backlog(&self) -> bool {
+        self.backlog.is_some()
+    }
+
+    /// Returns the offset of the first byte in the backlog, and how many it holds.
+    pub(crate) fn backlog_range(&self) -> (usize, usize) {
+        let len = self.backlog.as_ref().map_or(0, VecDeque::len);
+
+        (self.offset - len, len)
+    }
+
+    /// Returns the bytes of the stream after `offset` of `id`, when all of them are in the
+    /// backlog.
+    pub(crate) fn since(&self, id: &str, offset: usize) -> Option<Vec<u8>> {
+        let known_id = id == self.id
+            || (id == self.previous_id
+                && self
+                    .previous_offset
+                    .is_some_and(|previous_offset| offset <= previous_offset));
+        let backlog = self.backlog.as_ref()?;
+        let (start, _) = self.backlog_range();
+        if !known_id || offset < start || offset > self.offset {
+            return None;
+        }
+
+        Some(backlog.range(offset - start..).copied().collect())
+    }
+
+    /// Returns the position to save with a snapshot, which is only useful when this server is
+    /// part of a replication chain.
+    pub(crate) fn position(&self) -> Option<ReplicationPosition> {
+        self.has_backlog().then(|| ReplicationPosition {
+            id: self.id.clone(),
+            offset: self.offset,
+        })
+    }
+
+    /// Switches to a new id, the stream of the current one stays valid up to this point.
+    pub(crate) fn shift_id(&mut self, id: String) {
+        self.previous_id = std::mem::replace(&mut self.id, id);
+        self.previous_offset = Some(self.offset);
+    }
+
+    /// Appends bytes of the stream to the output buffers of the replicas, returning how many
+    /// receive them. The replicas that exceed the limit are disconnected.
+    pub(crate) fn send(&mut self, bytes: &[u8], limit: &OutputBufferLimit) -> usize {
+        // Only the connection of the replica is left holding a disconnected one.
+        self.outputs
+            .retain(|output| Arc::strong_count(output) > 1 && output.push(bytes, limit));
+
+        self.outputs.len()
+    }
+}
+
+#[derive(Debug, Default)]
+struct OutputBuffer {
+    bytes: Vec<u8>,
+    /// When the buffer went above the soft limit.
+    soft_limit_since: Option<Instant>,
+    /// Why the replica has to be disconnected.
+    closed: Option<&'static str>,
+}
+
+/// The stream propagated to a replica and not written to its connection yet.
+#[derive(Debug, Default)]
+pub(crate) struct ReplicaOutput {
+    buffer: Mutex<OutputBuffer>,
+    pushed: Notify,
+    closing: Notify,
+}
+
+impl ReplicaOutput {
+    /// Appends bytes, closing the buffer once it exceeds the limit. Returns whether it is still
+    /// open.
+    fn push(&self, bytes: &[u8], limit: &OutputBufferLimit) -> bool {
+        let mut buffer = self.buffer();
+        if buffer.closed.is_some() {
+            return false;
+        }
+
+        buffer.bytes.extend_from_slice(bytes);
+        let len = buffer.bytes.len() as u64;
+        buffer.soft_limit_since = if limit.soft > 0 && len > limit.soft {
+            Some(buffer.soft_limit_since.unwrap_or_else(Instant::now))
+        } else {
+            None
+        };
+        let over_soft_limit = buffer
+            .soft_limit_since
+            .is_some_and(|since| since.elapsed() > Duration::from_secs(limit.soft_seconds));
+        let overflowed = (limit.hard > 0 && len > limit.hard) || over_soft_limit;
+        if overflowed {
+            eprintln!(
+                "Replica output buffer of {} bytes is over the limit, disconnecting it",
+                len
+            );
+            buffer.bytes = Vec::new();
+            buffer.closed = Some("Replica output buffer limit reached");
+        }
+        drop(buffer);
+        self.pushed.notify_one();
+        if overflowed {
+            self.closing.notify_waiters();
+        }
+
+        !overflowed
+    }
+
+    fn close(&self, reason: &'static str) {
+        self.buffer().closed = Some(reason);
+        self.pushed.notify_one();
+        self.closing.notify_waiters();
+    }
+
+    /// Waits until the replica has to be disconnected, returning why. A replica that doesn't
+    /// read blocks the writes to its connection, so they are interrupted.
+    pub(crate) async fn closed(&self) -> &'static str {
+        loop {
+            let closing = self.closing.notified();
+            if let Some(reason) = self.buffer().closed {
+                return reason;
+            }
+            closing.await;
+        }
+    }
+
+    /// Waits for bytes to write to the replica, failing once it has to be disconnected.
+    pub(crate) async fn next(&self) -> anyhow::Result<Vec<u8>> {
+        loop {
+            {
+                let mut buffer = self.buffer();
+                if let Some(reason) = buffer.closed {
+                    anyhow::bail!(reason);
+                }
+                if !buffer.bytes.is_empty() {
+                    return Ok(std::mem::take(&mut buffer.bytes));
+                }
+            }
+
+            self.pushed.notified().await;
+        }
+    }
+
+    fn buffer(&self) -> MutexGuard<'_, OutputBuffer> {
+        self.buffer.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Generates a random id of 40 hexadecimal characters.
+pub(crate) fn random_id() -> String {
+    // Each `RandomState` is seeded with random keys.
+    let state = RandomState::new();
+    let nanos = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_nanos());
+
+    let mut id: String = (0..3)
+        .map(|i| {
+            let mut hasher = state.build_hasher();
+            hasher.write_u8(i);
+            hasher.write_u128(nanos);
+            format!("{:016x}", hasher.finish())
+        })
+        .collect();
+    id.truncate(40);
+
+    id
+}
+
+/// A replica streaming the commands propagated by this server.
+#[derive(Debug)]
+pub(crate) struct Replica {
+    pub(crate) ip: String,
+    /// The port announced with `REPLCONF listening-port`.
+    pub(crate) port: u16,
+    /// The offset of the replication stream the replica acknowledged with `REPLCONF ACK`.
+    pub(crate) ack_offset: usize,
+    pub(crate) last_ack: Instant,
+}
+
+/// The replicas, keyed by the id of their connection.
+#[derive(Debug)]
+pub(crate) struct Replicas {
+    replicas: BTreeMap<u64, Replica>,
+    /// When the last replica disconnected, the backlog is freed after `repl-backlog-ttl`.
+    empty_since: Instant,
+    /// Signaled on every acknowledgement, to wake up `WAIT`.
+    acks: watch::Sender<()>,
+}
+
+impl Default for Replicas {
+    fn default() -> Self {
+        Self {
+            replicas: BTreeMap::new(),
+            empty_since: Instant::now(),
+            acks: watch::channel(()).0,
+        }
+    }
+}
+
+impl Replicas {
+    pub(crate) fn register(&mut self, client_id: u64, ip: String, port: u16) {
+        self.replicas.insert(
+            client_id,
+            Replica {
+                ip,
+                port,
+                ack_offset: 0,
+                last_ack: Instant::now(),
+            },
+        );
+    }
+
+    pub(crate) fn unregister(&mut self, client_id: u64) {
+        if self.replicas.remove(&client_id).is_some() && self.replicas.is_empty() {
+            self.empty_since = Instant::now();
+        }
+    }
+
+    pub(crate) fn ack(&mut self, client_id: u64, offset: usize) {
+        if let Some(replica) = self.replicas.get_mut(&client_id) {
+            replica.ack_offset = replica.ack_offset.max(offset);
+            replica.last_ack = Instant::now();
+            self.acks.send_replace(());
+        }
+    }
+
+    pub(crate) fn last_ack(&self, client_id: u64) -> Option<Instant> {
+        self.replicas
+            .get(&client_id)
+            .map(|replica| replica.last_ack)
+    }
+
+    /// Returns how many replicas acknowledged the offset.
+    pub(crate) fn acknowledged(&self, offset: usize) -> usize {
+        self.iter()
+            .filter(|replica| replica.ack_offset >= offset)
+            .count()
+    }
+
+    /// Returns how many replicas acknowledged within `max_lag` seconds.
+    pub(crate) fn good(&self, max_lag: u64) -> usize {
+        self.iter()
+            .filter(|replica| replica.last_ack.elapsed().as_secs() <= max_lag)
+            .count()
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &Replica> {
+        self.replicas.values()
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.replicas.len()
+    }
+
+    /// Returns how long there was no replica.
+    pub(crate) fn empty_for(&self) -> Option<Duration> {
+        self.replicas.is_empty().then(|| self.empty_since.elapsed())
+    }
+}
+
+/// Set by `WAIT`, the connection replies once enough replicas acknowledged the offset.
+#[derive(Debug)]
+pub(crate) struct WaitAcks {
+    pub(crate) numreplicas: usize,
+    pub(crate) offset: usize,
+    /// Waits forever when `None`.
+    pub(crate) timeout: Option<Duration>,
+}
+
+/// Blocks until `numreplicas` replicas acknowledged the offset or the timeout expires, returning
+/// how many did.
+pub(crate) async fn wait_for_acks(db: &Db, wait: WaitAcks) -> usize {
+    let deadline = wait
+        .timeout
+        .map(|timeout| tokio::time::Instant::now() + timeout);
+    // Subscribing before counting, so an acknowledgement in between is not missed.
+    let mut acks = db.replicas().acks.subscribe();
+
+    loop {
+        let acknowledged = db.replicas().acknowledged(wait.offset);
+        if acknowledged >= wait.numreplicas {
+            return acknowledged;
+        }
+
+        let timeout = async {
+            match deadline {
+                Some(deadline) => tokio::time::sleep_until(deadline).await,
+                None => std::future::pending().await,
+            }
+        };
+        tokio::select! {
+            changed = acks.changed() => {
+                if changed.is_err() {
+                    return acknowledged;
+                }
+            }
+            () = timeout => return db.replicas().acknowledged(wait.offset),
+        }
+    }
+}
+
+/// Keeps the link with the master while this server is a replica: it synchronizes, applies the
+/// stream until the link breaks, then reconnects with an exponential backoff. The link restarts
+/// whenever the role changes.
+pub(crate) async fn run_replica(db: Db, port: u16) {
+    let mut role_changes = db.replication().subscribe_role_changes();
+
+    loop {
+        role_changes.borrow_and_update();
+        let Role::Replica { master_address } = db.replication().role.clone() else {
+            if role_changes.changed().await.is_err() {
+                return;
+            }
+            continue;
+        };
+
+        let mut delay = RECONNECT_DELAY_MIN;
+        loop {
+            tokio::select! {
+                biased;
+                _ = role_changes.changed() => break,
+                result = follow_master(&db, port, &master_address, &mut delay) => {
+                    if let Err(err) = result {
+                        eprintln!("MASTER CONNECTION ERROR: {:#}", err);
+                    }
+                }
+            }
+            db.replication().master_link = MasterLink::Down {
+                since: Instant::now(),
+            };
+
+            println!("Reconnecting to master in {:?}", delay);
+            tokio::select! {
+                biased;
+                _ = role_changes.changed() => break,
+                () = tokio::time::sleep(delay) => {}
+            }
+            delay = (delay * 2).min(RECONNECT_DELAY_MAX);
+        }
+        println!("Master link closed after a role change");
+    }
+}
+
+async fn follow_master(
+    db: &Db,
+    port: u16,
+    master_address: &str,
+    delay: &mut Duration,
+) -> anyhow::Result<()> {
+    let timeout = Duration::from_secs(db.config().repl_timeout);
+    db.replication().master_link = MasterLink::Syncing;
+    let stream = tokio::time::timeout(timeout, TcpStream::connect(master_address))
+        .await
+        .context("Timeout connecting to master")?
+        .context("Failed to connect to master")?;
+    let (mut writer, mut reader) = crate::split_stream(stream);
+
+    let buf = Handshake::new(&mut writer, &mut reader, db, port)
+        .send_handshake()
+        .await?;
+    {
+        let mut replication = db.replication();
+        replication.master_link = MasterLink::Up;
+        replication.master_last_io = Instant::now();
+    }
+    *delay = RECONNECT_DELAY_MIN;
+    println!("Master link is up");
+
+    let client = Client::new_master_link(db);
+    connection::handle_connection(&mut writer, &mut reader, db.clone(), client, buf).await?;
+    println!("Master closed the connection");
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn replication() -> Replication {
+        Replication::new(String::from("a").repeat(40), Role::Master)
+    }
+
+    #[test]
+    fn since_returns_the_bytes_after_the_offset() {
+        let mut replication = replication();
+        let id = replication.id.clone();
+        replication.create_backlog();
+        replication.feed(b"hello world", 100);
+
+        assert_eq!(replication.since(&id, 0), Some(b"hello world".to_vec()));
+        assert_eq!(replication.since(&id, 6), Some(b"world".to_vec()));
+        assert_eq!(replication.since(&id, 11), Some(Vec::new()));
+        assert_eq!(replication.since(&id, 12), None);
+        assert_eq!(replication.since(&"b".repeat(40), 6), None);
+    }
+
+    #[test]
+    fn since_fails_before_the_backlog() {
+        let mut replication = replication();
+        let id = replication.id.clone();
+        replication.feed(b"hello ", 100);
+        assert_eq!(replication.since(&id, 0), None);
+
+        replication.create_backlog();
+        replication.feed(b"world", 100);
+        assert_eq!(replication.since(&id, 0), None);
+        assert_eq!(replication.since(&id, 6), Some(b"world".to_vec()));
+
+        // Only the last bytes are kept.
+        replication.feed(b"!", 3);
+        assert_eq!(replication.backlog_range(), (9, 3));
+        assert_eq!(replication.since(&id, 6), None);
+        assert_eq!(replication.since(&id, 9), Some(b"ld!".to_vec()));
+    }
+
+    #[test]
+    fn since_continues_the_previous_id() {
+        let mut replication = replication();
+        let previous_id = replication.id.clone();
+        replication.create_backlog();
+        replication.feed(b"hello", 100);
+
+        replication.shift_id(String::from("b").repeat(40));
+        replication.feed(b" world", 100);
+
+        assert_eq!(
+            replication.since(&previous_id, 0),
+            Some(b"hello world".to_vec())
+        );
+        assert_eq!(replication.since(&previous_id, 5), Some(b" world".to_vec()));
+        // The previous master never sent the bytes after the switch.
+        assert_eq!(replication.since(&previous_id, 6), None);
+        assert_eq!(
+            replication.since(&"b".repeat(40), 6),
+            Some(b"world".to_vec())
+        );
+    }
+
+    fn limit(hard: u64, soft: u64, soft_seconds: u64) -> OutputBufferLimit {
+        OutputBufferLimit {
+            hard,
+            soft,
+            soft_seconds,
+        }
+    }
+
+    #[tokio::test]
+    async fn send_disconnects_replicas_over_the_hard_limit() {
+        let mut replication = replication();
+        let output = replication.subscribe();
+        let limit = limit(10, 0, 0);
+
+        assert_eq!(replication.send(b"hello", &limit), 1);
+        assert_eq!(output.next().await.unwrap(), b"hello");
+        // The limit applies to the bytes not written to the replica yet.
+        assert_eq!(replication.send(b"hello", &limit), 1);
+        assert_eq!(replication.send(b" world", &limit), 0);
+        assert_eq!(output.closed().await, "Replica output buffer limit reached");
+        assert!(output.next().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn send_disconnects_replicas_over_the_soft_limit_for_too_long() {
+        let mut replication = replication();
+        let output = replication.subscribe();
+        let limit = limit(0, 5, 1);
+
+        assert_eq!(replication.send(b"hello world", &limit), 1);
+        std::thread::sleep(Duration::from_millis(1100));
+        assert_eq!(replication.send(b"!", &limit), 0);
+        assert_eq!(output.closed().await, "Replica output buffer limit reached");
+    }
+
+    #[test]
+    fn send_forgets_disconnected_replicas() {
+        let mut replication = replication();
+        let output = replication.subscribe();
+        let limit = limit(0, 0, 0);
+
+        assert_eq!(replication.send(b"hello", &limit), 1);
+        drop(output);
+        assert_eq!(replication.send(b"hello", &limit), 0);
+    }
+}
@@ -0,0 +1,87 @@
+// Key to hash slot mapping, matching Redis Cluster.
+// https://redis.io/docs/latest/operate/oss_and_stack/reference/cluster-spec/#hash-tags
+
+pub(crate) const SLOT_COUNT: u16 = 16384;
+
+/// Returns the slot of a key, only hashing its `{...}` hash tag when it has a non-empty one.
+pub(crate) fn key_hash_slot(key: &[u8]) -> u16 {
+    let tag = key.iter().position(|&c| c == b'{').and_then(|start| {
+        key[start + 1..]
+            .iter()
+            .position(|&c| c == b'}')
+            .filter(|&len| len > 0)
+            .map(|len| &key[start + 1..start + 1 + len])
+    });
+
+    crc16(tag.unwrap_or(key)) % SLOT_COUNT
+}
+
+/// CRC16 XMODEM, the variant used by Redis Cluster.
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0;
+    for &byte in data {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+
+    crc
+}
+
+/// Fails unless every key hashes to the same slot, like multi-key commands in Redis Cluster.
+pub(crate) fn ensure_same_slot<'a>(keys: impl IntoIterator<Item = &'a [u8]>) -> anyhow::Result<()> {
+    let mut slots = keys.into_iter().map(key_hash_slot);
+    if let Some(first) = slots.next() {
+        anyhow::ensure!(
+            slots.all(|slot| slot == first),
+            "CROSSSLOT Keys in request don't hash to the same slot"
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc16_check_value() {
+        assert_eq!(crc16(b"123456789"), 0x31c3);
+    }
+
+    // Slots replied by `CLUSTER KEYSLOT` in Redis.
+    #[test]
+    fn slot() {
+        assert_eq!(key_hash_slot(b"somekey"), 11058);
+        assert_eq!(key_hash_slot(b"foo"), 12182);
+        assert_eq!(key_hash_slot(b"foo{hash_tag}"), 2515);
+    }
+
+    #[test]
+    fn hash_tag() {
+        let slot = key_hash_slot(b"user1000");
+        assert_eq!(key_hash_slot(b"{user1000}.following"), slot);
+        assert_eq!(key_hash_slot(b"{user1000}.followers"), slot);
+        // Only the first tag is hashed.
+        assert_eq!(key_hash_slot(b"foo{user1000}{bar}"), slot);
+        // Empty tags are ignored, the whole key is hashed.
+        assert_eq!(
+            key_hash_slot(b"foo{}{bar}"),
+            crc16(b"foo{}{bar}") % SLOT_COUNT
+        );
+        assert_eq!(key_hash_slot(b"foo{{bar}}"), key_hash_slot(b"{bar"));
+    }
+
+    #[test]
+    fn same_slot() {
+        assert!(ensure_same_slot([&b"{user1000}.a"[..], b"{user1000}.b"]).is_ok());
+        assert!(ensure_same_slot([&b"foo"[..], b"somekey"]).is_err());
+        assert!(ensure_same_slot([]).is_ok());
+    }
+}
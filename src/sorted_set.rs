@@ -0,0 +1,77 @@
+use std::{
+    cmp::Ordering,
+    collections::{BTreeSet, HashMap},
+    ops::Range,
+};
+
+#[derive(Debug, Clone, Copy)]
+struct Score(f64);
+
+impl PartialEq for Score {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Score {}
+
+impl PartialOrd for Score {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Score {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+/// Members ordered by score, and by member bytes when scores are equal.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct SortedSet {
+    scores: HashMap<Vec<u8>, f64>,
+    ordered: BTreeSet<(Score, Vec<u8>)>,
+}
+
+impl SortedSet {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Inserts or updates a member, returning whether it was added.
+    pub(crate) fn insert(&mut self, member: Vec<u8>, score: f64) -> bool {
+        let added = match self.scores.insert(member.clone(), score) {
+            Some(previous) => {
+                self.ordered.remove(&(Score(previous), member.clone()));
+                false
+            }
+            None => true,
+        };
+        self.ordered.insert((Score(score), member));
+
+        added
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.scores.len()
+    }
+
+    pub(crate) fn score(&self, member: &[u8]) -> Option<f64> {
+        self.scores.get(member).copied()
+    }
+
+    /// Iterates over the members in ascending score order.
+    pub(crate) fn iter(&self) -> impl DoubleEndedIterator<Item = (&[u8], f64)> {
+        self.ordered
+            .iter()
+            .map(|(score, member)| (member.as_slice(), score.0))
+    }
+
+    /// Iterates over the members with a score in `range`, in ascending score order.
+    pub(crate) fn range(&self, range: Range<f64>) -> impl Iterator<Item = (&[u8], f64)> {
+        self.ordered
+            .range((Score(range.start), Vec::new())..(Score(range.end), Vec::new()))
+            .map(|(score, member)| (member.as_slice(), score.0))
+    }
+}
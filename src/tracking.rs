@@ -0,0 +1,176 @@
+// Server assisted client side caching.
+// https://redis.io/docs/latest/develop/reference/client-side-caching/
+
+use std::collections::{HashMap, HashSet};
+
+use tokio::sync::mpsc;
+
+use crate::message::Message;
+
+/// The `CLIENT TRACKING` options of a connection.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct TrackingOptions {
+    pub(crate) redirect: Option<u64>,
+    pub(crate) bcast: bool,
+    pub(crate) prefixes: Vec<Vec<u8>>,
+    pub(crate) optin: bool,
+    pub(crate) optout: bool,
+    pub(crate) noloop: bool,
+}
+
+/// Keys read by the tracking connections, and the queues invalidation messages are sent to.
+#[derive(Debug, Default)]
+pub(crate) struct TrackingTable {
+    /// Queues of every connection, as invalidations can be redirected to any of them.
+    senders: HashMap<u64, mpsc::UnboundedSender<Message>>,
+    clients: HashMap<u64, TrackingOptions>,
+    /// Connections that read each key in the default mode, cleared once the key is invalidated.
+    keys: HashMap<String, HashSet<u64>>,
+    /// Connections in broadcasting mode for each prefix.
+    prefixes: HashMap<Vec<u8>, HashSet<u64>>,
+}
+
+impl TrackingTable {
+    pub(crate) fn register(&mut self, client_id: u64, sender: mpsc::UnboundedSender<Message>) {
+        self.senders.insert(client_id, sender);
+    }
+
+    pub(crate) fn unregister(&mut self, client_id: u64) {
+        self.disable(client_id);
+        self.senders.remove(&client_id);
+    }
+
+    pub(crate) fn exists(&self, client_id: u64) -> bool {
+        self.senders.contains_key(&client_id)
+    }
+
+    pub(crate) fn options(&self, client_id: u64) -> Option<&TrackingOptions> {
+        self.clients.get(&client_id)
+    }
+
+    pub(crate) fn enable(
+        &mut self,
+        client_id: u64,
+        options: TrackingOptions,
+    ) -> anyhow::Result<()> {
+        if let Some(current) = self.clients.get(&client_id) {
+            anyhow::ensure!(
+                current.bcast == options.bcast,
+                "ERR You can't switch BCAST mode on/off before disabling tracking for this client, and then re-enabling it with a different mode."
+            );
+            anyhow::ensure!(
+                current.optin == options.optin && current.optout == options.optout,
+                "ERR You can't switch OPTIN/OPTOUT mode before disabling tracking for this client, and then re-enabling it with a different mode."
+            );
+        }
+        if let Some(redirect) = options.redirect {
+            anyhow::ensure!(
+                self.exists(redirect),
+                "ERR The client ID you want redirect to does not exist"
+            );
+        }
+
+        if options.bcast {
+            let prefixes = if options.prefixes.is_empty() {
+                vec![Vec::new()]
+            } else {
+                options.prefixes.clone()
+            };
+            for prefix in prefixes {
+                self.prefixes.entry(prefix).or_default().insert(client_id);
+            }
+        }
+
+        let options = match self.clients.remove(&client_id) {
+            Some(mut current) => {
+                current.redirect = options.redirect;
+                current.noloop = options.noloop;
+                for prefix in options.prefixes {
+                    if !current.prefixes.contains(&prefix) {
+                        current.prefixes.push(prefix);
+                    }
+                }
+                current
+            }
+            None => options,
+        };
+        self.clients.insert(client_id, options);
+
+        Ok(())
+    }
+
+    /// Stops tracking, the keys the connection read are forgotten when they are invalidated.
+    pub(crate) fn disable(&mut self, client_id: u64) {
+        if self.clients.remove(&client_id).is_some() {
+            self.prefixes.retain(|_, clients| {
+                clients.remove(&client_id);
+                !clients.is_empty()
+            });
+        }
+    }
+
+    /// Remembers the keys read by a connection, so it is notified when they are modified.
+    pub(crate) fn track_keys(&mut self, client_id: u64, keys: Vec<String>) {
+        for key in keys {
+            self.keys.entry(key).or_default().insert(client_id);
+        }
+    }
+
+    /// Sends invalidation messages for the modified keys, skipping the connection that modified
+    /// them when it uses `NOLOOP`.
+    pub(crate) fn invalidate(&mut self, keys: &[String], source: Option<u64>) {
+        let mut invalidations: HashMap<u64, Vec<String>> = HashMap::new();
+
+        for key in keys {
+            let readers = self.keys.remove(key).into_iter().flatten();
+            let subscribers = self
+                .prefixes
+                .iter()
+                .filter(|(prefix, _)| key.as_bytes().starts_with(prefix))
+                .flat_map(|(_, clients)| clients.iter().copied());
+
+            for client_id in readers.chain(subscribers) {
+                let Some(options) = self.clients.get(&client_id) else {
+                    continue;
+                };
+                if options.noloop && source == Some(client_id) {
+                    continue;
+                }
+
+                let keys = invalidations.entry(client_id).or_default();
+                if !keys.contains(key) {
+                    keys.push(key.to_string());
+                }
+            }
+        }
+
+        for (client_id, keys) in invalidations {
+            let message = Message::Push(vec![
+                Message::bulk_string("invalidate"),
+                Message::array(keys.into_iter().map(Message::bulk_string).collect()),
+            ]);
+            self.send(client_id, message);
+        }
+    }
+
+    fn send(&self, client_id: u64, message: Message) {
+        let redirect = self
+            .clients
+            .get(&client_id)
+            .and_then(|options| options.redirect);
+
+        match self.senders.get(&redirect.unwrap_or(client_id)) {
+            Some(sender) => {
+                let _ = sender.send(message);
+            }
+            None => {
+                if let (Some(redirect), Some(sender)) = (redirect, self.senders.get(&client_id)) {
+                    let _ = sender.send(Message::Push(vec![
+                        Message::bulk_string("tracking-redir-broken"),
+                        Message::Integer(redirect as i64),
+                    ]));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,90 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use crate::{
+    commands::Command,
+    db::{Db, Keyspace},
+};
+
+/// Commands queued by a connection between `MULTI` and `EXEC`.
+#[derive(Default)]
+pub(crate) struct Transaction {
+    commands: Vec<Box<dyn Command>>,
+    aborted: bool,
+}
+
+impl Transaction {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn queue(&mut self, command: Box<dyn Command>) {
+        self.commands.push(command);
+    }
+
+    /// Flags the transaction so `EXEC` fails, used when a queued command has a syntax error.
+    pub(crate) fn abort(&mut self) {
+        self.aborted = true;
+    }
+
+    pub(crate) fn is_aborted(&self) -> bool {
+        self.aborted
+    }
+
+    pub(crate) fn commands(&self) -> &[Box<dyn Command>] {
+        &self.commands
+    }
+}
+
+/// Keys watched by a connection with `WATCH`, released when the connection is dropped.
+pub(crate) struct WatchedKeys {
+    db: Db,
+    keys: Vec<(String, bool)>,
+    dirty: Arc<AtomicBool>,
+}
+
+impl WatchedKeys {
+    pub(crate) fn new(db: Db) -> Self {
+        Self {
+            db,
+            keys: Vec::new(),
+            dirty: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub(crate) fn watch(&mut self, keyspace: &mut Keyspace, key: String) {
+        if self.keys.iter().any(|(watched, _)| *watched == key) {
+            return;
+        }
+
+        let exists = keyspace.watch(&key, &self.dirty);
+        self.keys.push((key, exists));
+    }
+
+    pub(crate) fn unwatch(&mut self, keyspace: &mut Keyspace) {
+        for (key, _) in self.keys.drain(..) {
+            keyspace.unwatch(&key, &self.dirty);
+        }
+        self.dirty.store(false, Ordering::SeqCst);
+    }
+
+    /// Returns whether a watched key was modified, or expired since it was watched.
+    pub(crate) fn is_dirty(&self, keyspace: &Keyspace) -> bool {
+        self.dirty.load(Ordering::SeqCst)
+            || self
+                .keys
+                .iter()
+                .any(|(key, exists)| *exists && keyspace.is_expired(key))
+    }
+}
+
+impl Drop for WatchedKeys {
+    fn drop(&mut self) {
+        if !self.keys.is_empty() {
+            let db = self.db.clone();
+            self.unwatch(&mut db.keyspace());
+        }
+    }
+}